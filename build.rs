@@ -0,0 +1,38 @@
+//! Records the commit the bot is built from, and when it was built, for `¡version`.
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let mut commit = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".into());
+    if git(&["status", "--porcelain", "--untracked-files=no"]).map_or(false, |s| !s.is_empty()) {
+        commit.push_str("-dirty");
+    }
+    let built = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    println!("cargo:rustc-env=BUILD_COMMIT={}", commit);
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", built);
+    // Naming files turns off rerunning on any change in the package, so the sources are
+    // listed too, to keep the timestamp that of the last build that changed something.
+    for path in &[
+        ".git/HEAD",
+        ".git/refs",
+        ".git/index",
+        "src",
+        "repl-bot/src",
+        "build.rs",
+    ] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+}
+
+/// The trimmed output of a git command, if it succeeds.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
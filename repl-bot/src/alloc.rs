@@ -0,0 +1,86 @@
+//! A global allocator that keeps track of how much memory threads hold.
+//!
+//! The interpreter allocates through the regular global allocator, so this is how we find
+//! out how much memory an evaluation uses without support from the interpreter. Binaries
+//! must install it for memory limits to work:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: repl_bot::alloc::CountingAllocator = repl_bot::alloc::CountingAllocator;
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+thread_local! {
+    /// The counter of the calling thread, while it's metered.
+    static METERED: Cell<Option<&'static AtomicI64>> = const { Cell::new(None) };
+    /// The counter of the calling thread, created the first time it's metered and reused
+    /// after that.
+    static COUNTER: Cell<Option<&'static AtomicI64>> = const { Cell::new(None) };
+}
+
+/// Net bytes allocated by a thread since its metering started; this can be read from any
+/// thread.
+#[derive(Clone, Copy)]
+pub struct MeteredBytes(&'static AtomicI64);
+
+impl MeteredBytes {
+    pub fn get(self) -> u64 {
+        self.0.load(Ordering::Relaxed).max(0) as u64
+    }
+}
+
+/// Starts counting allocations made by the calling thread, from zero.
+pub fn start_metering() -> MeteredBytes {
+    let counter = COUNTER.with(|c| match c.get() {
+        Some(counter) => counter,
+        None => {
+            let counter: &'static AtomicI64 = Box::leak(Box::new(AtomicI64::new(0)));
+            c.set(Some(counter));
+            counter
+        }
+    });
+    counter.store(0, Ordering::SeqCst);
+    METERED.with(|m| m.set(Some(counter)));
+    MeteredBytes(counter)
+}
+
+pub fn stop_metering() {
+    METERED.with(|m| m.set(None));
+}
+
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn record(delta: i64) {
+        // `try_with` because allocations can happen while thread locals are torn down.
+        if let Ok(Some(counter)) = METERED.try_with(Cell::get) {
+            counter.fetch_add(delta, Ordering::Relaxed);
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::record(layout.size() as i64);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Self::record(-(layout.size() as i64));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            Self::record(new_size as i64 - layout.size() as i64);
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,155 @@
+//! Cron schedules for recurring evaluations, in UTC.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A standard five-field cron schedule: minute, hour, day of month, month and day of week.
+/// Fields are `*`, numbers, ranges like `1-5`, steps like `*/15` or `0-30/10`, or
+/// comma-separated lists of those.
+pub struct Schedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// Whether the day of month and day of week fields were both restricted, in which case
+    /// a day matching either of them matches, as in cron.
+    either_day: bool,
+}
+
+/// A minute in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Minute {
+    pub minute: u32,
+    pub hour: u32,
+    pub day: u32,
+    pub month: u32,
+    /// 0 is Sunday.
+    pub weekday: u32,
+}
+
+impl Schedule {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let fields = spec.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err("a schedule has five fields: minute, hour, day, month and weekday".into());
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7, "weekday")?;
+        // 7 is Sunday too.
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        weekdays.truncate(7);
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59, "minute")?,
+            hours: parse_field(fields[1], 0, 23, "hour")?,
+            days: parse_field(fields[2], 1, 31, "day")?,
+            months: parse_field(fields[3], 1, 12, "month")?,
+            weekdays,
+            either_day: fields[2] != "*" && fields[4] != "*",
+        })
+    }
+
+    pub fn matches(&self, at: Minute) -> bool {
+        let day = self.days[at.day as usize];
+        let weekday = self.weekdays[at.weekday as usize];
+        let day_matches = if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        self.minutes[at.minute as usize]
+            && self.hours[at.hour as usize]
+            && self.months[at.month as usize]
+            && day_matches
+    }
+}
+
+/// Parses a field whose values go from `min` to `max`, into flags indexed by value.
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; max as usize + 1];
+    let number = |s: &str| -> Result<u32, String> {
+        match s.parse::<u32>() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => Err(format!(
+                "invalid {} {}: expected a number from {} to {}",
+                name, s, min, max
+            )),
+        }
+    };
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(slash) => {
+                let step = part[slash + 1..]
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid {} step in {}", name, part))?;
+                (&part[..slash], step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(dash) = range.find('-') {
+            (number(&range[..dash])?, number(&range[dash + 1..])?)
+        } else {
+            let value = number(range)?;
+            // `5/10` means from 5 to the end, every 10.
+            (value, if step > 1 { max } else { value })
+        };
+        if start > end {
+            return Err(format!("invalid {} range {}", name, range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+impl Minute {
+    /// The minute `time` falls in.
+    pub fn of(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let days = secs / 86_400;
+        let (_, month, day) = civil_from_days(days as i64);
+        Self {
+            minute: (secs / 60 % 60) as u32,
+            hour: (secs / 3600 % 24) as u32,
+            day,
+            month,
+            // 1970-01-01 was a Thursday.
+            weekday: ((days + 4) % 7) as u32,
+        }
+    }
+}
+
+/// How long until the start of the minute after `time`.
+pub fn until_next_minute(time: SystemTime) -> Duration {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let into_minute = Duration::from_millis((since_epoch.as_millis() % 60_000) as u64);
+    Duration::from_secs(60) - into_minute
+}
+
+/// Year, month and day of a number of days since 1970-01-01, in the proleptic Gregorian
+/// calendar (Howard Hinnant's algorithm).
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
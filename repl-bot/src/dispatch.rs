@@ -0,0 +1,382 @@
+//! Handing jobs to the threads that run interpreters, from event handlers on any thread.
+//!
+//! Interpreters usually can't be shared between threads, so each worker thread owns some and
+//! reads jobs for them from a channel. A `Pool` sends a job to the worker the caller picks,
+//! and waits for the reply on the channel that comes with the job.
+//!
+//! A worker only takes so many jobs at once. Past that, callers are turned away straight
+//! away, with an estimate of when to try again, instead of waiting for the interpreter.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serenity::prelude::Mutex;
+use tracing::warn;
+
+/// How long a job is assumed to take until one has run.
+const INITIAL_JOB_TIME: Duration = Duration::from_secs(1);
+/// How often a ping tries again to reach a busy worker.
+const PING_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Jobs the workers of a pool run.
+pub trait Job: Send {
+    /// A job the worker answers on `response` as soon as it gets to it.
+    fn ping(response: SyncSender<()>) -> Self;
+}
+
+/// Why a job didn't get a reply.
+#[derive(Debug)]
+pub enum Error {
+    /// The worker's thread is gone.
+    WorkerDown,
+    /// Another job held the worker's channel for too long.
+    LockTimeout,
+    /// The worker's queue is full; trying again after `wait` may work.
+    Overloaded { wait: Duration },
+    /// The worker didn't get to a ping in time.
+    Unresponsive,
+    /// Whoever asked for a job stopped waiting before the worker replied.
+    ReplyDropped { job: &'static str },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::WorkerDown => write!(f, "the interpreter is down"),
+            Error::LockTimeout => write!(f, "timeout waiting for interpreter lock"),
+            Error::Overloaded { wait } => write!(
+                f,
+                "The bot is overloaded, try again in {}s.",
+                wait.as_secs().max(1)
+            ),
+            Error::Unresponsive => write!(f, "the interpreter didn't answer in time"),
+            Error::ReplyDropped { job } => {
+                write!(f, "the reply to a {} job was no longer awaited", job)
+            }
+        }
+    }
+}
+
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
+
+/// Sends `value` back to whoever asked for a `job`. They may have stopped waiting, which is
+/// logged rather than taking the worker down.
+pub fn reply<T>(response: SyncSender<T>, value: T, job: &'static str) {
+    if response.send(value).is_err() {
+        warn!("{}", Error::ReplyDropped { job });
+    }
+}
+
+struct Worker<J> {
+    sender: Mutex<SyncSender<J>>,
+    /// Jobs being run or waiting for this worker, shared with it so long evaluations can be
+    /// preempted.
+    queued: Arc<AtomicUsize>,
+}
+
+/// The worker threads jobs are sent to.
+pub struct Pool<J> {
+    workers: Vec<Worker<J>>,
+    /// How long a caller waits for the interpreter to become available.
+    lock_timeout: Duration,
+    /// Jobs a worker may have queued before further ones are turned away.
+    queue_length: usize,
+    /// Moving average of how long jobs take, waiting included, in microseconds.
+    average_job_micros: AtomicU64,
+    /// Jobs being run or waiting for the interpreter.
+    pending: AtomicUsize,
+    /// Cleared once an interpreter thread is found to be gone.
+    alive: AtomicBool,
+}
+
+impl<J: Job> Pool<J> {
+    /// Creates a pool of the workers behind `workers`, of which there must be at least one.
+    /// Each comes with the count of its queued jobs, which the pool keeps. The channels
+    /// should have no buffer, so that jobs wait in the pool, where they are counted.
+    pub fn new(
+        workers: Vec<(SyncSender<J>, Arc<AtomicUsize>)>,
+        lock_timeout: Duration,
+        queue_length: usize,
+    ) -> Self {
+        assert!(!workers.is_empty(), "a pool needs a worker");
+        Self {
+            workers: workers
+                .into_iter()
+                .map(|(sender, queued)| Worker {
+                    sender: Mutex::new(sender),
+                    queued,
+                })
+                .collect(),
+            lock_timeout,
+            queue_length,
+            average_job_micros: AtomicU64::new(INITIAL_JOB_TIME.as_micros() as u64),
+            pending: AtomicUsize::new(0),
+            alive: AtomicBool::new(true),
+        }
+    }
+
+    /// How many workers there are.
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// How many jobs are being run or waiting for the interpreter.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Whether every worker thread was still running at its last job.
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    /// How many of the workers have as many jobs queued as they take, and so turn further
+    /// ones away.
+    pub fn saturated_workers(&self) -> usize {
+        self.workers
+            .iter()
+            .filter(|worker| worker.queued.load(Ordering::SeqCst) >= self.queue_length)
+            .count()
+    }
+
+    /// How long `worker` took to answer a ping, or why it didn't within `deadline`. Pings
+    /// skip the queue limit, but wait behind whatever the worker is running.
+    pub fn ping(&self, worker: usize, deadline: Duration) -> Result<Duration, Error> {
+        let started = Instant::now();
+        let worker = &self.workers[worker];
+        let channel = worker
+            .sender
+            .try_lock_for(deadline)
+            .ok_or(Error::LockTimeout)?;
+        let (response_sender, response_receiver) = mpsc::sync_channel(1);
+        let mut job = J::ping(response_sender);
+        // Jobs are handed over directly, so this waits for the worker to be free.
+        loop {
+            match channel.try_send(job) {
+                Ok(()) => break,
+                Err(TrySendError::Full(returned)) if started.elapsed() < deadline => {
+                    job = returned;
+                    thread::sleep(PING_POLL_INTERVAL);
+                }
+                Err(TrySendError::Full(_)) => return Err(Error::Unresponsive),
+                Err(TrySendError::Disconnected(_)) => {
+                    self.alive.store(false, Ordering::SeqCst);
+                    return Err(Error::WorkerDown);
+                }
+            }
+        }
+        drop(channel);
+        match response_receiver.recv_timeout(deadline.saturating_sub(started.elapsed())) {
+            Ok(()) => Ok(started.elapsed()),
+            Err(RecvTimeoutError::Timeout) => Err(Error::Unresponsive),
+            // Workers may drop pings they couldn't answer, as a sandbox that failed does.
+            Err(RecvTimeoutError::Disconnected) => Err(Error::WorkerDown),
+        }
+    }
+
+    /// Sends the job built by `make_job` to `worker`, and waits for the reply on the channel
+    /// the job is given. Fails at once if the worker's queue is full.
+    pub fn send<T>(
+        &self,
+        worker: usize,
+        make_job: impl FnOnce(SyncSender<T>) -> J,
+    ) -> Result<T, Error> {
+        let worker = &self.workers[worker];
+        let queued = worker.queued.fetch_add(1, Ordering::SeqCst);
+        if queued >= self.queue_length {
+            worker.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(self.overloaded(queued));
+        }
+        let (response_sender, response_receiver) = mpsc::sync_channel(0);
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let started = Instant::now();
+        let result = match worker.sender.try_lock_for(self.lock_timeout) {
+            Some(channel) => {
+                let sent = channel.send(make_job(response_sender));
+                match sent.map(|()| response_receiver.recv()) {
+                    Ok(Ok(response)) => Ok(response),
+                    _ => {
+                        self.alive.store(false, Ordering::SeqCst);
+                        Err(Error::WorkerDown)
+                    }
+                }
+            }
+            None => Err(Error::LockTimeout),
+        };
+        if result.is_ok() {
+            self.record_job_time(started.elapsed());
+        }
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+        worker.queued.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    /// Folds `elapsed` into the average job time, weighing it an eighth.
+    fn record_job_time(&self, elapsed: Duration) {
+        let sample = elapsed.as_micros() as u64;
+        let _ =
+            self.average_job_micros
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |average| {
+                    Some(average - average / 8 + sample / 8)
+                });
+    }
+
+    /// The error turning a job away from a worker with `queued` jobs ahead of it.
+    fn overloaded(&self, queued: usize) -> Error {
+        let average = Duration::from_micros(self.average_job_micros.load(Ordering::SeqCst));
+        Error::Overloaded {
+            wait: average * queued as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+
+    enum TestJob {
+        /// Replies with `value` once `release` sends something or is dropped.
+        Hold {
+            value: u32,
+            release: Receiver<()>,
+            response: SyncSender<u32>,
+        },
+        Ping {
+            response: SyncSender<()>,
+        },
+    }
+
+    impl Job for TestJob {
+        fn ping(response: SyncSender<()>) -> Self {
+            TestJob::Ping { response }
+        }
+    }
+
+    fn run(jobs: Receiver<TestJob>) {
+        while let Ok(job) = jobs.recv() {
+            match job {
+                TestJob::Hold {
+                    value,
+                    release,
+                    response,
+                } => {
+                    let _ = release.recv();
+                    reply(response, value, "hold");
+                }
+                TestJob::Ping { response } => reply(response, (), "ping"),
+            }
+        }
+    }
+
+    /// A pool of one running worker, taking `queue_length` jobs at once.
+    fn pool(queue_length: usize) -> Arc<Pool<TestJob>> {
+        let (sender, jobs) = mpsc::sync_channel(0);
+        thread::spawn(move || run(jobs));
+        let workers = vec![(sender, Arc::new(AtomicUsize::new(0)))];
+        Arc::new(Pool::new(workers, Duration::from_secs(30), queue_length))
+    }
+
+    /// Sends `pool` a job that holds its worker until the returned sender is used or dropped,
+    /// and waits for it to be queued.
+    fn hold(pool: &Arc<Pool<TestJob>>) -> (SyncSender<()>, thread::JoinHandle<Result<u32, Error>>) {
+        let (release, held) = mpsc::sync_channel(1);
+        let sender = pool.clone();
+        let job = thread::spawn(move || {
+            sender.send(0, |response| TestJob::Hold {
+                value: 1,
+                release: held,
+                response,
+            })
+        });
+        while pool.pending() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        (release, job)
+    }
+
+    #[test]
+    fn answers_jobs() {
+        let pool = pool(8);
+        let (release, held) = hold(&pool);
+        release.send(()).unwrap();
+        assert_eq!(held.join().unwrap().unwrap(), 1);
+        assert!(pool.ping(0, Duration::from_secs(30)).is_ok());
+        assert_eq!(pool.pending(), 0);
+        assert!(pool.is_alive());
+    }
+
+    #[test]
+    fn turns_jobs_away_when_the_queue_is_full() {
+        let pool = pool(1);
+        let (release, held) = hold(&pool);
+        assert_eq!(pool.saturated_workers(), 1);
+        let (_, unused) = mpsc::sync_channel(1);
+        let turned_away = pool.send(0, |response| TestJob::Hold {
+            value: 2,
+            release: unused,
+            response,
+        });
+        match turned_away {
+            Err(Error::Overloaded { wait }) => assert!(wait > Duration::from_secs(0)),
+            other => panic!("expected an overloaded error, got {:?}", other),
+        }
+        // Pings skip the queue limit, but wait for the job the worker is running.
+        assert!(pool.ping(0, Duration::from_millis(50)).is_err());
+
+        drop(release);
+        assert_eq!(held.join().unwrap().unwrap(), 1);
+        assert_eq!(pool.saturated_workers(), 0);
+        assert!(pool.is_alive());
+    }
+
+    #[test]
+    fn keeps_serving_after_a_caller_stops_waiting() {
+        let (sender, jobs) = mpsc::sync_channel(0);
+        thread::spawn(move || run(jobs));
+        let (release, held) = mpsc::sync_channel(1);
+        let (response, receiver) = mpsc::sync_channel(0);
+        sender
+            .send(TestJob::Hold {
+                value: 1,
+                release: held,
+                response,
+            })
+            .unwrap();
+        // The worker took the job, and its reply now has nowhere to go.
+        drop(receiver);
+        release.send(()).unwrap();
+
+        let pool = Pool::new(
+            vec![(sender, Arc::new(AtomicUsize::new(0)))],
+            Duration::from_secs(30),
+            8,
+        );
+        assert!(pool.ping(0, Duration::from_secs(30)).is_ok());
+        assert!(pool.is_alive());
+    }
+
+    #[test]
+    fn notices_a_worker_that_is_gone() {
+        let (sender, jobs) = mpsc::sync_channel::<TestJob>(0);
+        drop(jobs);
+        let pool = Pool::new(
+            vec![(sender, Arc::new(AtomicUsize::new(0)))],
+            Duration::from_secs(30),
+            8,
+        );
+        assert!(matches!(
+            pool.ping(0, Duration::from_secs(30)),
+            Err(Error::WorkerDown)
+        ));
+        assert!(!pool.is_alive());
+    }
+}
@@ -0,0 +1,242 @@
+//! Helpers to lay out text in Discord messages and embeds.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cron;
+
+/// Maximum length of an embed field value.
+pub const EMBED_FIELD_LIMIT: usize = 1024;
+/// Maximum length of a message.
+pub const MESSAGE_LIMIT: usize = 2000;
+/// Most pairs of lines `diff_lines` compares; past that, the lines that differ are shown as
+/// replaced whole.
+const MAX_DIFF_CELLS: usize = 250_000;
+
+/// Truncates `s` to at most `limit` characters, marking the cut with an ellipsis.
+pub fn truncate(s: &str, limit: usize) -> String {
+    if s.chars().count() <= limit {
+        return s.to_string();
+    }
+    let mut truncated = s.chars().take(limit.saturating_sub(1)).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+/// Wraps `content` in a fenced code block that fits in `limit` characters.
+pub fn code_block(language: &str, content: &str, limit: usize) -> String {
+    // Keep the content from closing the fence early.
+    let content = content.replace("```", "`\u{200B}``");
+    let content = if content.is_empty() { " " } else { &content };
+    let overhead = language.len() + 8;
+    format!(
+        "```{}\n{}\n```",
+        language,
+        truncate(content, limit.saturating_sub(overhead))
+    )
+}
+
+/// How the values of results are printed. Limits of zero mean no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Printing {
+    /// Printing stops after about this many characters.
+    pub max_chars: usize,
+    /// Lists and vectors nested deeper than this are shown as `...`.
+    pub max_depth: usize,
+    /// Elements of a list or vector past this many are shown as `...`.
+    pub max_items: usize,
+    /// Whether structure that appears several times in a value is labelled, as
+    /// `write-shared` does. Structure that refers back to itself always is.
+    pub shared: bool,
+}
+
+/// How much of a program its result quotes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuoteInput {
+    Off,
+    FirstLine,
+    #[serde(alias = "on")]
+    Full,
+}
+
+impl QuoteInput {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(QuoteInput::Off),
+            "first-line" => Some(QuoteInput::FirstLine),
+            // `on` was the only other choice before first-line quotes.
+            "full" | "on" => Some(QuoteInput::Full),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QuoteInput::Off => "off",
+            QuoteInput::FirstLine => "first-line",
+            QuoteInput::Full => "full",
+        }
+    }
+
+    /// The part of `code` to quote, if any.
+    pub fn quote(self, code: &str) -> Option<String> {
+        match self {
+            QuoteInput::Off => None,
+            QuoteInput::Full => Some(code.to_string()),
+            QuoteInput::FirstLine => {
+                let code = code.trim();
+                let mut lines = code.lines();
+                let first = lines.next().unwrap_or("");
+                Some(match lines.next() {
+                    Some(_) => format!("{} …", first.trim_end()),
+                    None => first.to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// Where results put program output longer than the configured number of lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LongOutput {
+    /// Shown whole in the result.
+    Inline,
+    /// The lines past the limit are hidden behind a spoiler in the result.
+    Spoiler,
+    /// The lines past the limit are sent in a message of their own after the result.
+    FollowUp,
+}
+
+/// The first `lines` lines of `text`, and the rest if there is more. Zero means no limit.
+pub fn split_lines(text: &str, lines: usize) -> (&str, Option<&str>) {
+    if lines == 0 {
+        return (text, None);
+    }
+    match text.match_indices('\n').nth(lines - 1) {
+        Some((end, _)) if end + 1 < text.len() => (&text[..end], Some(&text[end + 1..])),
+        _ => (text, None),
+    }
+}
+
+/// The lines that differ between `old` and `new`, prefixed with `- ` where they were removed
+/// and `+ ` where they were added, or `None` if they have the same lines.
+pub fn diff_lines(old: &str, new: &str) -> Option<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // Only what lies between the lines both start and end with is compared.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+    if old.is_empty() && new.is_empty() {
+        return None;
+    }
+
+    let mut diff = Vec::new();
+    if old.len() * new.len() > MAX_DIFF_CELLS {
+        diff.extend(old.iter().map(|line| format!("- {}", line)));
+        diff.extend(new.iter().map(|line| format!("+ {}", line)));
+        return Some(diff.join("\n"));
+    }
+    // `common[i][j]` is the length of the longest common subsequence of `old[i..]` and
+    // `new[j..]`.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    Some(diff.join("\n"))
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2} s", duration.as_secs_f64())
+    }
+}
+
+/// Formats a long duration with its two most significant units, as in "3d 4h".
+pub fn format_uptime(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m {}s", minutes, secs % 60)
+    }
+}
+
+/// Formats a point in time to the minute, in UTC, as in "2021-03-04 05:06 UTC".
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = cron::civil_from_days((secs / 86_400) as i64);
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_to_the_limit() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("λλλλλλ", 4), "λλλ…");
+        assert_eq!(truncate("anything", 0), "…");
+    }
+
+    #[test]
+    fn fits_code_blocks_in_their_limit() {
+        let long = "x".repeat(3 * EMBED_FIELD_LIMIT);
+        let block = code_block("scheme", &long, EMBED_FIELD_LIMIT);
+        assert!(block.chars().count() <= EMBED_FIELD_LIMIT);
+        assert!(block.starts_with("```scheme\nxxx"));
+        assert!(block.ends_with("x…\n```"));
+        assert_eq!(code_block("", "", EMBED_FIELD_LIMIT), "```\n \n```");
+    }
+
+    #[test]
+    fn keeps_content_from_closing_the_fence() {
+        let block = code_block("", "(display \"```\")", EMBED_FIELD_LIMIT);
+        assert_eq!(block.matches("```").count(), 2);
+        assert!(block.starts_with("```\n") && block.ends_with("\n```"));
+    }
+}
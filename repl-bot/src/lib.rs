@@ -0,0 +1,26 @@
+//! Building blocks for Discord bots that evaluate code with an interpreter, taken out of
+//! peroxide-discord so that bots for other interpreters can use them:
+//!
+//! - handing jobs to the threads that own the interpreters, in `dispatch`;
+//! - running an interpreter under limits: `limits` interrupts evaluations that take too
+//!   long or use too much CPU time or memory, measured by the allocator in `alloc`;
+//! - managing per-user sessions, in `session`;
+//! - recognizing messages meant for the bot, in `trigger`, and presenting results, in
+//!   `format`;
+//! - talking to the chat service through the traits in `transport`, and sending to Discord
+//!   without losing messages to passing failures, in `send`;
+//! - rate limiting and cron schedules, in `ratelimit` and `cron`.
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod alloc;
+pub mod cron;
+pub mod dispatch;
+pub mod format;
+pub mod limits;
+pub mod ratelimit;
+pub mod send;
+pub mod session;
+pub mod transport;
+pub mod trigger;
@@ -0,0 +1,407 @@
+//! Resource limits on evaluations, and the watchdog that enforces them.
+//!
+//! Besides wall-clock time, evaluations are limited by CPU fuel and by a memory ceiling.
+//! Interpreters such as peroxide don't count reductions or allocations, so we meter what
+//! the process can observe instead: CPU time used by the interpreter thread stands in for
+//! reductions, and memory is counted by our global allocator (see `alloc`). CPU fuel is
+//! only metered on Linux.
+//!
+//! A thread may also be made preemptible, so that one program can't hold it for its whole
+//! timeout while other jobs wait for it.
+//!
+//! Each thread running evaluations gets one long-lived watchdog thread, which checks the
+//! limits of whatever evaluation is armed and sleeps while none is.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SendError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::alloc::{self, MeteredBytes};
+
+/// How often the watchdog checks the limits.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+thread_local! {
+    /// The preemption policy of evaluations watched on the calling thread.
+    static PREEMPTION: RefCell<Option<Preemption>> = const { RefCell::new(None) };
+    /// The watchdog thread of evaluations armed on the calling thread, started the first
+    /// time one is, or by `start_watchdog`.
+    static WATCHER: RefCell<Option<Sender<Watch>>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    /// Wall-clock time the whole program may take.
+    pub timeout: Duration,
+    /// CPU time the interpreter thread may use.
+    pub cpu_fuel: Duration,
+    /// How much memory the evaluation may hold, in bytes.
+    pub memory_fuel: u64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum LimitExceeded {
+    Timeout(Duration),
+    Cpu(Duration),
+    Memory(u64),
+    /// Other jobs were waiting for the thread after the evaluation had run this long.
+    Preempted(Duration),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitExceeded::Timeout(limit) => write!(f, "timed out after {}s", limit.as_secs()),
+            LimitExceeded::Cpu(limit) => {
+                write!(f, "ran out of CPU fuel ({} ms)", limit.as_millis())
+            }
+            LimitExceeded::Memory(limit) => {
+                write!(f, "memory limit exceeded ({} MiB)", limit / (1024 * 1024))
+            }
+            LimitExceeded::Preempted(after) => write!(
+                f,
+                "stopped after {}s to let other waiting evaluations run",
+                after.as_secs()
+            ),
+        }
+    }
+}
+
+/// What an evaluation had used when it was interrupted.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Usage {
+    pub elapsed: Duration,
+    /// CPU time of the interpreter thread, where it is metered.
+    pub cpu: Option<Duration>,
+    /// Memory held by the evaluation, in bytes.
+    pub memory: u64,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "after {:.2}s", self.elapsed.as_secs_f64())?;
+        if let Some(cpu) = self.cpu {
+            write!(f, ", {:.2}s of CPU time", cpu.as_secs_f64())?;
+        }
+        write!(f, ", {} KiB held", self.memory / 1024)
+    }
+}
+
+/// Why an evaluation was interrupted, and what it had used by then.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Interruption {
+    pub exceeded: LimitExceeded,
+    pub usage: Usage,
+}
+
+/// Lets evaluations be interrupted early while other jobs wait for their thread.
+#[derive(Clone)]
+pub struct Preemption {
+    /// How long an evaluation runs before it may be interrupted.
+    pub after: Duration,
+    /// Jobs being run by the thread or waiting for it.
+    pub queued: Arc<AtomicUsize>,
+}
+
+impl Preemption {
+    fn due(&self, elapsed: Duration) -> bool {
+        elapsed >= self.after && self.queued.load(Ordering::SeqCst) > 1
+    }
+}
+
+/// Makes the evaluations watched on the calling thread from now on preemptible.
+pub fn set_preemption(preemption: Preemption) {
+    PREEMPTION.with(|p| *p.borrow_mut() = Some(preemption));
+}
+
+/// Starts the watchdog of evaluations armed on the calling thread now rather than with the
+/// first of them, for processes that may not be able to start threads later.
+pub fn start_watchdog() {
+    WATCHER.with(|watcher| {
+        watcher.borrow_mut().get_or_insert_with(start_watcher);
+    });
+}
+
+/// Interrupts an evaluation when it exceeds its limits, or when it is preempted.
+pub struct Watchdog {
+    state: Arc<Mutex<State>>,
+}
+
+enum State {
+    Armed,
+    Disarmed,
+    Interrupted(Interruption),
+}
+
+impl Watchdog {
+    /// Starts watching. This must be called from the thread that runs the evaluation, as
+    /// that's the thread whose CPU time is metered.
+    pub fn arm<F>(interrupt: F, limits: Limits) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State::Armed));
+        let watch = Watch {
+            limits,
+            start: Instant::now(),
+            meter: Meter::start(),
+            memory: alloc::start_metering(),
+            preemption: PREEMPTION.with(|p| p.borrow().clone()),
+            interrupt: Some(Box::new(interrupt)),
+            state: state.clone(),
+        };
+        WATCHER.with(|watcher| {
+            let mut watcher = watcher.borrow_mut();
+            let sent = watcher.get_or_insert_with(start_watcher).send(watch);
+            // The thread only stops if it panicked; start another.
+            if let Err(SendError(watch)) = sent {
+                error!("The watchdog thread stopped, starting another");
+                let sender = start_watcher();
+                let _ = sender.send(watch);
+                *watcher = Some(sender);
+            }
+        });
+        Self { state }
+    }
+
+    /// Stops watching, returning why the evaluation was interrupted, if it was. This must be
+    /// called from the thread that armed the watchdog. It doesn't wait for the watchdog
+    /// thread, and the evaluation can't be interrupted once it returns.
+    pub fn disarm(self) -> Option<Interruption> {
+        alloc::stop_metering();
+        let mut state = lock(&self.state);
+        match mem::replace(&mut *state, State::Disarmed) {
+            State::Interrupted(interruption) => Some(interruption),
+            State::Armed | State::Disarmed => None,
+        }
+    }
+}
+
+/// An evaluation being watched, as the watchdog thread sees it.
+struct Watch {
+    limits: Limits,
+    start: Instant,
+    meter: Meter,
+    memory: MeteredBytes,
+    preemption: Option<Preemption>,
+    interrupt: Option<Box<dyn FnOnce() + Send>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Watch {
+    /// Interrupts the evaluation if it exceeded its limits or is preempted. Returns whether
+    /// it still needs watching.
+    fn check(&mut self) -> bool {
+        let mut state = lock(&self.state);
+        if !matches!(*state, State::Armed) {
+            return false;
+        }
+        let elapsed = self.start.elapsed();
+        let limits = &self.limits;
+        let exceeded = if elapsed >= limits.timeout {
+            LimitExceeded::Timeout(limits.timeout)
+        } else if self
+            .meter
+            .cpu_used()
+            .is_some_and(|used| used >= limits.cpu_fuel)
+        {
+            LimitExceeded::Cpu(limits.cpu_fuel)
+        } else if self.memory.get() >= limits.memory_fuel {
+            LimitExceeded::Memory(limits.memory_fuel)
+        } else if let Some(preemption) = self.preemption.as_ref().filter(|p| p.due(elapsed)) {
+            LimitExceeded::Preempted(preemption.after)
+        } else {
+            return true;
+        };
+        let usage = Usage {
+            elapsed,
+            cpu: self.meter.cpu_used(),
+            memory: self.memory.get(),
+        };
+        // With the state locked, so that an evaluation is never interrupted once disarmed.
+        if let Some(interrupt) = self.interrupt.take() {
+            interrupt();
+        }
+        *state = State::Interrupted(Interruption { exceeded, usage });
+        false
+    }
+}
+
+/// Starts a watchdog thread, which watches the evaluations sent to it until the returned
+/// sender is dropped.
+fn start_watcher() -> Sender<Watch> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || watch(receiver));
+    sender
+}
+
+fn watch(new: Receiver<Watch>) {
+    let mut watching: Vec<Watch> = Vec::new();
+    loop {
+        // With nothing to watch, there is nothing to do until something is armed.
+        let received = if watching.is_empty() {
+            new.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            new.recv_timeout(POLL_INTERVAL)
+        };
+        match received {
+            Ok(watch) => watching.push(watch),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let mut index = 0;
+        while index < watching.len() {
+            if watching[index].check() {
+                index += 1;
+            } else {
+                watching.swap_remove(index);
+            }
+        }
+    }
+}
+
+/// Locks `state`, which stays consistent even if a thread panicked holding it.
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Measures CPU time used by a thread since it was started.
+struct Meter {
+    cpu_clock: Option<libc::clockid_t>,
+    start_cpu: Option<Duration>,
+}
+
+impl Meter {
+    fn start() -> Self {
+        let cpu_clock = os::thread_cpu_clock();
+        Self {
+            cpu_clock,
+            start_cpu: cpu_clock.and_then(os::cpu_time),
+        }
+    }
+
+    fn cpu_used(&self) -> Option<Duration> {
+        let now = os::cpu_time(self.cpu_clock?)?;
+        Some(now.checked_sub(self.start_cpu?).unwrap_or_default())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::time::Duration;
+
+    /// The CPU-time clock of the calling thread.
+    pub fn thread_cpu_clock() -> Option<libc::clockid_t> {
+        let mut clock = 0;
+        let ret = unsafe { libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock) };
+        if ret == 0 {
+            Some(clock)
+        } else {
+            None
+        }
+    }
+
+    pub fn cpu_time(clock: libc::clockid_t) -> Option<Duration> {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        if unsafe { libc::clock_gettime(clock, &mut time) } == 0 {
+            Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod os {
+    use std::time::Duration;
+
+    pub fn thread_cpu_clock() -> Option<libc::clockid_t> {
+        None
+    }
+
+    pub fn cpu_time(_clock: libc::clockid_t) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    const LIMITS: Limits = Limits {
+        timeout: Duration::from_secs(30),
+        cpu_fuel: Duration::from_secs(30),
+        memory_fuel: u64::MAX,
+    };
+
+    /// Arms a watchdog that records being triggered, and waits up to `wait` for it.
+    fn watch(limits: Limits, wait: Duration) -> (Option<Interruption>, bool) {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let flag = interrupted.clone();
+        let watchdog = Watchdog::arm(move || flag.store(true, Ordering::SeqCst), limits);
+        let start = Instant::now();
+        while start.elapsed() < wait && !interrupted.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let interruption = watchdog.disarm();
+        (interruption, interrupted.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn interrupts_evaluations_that_time_out() {
+        let limits = Limits {
+            timeout: Duration::from_millis(20),
+            ..LIMITS
+        };
+        let (interruption, interrupted) = watch(limits, Duration::from_secs(5));
+        assert!(interrupted);
+        let interruption = interruption.expect("the evaluation should have been interrupted");
+        assert!(matches!(interruption.exceeded, LimitExceeded::Timeout(_)));
+        assert!(interruption.usage.elapsed >= limits.timeout);
+    }
+
+    #[test]
+    fn leaves_evaluations_within_their_limits_alone() {
+        let (interruption, interrupted) = watch(LIMITS, Duration::from_millis(50));
+        assert!(interruption.is_none());
+        assert!(!interrupted);
+    }
+
+    #[test]
+    fn preempts_evaluations_others_wait_for() {
+        set_preemption(Preemption {
+            after: Duration::from_millis(20),
+            queued: Arc::new(AtomicUsize::new(2)),
+        });
+        let (interruption, interrupted) = watch(LIMITS, Duration::from_secs(5));
+        assert!(interrupted);
+        let exceeded = interruption.map(|interruption| interruption.exceeded);
+        assert!(matches!(exceeded, Some(LimitExceeded::Preempted(_))));
+    }
+
+    #[test]
+    fn never_interrupts_a_disarmed_evaluation() {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let flag = interrupted.clone();
+        let limits = Limits {
+            timeout: Duration::from_millis(20),
+            ..LIMITS
+        };
+        let watchdog = Watchdog::arm(move || flag.store(true, Ordering::SeqCst), limits);
+        assert!(watchdog.disarm().is_none());
+        thread::sleep(Duration::from_millis(50));
+        assert!(!interrupted.load(Ordering::SeqCst));
+    }
+}
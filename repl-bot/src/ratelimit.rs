@@ -0,0 +1,44 @@
+//! Sliding-window rate limiting per user.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serenity::model::id::UserId;
+
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    events: HashMap<UserId, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            events: HashMap::new(),
+        }
+    }
+
+    /// Records an event for `user` if they are under the limit; otherwise returns how long
+    /// until they can try again.
+    pub fn check(&mut self, user: UserId) -> Result<(), Duration> {
+        let now = Instant::now();
+        let window = self.window;
+        let events = self.events.entry(user).or_default();
+        while events
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= window)
+        {
+            events.pop_front();
+        }
+        if events.len() >= self.limit {
+            let retry_after = events
+                .front()
+                .map_or(window, |oldest| window - now.duration_since(*oldest));
+            return Err(retry_after);
+        }
+        events.push_back(now);
+        Ok(())
+    }
+}
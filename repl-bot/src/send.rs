@@ -0,0 +1,134 @@
+//! Sending messages so that a passing failure doesn't lose them.
+//!
+//! Failures that may go away, such as rate limits, server errors and network errors, are
+//! retried with exponential backoff. Messages Discord rejects as too large are sent again
+//! shortened. Sends that fail for good are logged with the `dead_letter` target, along with
+//! what was being sent.
+
+use std::thread;
+use std::time::Duration;
+
+use serenity::builder::CreateMessage;
+use serenity::http::{Http, HttpError};
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::model::ModelError;
+use serenity::Error;
+use tracing::{error, warn};
+
+use crate::format;
+
+/// Times a send is tried before giving up.
+const ATTEMPTS: u32 = 4;
+/// Wait before the first retry; each further retry waits twice as long.
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+/// Discord's error code for a request body it can't accept; for messages, the usual reason
+/// is a field being too long.
+const INVALID_FORM_BODY: isize = 50035;
+
+/// What a failed send calls for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Trying again later may work.
+    Transient,
+    /// The message is too large, and needs shortening.
+    TooLarge,
+    Permanent,
+}
+
+pub fn classify(error: &Error) -> Failure {
+    match error {
+        Error::Model(ModelError::MessageTooLong(_))
+        | Error::Model(ModelError::EmbedTooLarge(_)) => Failure::TooLarge,
+        Error::Http(http) => match http.as_ref() {
+            HttpError::UnsuccessfulRequest(response) => {
+                let status = response.status_code;
+                if status.as_u16() == 429 || status.is_server_error() {
+                    Failure::Transient
+                } else if status.as_u16() == 413
+                    || (status.as_u16() == 400 && response.error.code == INVALID_FORM_BODY)
+                {
+                    Failure::TooLarge
+                } else {
+                    Failure::Permanent
+                }
+            }
+            HttpError::Request(_) => Failure::Transient,
+            _ => Failure::Permanent,
+        },
+        Error::Io(_) => Failure::Transient,
+        _ => Failure::Permanent,
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails for a reason other than a transient one, or has
+/// been tried `ATTEMPTS` times.
+pub fn with_retries<T>(mut attempt: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+    let mut backoff = FIRST_BACKOFF;
+    for _ in 1..ATTEMPTS {
+        match attempt() {
+            Err(why) if classify(&why) == Failure::Transient => {
+                warn!(
+                    "Error sending message, retrying in {:?}: {:?}",
+                    backoff, why
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    attempt()
+}
+
+/// Sends `text` to `channel`, shortened if it's too large.
+pub fn say(http: &Http, channel: ChannelId, text: &str) -> Option<Message> {
+    match with_retries(|| channel.say(http, text)) {
+        Ok(message) => Some(message),
+        Err(why) if classify(&why) == Failure::TooLarge => {
+            let shortened = format::truncate(text, format::MESSAGE_LIMIT);
+            match with_retries(|| channel.say(http, &shortened)) {
+                Ok(message) => Some(message),
+                Err(why) => {
+                    dead_letter(channel, &shortened, &why);
+                    None
+                }
+            }
+        }
+        Err(why) => {
+            dead_letter(channel, text, &why);
+            None
+        }
+    }
+}
+
+/// Sends the message `build` makes to `channel`, or `fallback` instead if it's too large.
+/// `build` may be called several times.
+pub fn send<'a>(
+    http: &Http,
+    channel: ChannelId,
+    build: impl for<'b> Fn(&'b mut CreateMessage<'a>) -> &'b mut CreateMessage<'a>,
+    fallback: &str,
+) -> Option<Message> {
+    match with_retries(|| channel.send_message(http, |m| build(m))) {
+        Ok(message) => Some(message),
+        Err(why) if classify(&why) == Failure::TooLarge => {
+            warn!("Message too large, sending a shorter one: {:?}", why);
+            say(http, channel, fallback)
+        }
+        Err(why) => {
+            dead_letter(channel, fallback, &why);
+            None
+        }
+    }
+}
+
+fn dead_letter(channel: ChannelId, content: &str, why: &Error) {
+    error!(
+        target: "dead_letter",
+        channel = channel.0,
+        content,
+        "Giving up sending message: {:?}",
+        why
+    );
+}
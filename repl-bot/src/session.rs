@@ -0,0 +1,140 @@
+//! Per-user sessions that are dropped when idle or when there are too many. Sessions can be
+//! keyed by something else than users too.
+
+use std::collections::hash_map::Entry as MapEntry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::UserId;
+
+struct Entry<S> {
+    session: S,
+    last_used: Instant,
+}
+
+/// At most `max_sessions` sessions, each dropped after `idle` without use.
+pub struct SessionPool<S, K = UserId> {
+    max_sessions: usize,
+    idle: Duration,
+    sessions: HashMap<K, Entry<S>>,
+}
+
+impl<S, K: Copy + Eq + Hash> SessionPool<S, K> {
+    pub fn new(max_sessions: usize, idle: Duration) -> Self {
+        Self {
+            max_sessions,
+            idle,
+            sessions: HashMap::new(),
+        }
+    }
+
+    pub fn contains(&self, user: K) -> bool {
+        self.sessions.contains_key(&user)
+    }
+
+    /// The session of `user`, created with `create` if they don't have one. Sessions that
+    /// went idle are dropped first, and the least recently used ones if there is no room
+    /// for a new session.
+    pub fn get_or_create<E>(
+        &mut self,
+        user: K,
+        create: impl FnOnce() -> Result<S, E>,
+    ) -> Result<&mut S, E> {
+        self.evict(user);
+        let entry = match self.sessions.entry(user) {
+            MapEntry::Occupied(entry) => entry.into_mut(),
+            MapEntry::Vacant(entry) => entry.insert(Entry {
+                session: create()?,
+                last_used: Instant::now(),
+            }),
+        };
+        entry.last_used = Instant::now();
+        Ok(&mut entry.session)
+    }
+
+    /// Replaces the session of `user`.
+    pub fn insert(&mut self, user: K, session: S) {
+        self.evict(user);
+        let entry = Entry {
+            session,
+            last_used: Instant::now(),
+        };
+        self.sessions.insert(user, entry);
+    }
+
+    /// Drops idle sessions, and the least recently used ones if `incoming` is about to get
+    /// a session while at capacity.
+    pub fn evict(&mut self, incoming: K) {
+        let idle = self.idle;
+        self.sessions.retain(|_, e| e.last_used.elapsed() < idle);
+        if self.sessions.contains_key(&incoming) {
+            return;
+        }
+        while self.sessions.len() >= self.max_sessions {
+            let oldest = match self.sessions.iter().min_by_key(|(_, e)| e.last_used) {
+                Some((user, _)) => *user,
+                None => break,
+            };
+            self.sessions.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(session: &'static str) -> impl FnOnce() -> Result<&'static str, ()> {
+        move || Ok(session)
+    }
+
+    #[test]
+    fn keeps_sessions_until_they_are_dropped() {
+        let mut pool = SessionPool::new(2, Duration::from_secs(60));
+        assert_eq!(
+            pool.get_or_create(UserId(1), create("first")),
+            Ok(&mut "first")
+        );
+        assert_eq!(
+            pool.get_or_create(UserId(1), create("again")),
+            Ok(&mut "first")
+        );
+        pool.insert(UserId(1), "replaced");
+        assert_eq!(
+            pool.get_or_create(UserId(1), create("again")),
+            Ok(&mut "replaced")
+        );
+    }
+
+    #[test]
+    fn drops_the_least_recently_used_session_when_full() {
+        let mut pool = SessionPool::new(2, Duration::from_secs(60));
+        pool.get_or_create(UserId(1), create("first")).unwrap();
+        pool.get_or_create(UserId(2), create("second")).unwrap();
+        pool.get_or_create(UserId(1), create("first")).unwrap();
+        pool.get_or_create(UserId(3), create("third")).unwrap();
+        assert!(pool.contains(UserId(1)));
+        assert!(!pool.contains(UserId(2)));
+        assert!(pool.contains(UserId(3)));
+    }
+
+    #[test]
+    fn drops_idle_sessions() {
+        let mut pool = SessionPool::new(8, Duration::from_millis(20));
+        pool.get_or_create(UserId(1), create("first")).unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        pool.evict(UserId(2));
+        assert!(!pool.contains(UserId(1)));
+    }
+
+    #[test]
+    fn keeps_no_session_it_failed_to_create() {
+        let mut pool = SessionPool::<&str>::new(8, Duration::from_secs(60));
+        assert_eq!(
+            pool.get_or_create(UserId(1), || Err("no interpreter")),
+            Err("no interpreter")
+        );
+        assert!(!pool.contains(UserId(1)));
+    }
+}
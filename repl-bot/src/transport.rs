@@ -0,0 +1,116 @@
+//! What the bot needs from a chat service, so that message handling doesn't depend on
+//! Discord itself. The serenity adapter is what the bot runs on; the in-memory one lets
+//! messages be fed to the bot and its replies collected without a connection.
+
+use std::sync::Mutex;
+
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::prelude::Context;
+
+use crate::send;
+
+/// A message someone sent.
+pub trait ChatEvent {
+    fn author(&self) -> UserId;
+    /// Whether the author is a bot, whose messages are ignored.
+    fn is_from_bot(&self) -> bool;
+    /// The guild the message was sent in, or `None` for direct messages.
+    fn guild(&self) -> Option<GuildId>;
+    fn channel(&self) -> ChannelId;
+    /// Name of the channel, if it's known.
+    fn channel_name(&self) -> Option<String>;
+    fn content(&self) -> &str;
+}
+
+/// Sends replies.
+pub trait Transport {
+    /// Sends `text` to `channel`. Failures are logged, as there's no one else to tell.
+    fn say(&self, channel: ChannelId, text: &str);
+}
+
+/// A serenity message, with the context needed to look up its channel.
+pub struct SerenityEvent<'a> {
+    pub ctx: &'a Context,
+    pub msg: &'a Message,
+}
+
+impl ChatEvent for SerenityEvent<'_> {
+    fn author(&self) -> UserId {
+        self.msg.author.id
+    }
+
+    fn is_from_bot(&self) -> bool {
+        self.msg.author.bot
+    }
+
+    fn guild(&self) -> Option<GuildId> {
+        self.msg.guild_id
+    }
+
+    fn channel(&self) -> ChannelId {
+        self.msg.channel_id
+    }
+
+    fn channel_name(&self) -> Option<String> {
+        self.msg.channel_id.name(&self.ctx.cache)
+    }
+
+    fn content(&self) -> &str {
+        &self.msg.content
+    }
+}
+
+impl Transport for Context {
+    fn say(&self, channel: ChannelId, text: &str) {
+        send::say(&self.http, channel, text);
+    }
+}
+
+/// A message made up rather than received.
+pub struct MemoryEvent {
+    pub author: UserId,
+    pub from_bot: bool,
+    pub guild: Option<GuildId>,
+    pub channel: ChannelId,
+    pub channel_name: Option<String>,
+    pub content: String,
+}
+
+impl ChatEvent for MemoryEvent {
+    fn author(&self) -> UserId {
+        self.author
+    }
+
+    fn is_from_bot(&self) -> bool {
+        self.from_bot
+    }
+
+    fn guild(&self) -> Option<GuildId> {
+        self.guild
+    }
+
+    fn channel(&self) -> ChannelId {
+        self.channel
+    }
+
+    fn channel_name(&self) -> Option<String> {
+        self.channel_name.clone()
+    }
+
+    fn content(&self) -> &str {
+        &self.content
+    }
+}
+
+/// Keeps the replies it is asked to send, in order.
+#[derive(Default)]
+pub struct MemoryTransport {
+    pub sent: Mutex<Vec<(ChannelId, String)>>,
+}
+
+impl Transport for MemoryTransport {
+    fn say(&self, channel: ChannelId, text: &str) {
+        self.sent.lock().unwrap().push((channel, text.to_string()));
+    }
+}
@@ -0,0 +1,121 @@
+//! Recognizing messages that ask for an evaluation.
+
+use regex::Regex;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+
+/// Compiled matchers for a set of textual prefixes.
+pub struct Triggers {
+    pub prefixes: Vec<String>,
+    prefixed: Regex,
+    /// A prefix followed by `!`, for evaluations in the admin environment.
+    unrestricted: Regex,
+}
+
+impl Triggers {
+    pub fn new(prefixes: &[String]) -> Result<Self, String> {
+        if prefixes.is_empty() {
+            return Err("at least one prefix is required".into());
+        }
+        let group = prefixes
+            .iter()
+            .map(|p| regex::escape(p))
+            .collect::<Vec<_>>()
+            .join("|");
+        let prefixed = Regex::new(&format!(r"(?s)\A(?:{})(?:\s+(.*))?\z", group))
+            .map_err(|e| format!("invalid prefixes: {}", e))?;
+        let unrestricted = Regex::new(&format!(r"(?s)\A(?:{})!(?:\s+(.*))?\z", group))
+            .map_err(|e| format!("invalid prefixes: {}", e))?;
+        Ok(Self {
+            prefixes: prefixes.to_vec(),
+            prefixed,
+            unrestricted,
+        })
+    }
+
+    /// Extracts the code from a message asking for an evaluation in the admin environment,
+    /// such as `¡cl! code`.
+    pub fn extract_unrestricted(&self, content: &str) -> Option<String> {
+        let body = self
+            .unrestricted
+            .captures(content)?
+            .get(1)
+            .map_or("", |body| body.as_str());
+        Some(strip_code_fence(body).to_string())
+    }
+
+    /// Extracts the code from a message, if it starts with one of our prefixes or mentions
+    /// `bot_id`. The code is empty if the prefix is alone.
+    pub fn extract(&self, content: &str, bot_id: UserId) -> Option<String> {
+        let body = match strip_mention(content, bot_id) {
+            Some(rest) => rest,
+            None => self
+                .prefixed
+                .captures(content)?
+                .get(1)
+                .map_or("", |body| body.as_str()),
+        };
+        Some(strip_code_fence(body).to_string())
+    }
+}
+
+/// Extracts the code from a direct message, where no prefix is needed.
+pub fn extract_direct(content: &str) -> String {
+    strip_code_fence(content).to_string()
+}
+
+/// Where a message link, as copied from the Discord client, points.
+pub struct MessageLink {
+    /// `None` for messages in DMs.
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+}
+
+/// Parses `text` if it is just a message link.
+pub fn parse_message_link(text: &str) -> Option<MessageLink> {
+    lazy_static! {
+        static ref LINK_RE: Regex = Regex::new(
+            r"\A<?https://(?:(?:ptb|canary)\.)?discord(?:app)?\.com/channels/(@me|\d+)/(\d+)/(\d+)>?\z"
+        )
+        .unwrap();
+    }
+    let captures = LINK_RE.captures(text.trim())?;
+    let id = |i: usize| captures[i].parse::<u64>().ok();
+    Some(MessageLink {
+        guild_id: if &captures[1] == "@me" {
+            None
+        } else {
+            Some(GuildId(id(1)?))
+        },
+        channel_id: ChannelId(id(2)?),
+        message_id: MessageId(id(3)?),
+    })
+}
+
+fn strip_mention(content: &str, bot_id: UserId) -> Option<&str> {
+    let mention = format!("<@{}>", bot_id);
+    let nick_mention = format!("<@!{}>", bot_id);
+    content
+        .strip_prefix(&mention)
+        .or_else(|| content.strip_prefix(&nick_mention))
+        .map(str::trim)
+}
+
+/// Removes a code block or inline code wrapper around the code, if there is one.
+///
+/// Accepted forms are a fenced block with an optional language tag, ``double`` and
+/// `single` backtick inline code.
+fn strip_code_fence(body: &str) -> &str {
+    lazy_static! {
+        static ref WRAPPER_RES: Vec<Regex> = vec![
+            Regex::new(r"(?s)\A```(?:scheme\s+|[\w+-]*\n)?(.*)```\z").unwrap(),
+            Regex::new(r"(?s)\A``\s?(.*?)\s?``\z").unwrap(),
+            Regex::new(r"(?s)\A`([^`]*)`\z").unwrap(),
+        ];
+    }
+    WRAPPER_RES
+        .iter()
+        .find_map(|re| re.captures(body).and_then(|c| c.get(1)))
+        .map(|code| code.as_str())
+        .unwrap_or(body)
+}
@@ -0,0 +1,36 @@
+//! Discord actions requested by Scheme code through `bot-say` and `bot-react`.
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+
+/// The Discord message an evaluation runs on behalf of. Programs can only act on Discord
+/// while one is set.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Invocation {
+    /// Not exposed to Scheme, but used to rate limit actions.
+    pub author_id: UserId,
+    pub author_name: String,
+    /// Most actions a single evaluation may do. Those it queues past that are dropped.
+    pub max_actions: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    /// Send a message to the channel the evaluation was triggered in.
+    Say(String),
+    /// React to the message that triggered the evaluation.
+    React(String),
+}
+
+impl Action {
+    /// Parses an action as queued by the bot prelude, `kind:text`.
+    pub fn parse(queued: &str) -> Option<Self> {
+        let (kind, text) = queued.split_at(queued.find(':')?);
+        let text = text[1..].to_string();
+        match kind {
+            "say" => Some(Action::Say(text)),
+            "react" => Some(Action::React(text)),
+            _ => None,
+        }
+    }
+}
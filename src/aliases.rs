@@ -0,0 +1,82 @@
+//! Aliases defined by guild admins with `¡alias`: short commands standing for the start of
+//! a longer message.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+
+use serenity::model::id::GuildId;
+
+/// The aliases of each guild, and the file they are saved to.
+pub struct AliasStore {
+    path: Option<String>,
+    /// Keyed by guild ID, as JSON keys are strings, then by alias name, without the `¡`.
+    guilds: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl AliasStore {
+    /// Loads the aliases saved at `path`, if any. Without a path, they are only kept in
+    /// memory.
+    pub fn load(path: Option<String>) -> Result<Self, String> {
+        let guilds = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("error parsing {}: {}", path, e))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(format!("error reading {}: {}", path, e)),
+            },
+            None => HashMap::new(),
+        };
+        Ok(Self { path, guilds })
+    }
+
+    /// The aliases of `guild`, by name.
+    pub fn guild(&self, guild: GuildId) -> Option<&BTreeMap<String, String>> {
+        self.guilds.get(&guild.to_string())
+    }
+
+    /// `content` with the alias it starts with, if any, replaced by what it stands for.
+    pub fn expand(&self, guild: GuildId, content: &str) -> Option<String> {
+        let content = content.trim();
+        let rest = content.strip_prefix('¡')?;
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let expansion = self.guild(guild)?.get(&rest[..end])?;
+        Some(
+            format!("{} {}", expansion, rest[end..].trim())
+                .trim()
+                .to_string(),
+        )
+    }
+
+    /// Makes `name` stand for `expansion` in `guild`, and saves every guild's aliases.
+    pub fn set(&mut self, guild: GuildId, name: &str, expansion: &str) -> Result<(), String> {
+        self.guilds
+            .entry(guild.to_string())
+            .or_default()
+            .insert(name.to_string(), expansion.to_string());
+        self.save()
+    }
+
+    /// Removes the alias `name` of `guild`, and saves every guild's aliases. Returns whether
+    /// there was one.
+    pub fn remove(&mut self, guild: GuildId, name: &str) -> Result<bool, String> {
+        let removed = self
+            .guilds
+            .get_mut(&guild.to_string())
+            .and_then(|aliases| aliases.remove(name))
+            .is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = serde_json::to_string_pretty(&self.guilds).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("error writing {}: {}", path, e))
+    }
+}
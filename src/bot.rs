@@ -0,0 +1,547 @@
+//! Deciding what a message asks the bot to do, independently of the chat service.
+
+use std::sync::Arc;
+
+use repl_bot::transport::ChatEvent;
+use repl_bot::trigger;
+use serenity::model::id::UserId;
+
+use crate::config::Config;
+use crate::drafts;
+use crate::i18n::Catalog;
+use crate::pairs::{HANDOFF_COMMAND, LEAVE_COMMAND, PAIR_COMMAND};
+use crate::worker::SessionKey;
+
+/// How the result of an evaluation is presented.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Evaluate,
+    /// Also shows the size of the code, for `¡golf`.
+    Golf,
+    /// Summarizes the program's checks instead of showing its values, for `¡test`.
+    Test,
+}
+
+/// Commands for the bot's owner, in direct messages.
+pub enum OwnerCommand<'a> {
+    Guilds,
+    Shutdown,
+    ReloadConfig,
+    Broadcast(&'a str),
+}
+
+/// What a message asks for. Arguments are borrowed from the message.
+pub enum Route<'a> {
+    Source,
+    Version,
+    Help,
+    /// `¡reload` of the given session, by someone allowed to.
+    Reload(SessionKey),
+    SourceOf(SessionKey, &'a str),
+    Bindings(SessionKey, &'a str),
+    /// `¡apropos text page`.
+    Apropos(SessionKey, &'a str, &'a str),
+    Schedule(&'a str),
+    Set(&'a str),
+    Lang(&'a str),
+    Alias(&'a str),
+    Features(&'a str),
+    Commit,
+    Export(SessionKey),
+    /// `¡import` into the given session, with what follows it.
+    Import(SessionKey, &'a str),
+    Stats,
+    Challenge(&'a str),
+    Submit(&'a str),
+    Propose(&'a str),
+    /// `¡begin`, starting a program written over several messages.
+    Begin,
+    /// `¡pair`, with what follows it.
+    Pair(&'a str),
+    Handoff,
+    Leave,
+    Owner(OwnerCommand<'a>),
+    Evaluate {
+        session: SessionKey,
+        command: String,
+        mode: Mode,
+    },
+    /// Nothing to do but answer this.
+    Reply(&'static str),
+    Ignore,
+}
+
+/// The message handling that doesn't need Discord.
+pub struct Bot {
+    config: Arc<Config>,
+    /// Mentioning the bot works as a prefix.
+    bot_id: UserId,
+    /// The messages of the language spoken where the message was sent.
+    catalog: &'static Catalog,
+    /// The pair session the author is in in the message's channel, whose environment then
+    /// stands in for the guild's.
+    pair: Option<u64>,
+}
+
+impl Bot {
+    pub fn new(config: Arc<Config>, bot_id: UserId, catalog: &'static Catalog) -> Self {
+        Self {
+            config,
+            bot_id,
+            catalog,
+            pair: None,
+        }
+    }
+
+    /// The same bot, for a message whose author is in the pair session `pair` there.
+    pub fn in_pair(self, pair: Option<u64>) -> Self {
+        Self { pair, ..self }
+    }
+
+    /// Whether the bot listens to `event` at all: it must come from a person, in the
+    /// configured channel or in direct messages if they are enabled.
+    pub fn accepts(&self, event: &impl ChatEvent) -> bool {
+        if event.is_from_bot() {
+            return false;
+        }
+        match event.guild() {
+            None => self.config.dm.enabled,
+            Some(_) => event.channel_name().as_ref() == Some(&self.config.channel_name),
+        }
+    }
+
+    /// Works out what `event` asks for. `custom_call` turns a call of a command defined
+    /// with `define-command` into the code to run, if it is one.
+    pub fn route<'a>(
+        &self,
+        event: &'a impl ChatEvent,
+        custom_call: impl FnOnce(&str) -> Option<String>,
+    ) -> Route<'a> {
+        let config = &self.config;
+        let catalog = self.catalog;
+        let content = event.content().trim();
+        let author = event.author();
+        let direct = event.guild().is_none();
+        let environment = self.environment(event);
+
+        if direct {
+            if let Some(route) = self.owner_route(content, author) {
+                return route;
+            }
+        }
+
+        match content {
+            "¡source" => return Route::Source,
+            "¡version" => return Route::Version,
+            "¡help" | "/help" => return Route::Help,
+            "¡reload" if direct || self.pair.is_some() || config.is_admin(author) => {
+                return Route::Reload(environment)
+            }
+            "¡reload" => return Route::Reply(catalog.reload_denied),
+            "¡commit" => return Route::Commit,
+            "¡export" => return Route::Export(environment),
+            "¡stats" => return Route::Stats,
+            drafts::BEGIN_COMMAND => return Route::Begin,
+            drafts::END_COMMAND | drafts::ABORT_COMMAND => {
+                return Route::Reply(catalog.not_composing)
+            }
+            LEAVE_COMMAND if direct => return Route::Reply(catalog.pairs_in_servers),
+            LEAVE_COMMAND => return Route::Leave,
+            _ => {}
+        }
+
+        if let Some(name) = command_args(content, "¡source-of") {
+            return Route::SourceOf(environment, name);
+        }
+        if let Some(args) = command_args(content, "¡bindings") {
+            return Route::Bindings(environment, args);
+        }
+        if let Some(args) = command_args(content, "¡apropos") {
+            let mut words = args.split_whitespace();
+            return match words.next() {
+                Some(text) => Route::Apropos(environment, text, words.next().unwrap_or("")),
+                None => Route::Reply(catalog.apropos_usage),
+            };
+        }
+        if let Some(args) = command_args(content, "¡schedule") {
+            return if direct {
+                Route::Reply(catalog.schedule_in_servers)
+            } else {
+                Route::Schedule(args)
+            };
+        }
+        if let Some(args) = command_args(content, "¡set") {
+            return Route::Set(args);
+        }
+        if let Some(args) = command_args(content, "¡lang") {
+            return if direct {
+                Route::Reply(catalog.lang_in_servers)
+            } else {
+                Route::Lang(args)
+            };
+        }
+        if let Some(args) = command_args(content, "¡alias") {
+            return if direct {
+                Route::Reply(catalog.aliases_in_servers)
+            } else {
+                Route::Alias(args)
+            };
+        }
+        if let Some(args) = command_args(content, "¡features") {
+            return if direct {
+                Route::Reply(catalog.features_in_servers)
+            } else {
+                Route::Features(args)
+            };
+        }
+        if let Some(args) = command_args(content, PAIR_COMMAND) {
+            return if direct {
+                Route::Reply(catalog.pairs_in_servers)
+            } else {
+                Route::Pair(args)
+            };
+        }
+        if command_args(content, HANDOFF_COMMAND).is_some() {
+            return if direct {
+                Route::Reply(catalog.pairs_in_servers)
+            } else {
+                Route::Handoff
+            };
+        }
+        if let Some(args) = command_args(content, "¡import") {
+            return Route::Import(environment, args);
+        }
+        if let Some(args) = command_args(content, "¡challenge") {
+            return Route::Challenge(args);
+        }
+        if let Some(code) = command_args(content, "¡submit") {
+            return if direct {
+                Route::Reply(catalog.challenges_in_servers)
+            } else {
+                Route::Submit(code)
+            };
+        }
+        if let Some(code) = command_args(content, "¡propose") {
+            return if direct {
+                Route::Reply(catalog.proposals_in_servers)
+            } else {
+                Route::Propose(code)
+            };
+        }
+
+        let mode_command = command_args(content, "¡golf")
+            .map(|code| (Mode::Golf, code))
+            .or_else(|| command_args(content, "¡test").map(|code| (Mode::Test, code)));
+        let mode = mode_command.map_or(Mode::Evaluate, |(mode, _)| mode);
+        let mode_code = mode_command.map(|(_, code)| trigger::extract_direct(code));
+        let unrestricted = config.triggers(event.guild()).extract_unrestricted(content);
+        if unrestricted.is_some() && !config.is_admin(author) {
+            return Route::Reply(catalog.full_environment_denied);
+        }
+        // Commands defined from Scheme live in the guild's environment.
+        let custom_call = if direct || self.pair.is_some() {
+            None
+        } else {
+            custom_call(content)
+        };
+        let (session, command) = match (unrestricted, mode_code, custom_call) {
+            (Some(code), _, _) => (SessionKey::Admin, code),
+            (None, Some(code), _) => (environment, code),
+            (None, None, Some(call)) => (environment, call),
+            (None, None, None) => {
+                let extracted = config.triggers(event.guild()).extract(content, self.bot_id);
+                match extracted {
+                    Some(command) if !direct => (environment, command),
+                    None if !direct => return Route::Ignore,
+                    extracted => (
+                        environment,
+                        extracted.unwrap_or_else(|| trigger::extract_direct(content)),
+                    ),
+                }
+            }
+        };
+        if command.trim().is_empty() {
+            return Route::Reply(catalog.nothing_to_evaluate);
+        }
+        Route::Evaluate {
+            session,
+            command,
+            mode,
+        }
+    }
+
+    /// The environment of the conversation `event` is in: the guild's, or that of the
+    /// author's pair session there, or the author's own in DMs.
+    pub fn environment(&self, event: &impl ChatEvent) -> SessionKey {
+        match (event.guild(), self.pair) {
+            (Some(guild), Some(pair)) => SessionKey::Pair(guild, pair),
+            (Some(guild), None) => SessionKey::Guild(guild),
+            (None, _) => SessionKey::Direct(event.author()),
+        }
+    }
+
+    /// What an owner command in a direct message asks for, if `content` is one.
+    fn owner_route<'a>(&self, content: &'a str, author: UserId) -> Option<Route<'a>> {
+        let route = match content {
+            "¡guilds" => Route::Owner(OwnerCommand::Guilds),
+            "¡shutdown" => Route::Owner(OwnerCommand::Shutdown),
+            "¡reload-config" => Route::Owner(OwnerCommand::ReloadConfig),
+            _ => {
+                if let Some(text) = command_args(content, "¡broadcast") {
+                    if text.is_empty() {
+                        Route::Reply(self.catalog.broadcast_usage)
+                    } else {
+                        Route::Owner(OwnerCommand::Broadcast(text))
+                    }
+                } else if let Some(code) = command_args(content, "¡eval-host") {
+                    Route::Evaluate {
+                        session: SessionKey::Admin,
+                        command: trigger::extract_direct(code),
+                        mode: Mode::Evaluate,
+                    }
+                } else {
+                    return None;
+                }
+            }
+        };
+        Some(if self.config.is_owner(author) {
+            route
+        } else {
+            Route::Reply(self.catalog.owner_only)
+        })
+    }
+}
+
+/// The arguments of `name` if `content` is that command, with or without arguments.
+pub fn command_args<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+    let rest = content.strip_prefix(name)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use repl_bot::transport::{MemoryEvent, MemoryTransport, Transport};
+    use serenity::model::id::{ChannelId, GuildId};
+
+    use super::*;
+    use crate::i18n::Language;
+
+    const BOT: UserId = UserId(99);
+    const ADMIN: UserId = UserId(1);
+    const USER: UserId = UserId(2);
+    const GUILD: GuildId = GuildId(10);
+    const CHANNEL: ChannelId = ChannelId(20);
+
+    fn bot(config: &str) -> Bot {
+        let config = Config::from_toml(&format!("admins = [\"{}\"]\n{}", ADMIN, config)).unwrap();
+        Bot::new(Arc::new(config), BOT, Language::En.catalog())
+    }
+
+    fn in_guild(author: UserId, content: &str) -> MemoryEvent {
+        MemoryEvent {
+            author,
+            from_bot: false,
+            guild: Some(GUILD),
+            channel: CHANNEL,
+            channel_name: Some("lisp".into()),
+            content: content.into(),
+        }
+    }
+
+    fn direct(author: UserId, content: &str) -> MemoryEvent {
+        MemoryEvent {
+            guild: None,
+            channel_name: None,
+            ..in_guild(author, content)
+        }
+    }
+
+    /// The session and code of an evaluation `event` asks for, with no commands defined.
+    fn evaluation(bot: &Bot, event: &MemoryEvent) -> Option<(SessionKey, String)> {
+        match bot.route(event, |_| None) {
+            Route::Evaluate {
+                session, command, ..
+            } => Some((session, command)),
+            _ => None,
+        }
+    }
+
+    /// The session `event` asks to start over, if it does.
+    fn reload(bot: &Bot, event: &MemoryEvent) -> Option<SessionKey> {
+        match bot.route(event, |_| None) {
+            Route::Reload(session) => Some(session),
+            _ => None,
+        }
+    }
+
+    /// What the bot sends back to `event` when all it does is answer.
+    fn replies(bot: &Bot, event: &MemoryEvent) -> Vec<(ChannelId, String)> {
+        let transport = MemoryTransport::default();
+        if bot.accepts(event) {
+            if let Route::Reply(text) = bot.route(event, |_| None) {
+                transport.say(event.channel(), text);
+            }
+        }
+        transport.sent.into_inner().unwrap()
+    }
+
+    #[test]
+    fn accepts_people_in_the_configured_channel() {
+        let bot = bot("");
+        assert!(bot.accepts(&in_guild(USER, "¡cl 1")));
+        let other_channel = MemoryEvent {
+            channel_name: Some("general".into()),
+            ..in_guild(USER, "¡cl 1")
+        };
+        assert!(!bot.accepts(&other_channel));
+        let unknown_channel = MemoryEvent {
+            channel_name: None,
+            ..in_guild(USER, "¡cl 1")
+        };
+        assert!(!bot.accepts(&unknown_channel));
+        let from_bot = MemoryEvent {
+            from_bot: true,
+            ..in_guild(USER, "¡cl 1")
+        };
+        assert!(!bot.accepts(&from_bot));
+    }
+
+    #[test]
+    fn ignores_direct_messages_when_disabled() {
+        assert!(bot("").accepts(&direct(USER, "(+ 1 2)")));
+        let bot = bot("[dm]\nenabled = false");
+        assert!(!bot.accepts(&direct(USER, "(+ 1 2)")));
+        assert!(replies(&bot, &direct(USER, "¡reload")).is_empty());
+        assert!(bot.accepts(&in_guild(USER, "¡cl (+ 1 2)")));
+    }
+
+    #[test]
+    fn evaluates_after_a_prefix_or_a_mention() {
+        let bot = bot("");
+        let expected = Some((SessionKey::Guild(GUILD), "(+ 1 2)".to_string()));
+        assert_eq!(evaluation(&bot, &in_guild(USER, "¡cl (+ 1 2)")), expected);
+        assert_eq!(evaluation(&bot, &in_guild(USER, "oo (+ 1 2)")), expected);
+        assert_eq!(evaluation(&bot, &in_guild(USER, "<@99> (+ 1 2)")), expected);
+        assert_eq!(evaluation(&bot, &in_guild(USER, "¡cl `(+ 1 2)`")), expected);
+        assert!(matches!(
+            bot.route(&in_guild(USER, "(+ 1 2)"), |_| None),
+            Route::Ignore
+        ));
+        assert!(matches!(
+            bot.route(&in_guild(USER, "¡clx (+ 1 2)"), |_| None),
+            Route::Ignore
+        ));
+    }
+
+    #[test]
+    fn uses_the_guild_prefixes() {
+        let bot = bot(&format!("[guilds.{}]\nprefixes = [\">\"]", GUILD));
+        assert_eq!(
+            evaluation(&bot, &in_guild(USER, "> (+ 1 2)")),
+            Some((SessionKey::Guild(GUILD), "(+ 1 2)".to_string()))
+        );
+        assert!(matches!(
+            bot.route(&in_guild(USER, "¡cl (+ 1 2)"), |_| None),
+            Route::Ignore
+        ));
+    }
+
+    #[test]
+    fn evaluates_direct_messages_without_a_prefix() {
+        let bot = bot("");
+        assert_eq!(
+            evaluation(&bot, &direct(USER, "(+ 1 2)")),
+            Some((SessionKey::Direct(USER), "(+ 1 2)".to_string()))
+        );
+        assert_eq!(
+            evaluation(&bot, &direct(USER, "¡cl (+ 1 2)")),
+            Some((SessionKey::Direct(USER), "(+ 1 2)".to_string()))
+        );
+    }
+
+    #[test]
+    fn reloads_the_guild_only_for_admins() {
+        let bot = bot("");
+        assert_eq!(
+            reload(&bot, &in_guild(ADMIN, "¡reload")),
+            Some(SessionKey::Guild(GUILD))
+        );
+        let catalog = Language::En.catalog();
+        assert_eq!(
+            replies(&bot, &in_guild(USER, "¡reload")),
+            vec![(CHANNEL, catalog.reload_denied.to_string())]
+        );
+        // Everyone may start their own sessions over.
+        assert_eq!(
+            reload(&bot, &direct(USER, "¡reload")),
+            Some(SessionKey::Direct(USER))
+        );
+        let bot = bot.in_pair(Some(7));
+        assert_eq!(
+            reload(&bot, &in_guild(USER, "¡reload")),
+            Some(SessionKey::Pair(GUILD, 7))
+        );
+    }
+
+    #[test]
+    fn calls_custom_commands_in_guilds() {
+        let bot = bot("");
+        let call = |content: &str| {
+            assert_eq!(content, "¡greet bob");
+            Some("(greet \"bob\")".to_string())
+        };
+        match bot.route(&in_guild(USER, "¡greet bob"), call) {
+            Route::Evaluate {
+                session,
+                command,
+                mode: Mode::Evaluate,
+            } => {
+                assert_eq!(session, SessionKey::Guild(GUILD));
+                assert_eq!(command, "(greet \"bob\")");
+            }
+            _ => panic!("the command wasn't called"),
+        }
+        let unknown = bot.route(&in_guild(USER, "¡greet bob"), |_| None);
+        assert!(matches!(unknown, Route::Ignore));
+    }
+
+    #[test]
+    fn leaves_custom_commands_to_guilds() {
+        let bot = bot("");
+        let call = |_: &str| -> Option<String> { panic!("commands were looked up") };
+        assert_eq!(
+            match bot.route(&direct(USER, "¡greet bob"), call) {
+                Route::Evaluate { command, .. } => Some(command),
+                _ => None,
+            },
+            Some("¡greet bob".to_string())
+        );
+        let bot = bot.in_pair(Some(7));
+        assert!(matches!(
+            bot.route(&in_guild(USER, "¡greet bob"), call),
+            Route::Ignore
+        ));
+    }
+
+    #[test]
+    fn evaluates_unrestricted_code_only_for_admins() {
+        let bot = bot("");
+        assert_eq!(
+            evaluation(&bot, &in_guild(ADMIN, "¡cl! (+ 1 2)")),
+            Some((SessionKey::Admin, "(+ 1 2)".to_string()))
+        );
+        let catalog = Language::En.catalog();
+        assert_eq!(
+            replies(&bot, &in_guild(USER, "¡cl! (+ 1 2)")),
+            vec![(CHANNEL, catalog.full_environment_denied.to_string())]
+        );
+        assert_eq!(
+            replies(&bot, &direct(USER, "¡cl! (+ 1 2)")),
+            vec![(CHANNEL, catalog.full_environment_denied.to_string())]
+        );
+    }
+}
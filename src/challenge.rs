@@ -0,0 +1,143 @@
+//! Coding challenges: admins post a problem with hidden test cases, and users submit
+//! solutions that are run against them.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+
+use crate::i18n::{fill, Catalog};
+use crate::interpreter::Evaluation;
+
+pub struct Challenge {
+    pub name: String,
+    pub description: String,
+    pub tests: Vec<TestCase>,
+}
+
+/// A Scheme expression using the submission, and an expression for the value it should
+/// evaluate to.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TestCase {
+    pub expression: String,
+    pub expected: String,
+}
+
+impl Challenge {
+    /// Parses a challenge written as its name on the first line, then its description, with
+    /// test cases on lines like `test (solution 1 2) => 3`. Errors are in the language of
+    /// `catalog`.
+    pub fn parse(spec: &str, catalog: &Catalog) -> Result<Self, String> {
+        let mut lines = spec.trim().lines();
+        let name = lines
+            .next()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .ok_or(catalog.challenge_needs_name)?
+            .to_string();
+        let mut description = Vec::new();
+        let mut tests = Vec::new();
+        for line in lines {
+            match line.trim().strip_prefix("test ") {
+                Some(test) => {
+                    let arrow = test
+                        .rfind("=>")
+                        .ok_or_else(|| fill(catalog.test_without_expected, &[&test]))?;
+                    tests.push(TestCase {
+                        expression: test[..arrow].trim().to_string(),
+                        expected: test[arrow + 2..].trim().to_string(),
+                    });
+                }
+                None => description.push(line),
+            }
+        }
+        if tests.is_empty() {
+            return Err(catalog.challenge_needs_test.into());
+        }
+        Ok(Self {
+            name,
+            description: description.join("\n").trim().to_string(),
+            tests,
+        })
+    }
+}
+
+/// The result of running a submission against a challenge's tests.
+#[derive(Serialize, Deserialize)]
+pub struct Judgement {
+    /// Evaluation of the submitted code itself.
+    pub submission: Evaluation,
+    /// Evaluations of the tests' checks, in order; empty if the submission failed.
+    pub tests: Vec<Evaluation>,
+}
+
+impl Judgement {
+    pub fn test_passed(test: &Evaluation) -> bool {
+        test.succeeded() && test.values.last().map(String::as_str) == Some("#t")
+    }
+
+    pub fn passed(&self) -> usize {
+        self.tests.iter().filter(|t| Self::test_passed(t)).count()
+    }
+}
+
+/// A guild's current challenge and leaderboard.
+#[derive(Default)]
+pub struct GuildChallenges {
+    current: Option<Challenge>,
+    /// Who solved the current challenge.
+    solvers: HashSet<UserId>,
+    /// Author and length in characters of the shortest solution to the current challenge.
+    shortest: Option<(UserId, usize)>,
+    /// Challenges solved by each user.
+    points: HashMap<UserId, u32>,
+}
+
+impl GuildChallenges {
+    pub fn current(&self) -> Option<&Challenge> {
+        self.current.as_ref()
+    }
+
+    /// Replaces the current challenge.
+    pub fn set(&mut self, challenge: Challenge) {
+        self.current = Some(challenge);
+        self.solvers.clear();
+        self.shortest = None;
+    }
+
+    pub fn shortest(&self) -> Option<(UserId, usize)> {
+        self.shortest
+    }
+
+    /// Records a solution to the current challenge by `user` that is `length` characters
+    /// long. Returns whether it is the new shortest.
+    pub fn record_length(&mut self, user: UserId, length: usize) -> bool {
+        let shorter = self.shortest.map_or(true, |(_, best)| length < best);
+        if shorter {
+            self.shortest = Some((user, length));
+        }
+        shorter
+    }
+
+    /// Records that `user` solved the current challenge. Returns whether it's the first time
+    /// they did.
+    pub fn record_solve(&mut self, user: UserId) -> bool {
+        let first = self.solvers.insert(user);
+        if first {
+            *self.points.entry(user).or_insert(0) += 1;
+        }
+        first
+    }
+
+    /// The `n` users with the most challenges solved, best first.
+    pub fn leaderboard(&self, n: usize) -> Vec<(UserId, u32)> {
+        let mut users = self
+            .points
+            .iter()
+            .map(|(user, points)| (*user, *points))
+            .collect::<Vec<_>>();
+        users.sort_by(|a, b| b.1.cmp(&a.1));
+        users.truncate(n);
+        users
+    }
+}
@@ -0,0 +1,800 @@
+//! Runtime configuration for the bot.
+//!
+//! The configuration is read from a TOML file (see `config.example.toml`); every key is
+//! optional and falls back to the defaults below.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use repl_bot::format::{LongOutput, Printing, QuoteInput};
+use repl_bot::limits::Limits;
+use repl_bot::trigger::Triggers;
+use serde::Deserialize;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::i18n::Language;
+use crate::interpreter::NO_MUTATION;
+
+/// Name of the tier used when neither the user nor the guild has one.
+const DEFAULT_TIER: &str = "default";
+
+pub struct Config {
+    /// Name of the guild channel the bot listens in.
+    pub channel_name: String,
+    /// How long a message waits for the interpreter to become available.
+    pub lock_timeout: Duration,
+    /// Number of interpreter threads, or sandbox processes in sandbox mode.
+    pub workers: usize,
+    /// Jobs a worker may have running or waiting before further ones are turned away.
+    pub queue_length: usize,
+    /// Evaluations that have run this long are interrupted if other jobs are waiting for
+    /// their worker; zero disables this.
+    pub preempt_after: Duration,
+    /// Number of gateway shards, or 0 to use the number Discord recommends.
+    pub shards: u64,
+    /// How long a program may keep asking for input, in all.
+    pub input_timeout: Duration,
+    /// Longest program that can be written with `¡begin`, in characters.
+    pub draft_max_chars: usize,
+    /// Programs being written with `¡begin` are dropped after this long without a message.
+    pub draft_timeout: Duration,
+    /// How much of a program its result quotes, unless its guild or author chose otherwise.
+    pub quote_input: QuoteInput,
+    /// Where results put program output longer than `long_output_lines`.
+    pub long_output: LongOutput,
+    /// Zero means no limit.
+    pub long_output_lines: usize,
+    /// How long the result of an evaluation in a guild's environment is reused for
+    /// identical evaluations; zero disables this.
+    pub result_cache: Duration,
+    /// Guild interpreters unused for this long are dropped, keeping the committed
+    /// definitions to rebuild them from.
+    pub guild_session_idle: Duration,
+    /// Most programs a guild may commit to its environment, which is built again from them
+    /// when it was dropped or can't be used as it is.
+    pub max_committed_programs: usize,
+    pub dm: DmConfig,
+    pub pairs: PairsConfig,
+    pub actions: ActionsConfig,
+    pub http: HttpConfig,
+    pub sandbox: SandboxConfig,
+    pub logging: LoggingConfig,
+    pub paste: PasteConfig,
+    pub startup: StartupConfig,
+    /// File where user preferences are saved; they are only kept in memory without one.
+    pub preferences_path: Option<String>,
+    /// File where the definitions approved with `¡propose` are saved; they are only kept in
+    /// memory without one.
+    pub preludes_path: Option<String>,
+    /// How many more approvals than rejections a proposal needs to be added.
+    pub proposal_approvals: usize,
+    /// Whether messages asking for an evaluation get reactions showing how it is going.
+    pub status_reactions: bool,
+    /// Whether results in guilds are posted through a webhook, under the name and avatar of
+    /// who asked for them.
+    pub webhook_results: bool,
+    /// The language the bot speaks where none was chosen with `¡lang`.
+    pub language: Language,
+    /// File where the languages chosen with `¡lang` are saved; they are only kept in memory
+    /// without one.
+    pub languages_path: Option<String>,
+    /// File where the aliases defined with `¡alias` are saved; they are only kept in memory
+    /// without one.
+    pub aliases_path: Option<String>,
+    /// File where the features chosen with `¡features` are saved; they are only kept in
+    /// memory without one.
+    pub features_path: Option<String>,
+    /// Features on in guilds that didn't choose theirs.
+    default_features: Vec<String>,
+    /// Users allowed to run admin commands.
+    admins: HashSet<UserId>,
+    /// The operator of the bot, who may manage it from direct messages.
+    owner: Option<UserId>,
+    default_triggers: Triggers,
+    default_printing: Printing,
+    guilds: HashMap<GuildId, GuildConfig>,
+    /// Evaluation limits by tier name; always contains `DEFAULT_TIER`.
+    tiers: HashMap<String, Limits>,
+    user_tiers: HashMap<UserId, String>,
+}
+
+/// Settings for private sessions in direct messages with the bot.
+pub struct DmConfig {
+    pub enabled: bool,
+    /// Each user may run this many evaluations per `rate_limit_window`.
+    pub rate_limit: usize,
+    pub rate_limit_window: Duration,
+    /// At most this many DM sessions are kept alive; the least recently used is dropped.
+    pub max_sessions: usize,
+    /// Sessions unused for this long are dropped.
+    pub session_idle: Duration,
+}
+
+/// Settings for pair sessions, where two users share an environment in a guild channel.
+pub struct PairsConfig {
+    /// At most this many pair sessions run at once.
+    pub max_sessions: usize,
+    /// Sessions without a message from either user for this long end.
+    pub idle: Duration,
+}
+
+/// Limits on the Discord actions programs can take with `bot-say` and `bot-react`.
+pub struct ActionsConfig {
+    /// Most actions a single evaluation may queue.
+    pub max_per_evaluation: usize,
+    /// Each user's programs may take this many actions per `rate_limit_window`; further
+    /// actions are dropped.
+    pub rate_limit: usize,
+    pub rate_limit_window: Duration,
+}
+
+/// What is loaded into new interpreters.
+pub struct StartupConfig {
+    /// Load the standard library from this file instead of the bundled copy.
+    pub init_path: Option<String>,
+    /// Extra Scheme files loaded after the standard library and the bot prelude, in order.
+    pub files: Vec<String>,
+    /// Files loaded after the startup files in guilds with the feature of the same name
+    /// on, by feature name.
+    pub libraries: HashMap<String, Vec<String>>,
+}
+
+/// Settings for running evaluations in a separate, locked-down process.
+pub struct SandboxConfig {
+    pub enabled: bool,
+    /// Address space the sandbox process may use, in bytes.
+    pub memory_limit: u64,
+}
+
+pub struct LoggingConfig {
+    /// Filter in `RUST_LOG` syntax, such as `info` or `peroxide_discord=debug`. `RUST_LOG`
+    /// takes precedence when it's set.
+    pub level: String,
+    /// Whether to log JSON objects rather than text lines.
+    pub json: bool,
+}
+
+/// Settings for fetching code from paste services and uploading long results to one.
+pub struct PasteConfig {
+    pub enabled: bool,
+    /// Where long results are uploaded; they are truncated as usual if this isn't set.
+    pub upload_url: Option<String>,
+    /// Hosts code may be fetched from.
+    pub allowed_hosts: Vec<String>,
+    /// Longest paste we evaluate, in bytes.
+    pub max_fetch_bytes: u64,
+    pub timeout: Duration,
+}
+
+/// Settings for the HTTP API.
+pub struct HttpConfig {
+    pub enabled: bool,
+    /// Address to listen on, such as `127.0.0.1:8080`.
+    pub address: String,
+    /// Bearer token clients must present.
+    pub token: String,
+    /// How long `/healthz` and `/readyz` wait for each worker to answer a ping.
+    pub ping_timeout: Duration,
+}
+
+/// Settings that can be overridden for a single guild.
+pub struct GuildConfig {
+    pub triggers: Triggers,
+    /// Tier for users of the guild who don't have one of their own.
+    pub tier: Option<String>,
+    pub quote_input: Option<QuoteInput>,
+    pub printing: Printing,
+    /// Where every evaluation in the guild is mirrored, for moderators.
+    pub log_channel: Option<ChannelId>,
+    /// Where the guild's scheduled evaluations post their results, instead of the channel
+    /// they were scheduled from.
+    pub schedule_channel: Option<ChannelId>,
+    /// Features on unless the guild's admins chose others.
+    pub features: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Loads the configuration at `path`, using the defaults if the file doesn't exist.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let raw = match read_file(path)? {
+            Some(contents) => {
+                toml::from_str(&contents).map_err(|e| format!("error parsing {}: {}", path, e))?
+            }
+            None => RawConfig::default(),
+        };
+        Self::from_raw(raw)
+    }
+
+    /// Reads the configuration file at `path` as the sandbox gets it: with the HTTP API turned
+    /// off and its token removed, so that the sandbox never holds it.
+    pub fn without_secrets(path: &str) -> Result<String, String> {
+        let mut raw = match read_file(path)? {
+            Some(contents) => contents
+                .parse::<toml::Value>()
+                .map_err(|e| format!("error parsing {}: {}", path, e))?,
+            None => toml::Value::Table(Default::default()),
+        };
+        if let Some(http) = raw.get_mut("http").and_then(toml::Value::as_table_mut) {
+            http.remove("token");
+            http.insert("enabled".into(), toml::Value::Boolean(false));
+        }
+        Ok(raw.to_string())
+    }
+
+    /// Reads a configuration from the contents of a configuration file.
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        Self::from_raw(toml::from_str(contents).map_err(|e| e.to_string())?)
+    }
+
+    fn from_raw(raw: RawConfig) -> Result<Self, String> {
+        if raw.workers == 0 {
+            return Err("there must be at least one worker".into());
+        }
+        if raw.queue_length == 0 {
+            return Err("queue_length must be at least 1".into());
+        }
+        if raw.proposal_approvals == 0 {
+            return Err("proposal_approvals must be at least 1".into());
+        }
+        let default_tier = RawTier {
+            eval_timeout_secs: Some(raw.eval_timeout_secs),
+            cpu_fuel_ms: Some(raw.cpu_fuel_ms),
+            memory_fuel_mb: Some(raw.memory_fuel_mb),
+        };
+        let mut tiers = HashMap::new();
+        tiers.insert(DEFAULT_TIER.to_string(), default_tier.limits(&default_tier));
+        for (name, tier) in &raw.tiers {
+            tiers.insert(name.clone(), tier.limits(&default_tier));
+        }
+        let check_tier = |tier: &str| {
+            if tiers.contains_key(tier) {
+                Ok(())
+            } else {
+                Err(format!("unknown tier {}", tier))
+            }
+        };
+
+        let default_printing = Printing {
+            max_chars: raw.max_response_chars,
+            max_depth: raw.print_depth,
+            max_items: raw.print_items,
+            shared: raw.print_shared,
+        };
+        if raw.libraries.contains_key(NO_MUTATION) {
+            return Err(format!(
+                "{} is a built-in feature, not a library",
+                NO_MUTATION
+            ));
+        }
+        let libraries = &raw.libraries;
+        let check_features = |features: &[String]| {
+            let known =
+                |feature: &String| feature == NO_MUTATION || libraries.contains_key(feature);
+            match features.iter().find(|feature| !known(feature)) {
+                Some(unknown) => Err(format!("unknown feature {}", unknown)),
+                None => Ok(()),
+            }
+        };
+        check_features(&raw.features)?;
+        let mut guilds = HashMap::new();
+        for (id, guild) in raw.guilds {
+            let id = parse_id(&id)?;
+            let prefixes = guild.prefixes.as_ref().unwrap_or(&raw.prefixes);
+            let triggers = Triggers::new(prefixes).map_err(|e| format!("guild {}: {}", id, e))?;
+            if let Some(tier) = &guild.tier {
+                check_tier(tier)?;
+            }
+            let log_channel = guild.log_channel_id.as_deref().map(parse_id).transpose()?;
+            let schedule_channel = guild
+                .schedule_channel_id
+                .as_deref()
+                .map(parse_id)
+                .transpose()?;
+            if let Some(features) = &guild.features {
+                check_features(features).map_err(|e| format!("guild {}: {}", id, e))?;
+            }
+            guilds.insert(
+                GuildId(id),
+                GuildConfig {
+                    triggers,
+                    tier: guild.tier,
+                    quote_input: guild.quote_input,
+                    printing: Printing {
+                        max_chars: guild.max_response_chars.unwrap_or(raw.max_response_chars),
+                        max_depth: guild.print_depth.unwrap_or(raw.print_depth),
+                        max_items: guild.print_items.unwrap_or(raw.print_items),
+                        shared: guild.print_shared.unwrap_or(raw.print_shared),
+                    },
+                    log_channel: log_channel.map(ChannelId),
+                    schedule_channel: schedule_channel.map(ChannelId),
+                    features: guild.features,
+                },
+            );
+        }
+        if raw.http.enabled && raw.http.token.is_empty() {
+            return Err("http.token must be set when the HTTP API is enabled".into());
+        }
+        // The sandbox child runs one job at a time and can't see what waits for it.
+        if raw.sandbox.enabled && raw.preempt_after_secs > 0 {
+            return Err("preempt_after_secs must be 0 when the sandbox is enabled".into());
+        }
+
+        let admins = raw
+            .admins
+            .iter()
+            .map(|id| parse_id(id).map(UserId))
+            .collect::<Result<_, _>>()?;
+
+        let mut user_tiers = HashMap::new();
+        for (id, tier) in raw.user_tiers {
+            check_tier(&tier)?;
+            user_tiers.insert(UserId(parse_id(&id)?), tier);
+        }
+
+        Ok(Self {
+            channel_name: raw.channel_name,
+            lock_timeout: Duration::from_secs(raw.lock_timeout_secs),
+            workers: raw.workers,
+            queue_length: raw.queue_length,
+            preempt_after: Duration::from_secs(raw.preempt_after_secs),
+            shards: raw.shards,
+            input_timeout: Duration::from_secs(raw.input_timeout_secs),
+            draft_max_chars: raw.draft_max_chars,
+            draft_timeout: Duration::from_secs(raw.draft_timeout_secs),
+            quote_input: raw.quote_input,
+            long_output: raw.long_output,
+            long_output_lines: raw.long_output_lines,
+            result_cache: Duration::from_secs(raw.result_cache_secs),
+            guild_session_idle: Duration::from_secs(raw.guild_session_idle_secs),
+            max_committed_programs: raw.max_committed_programs,
+            dm: DmConfig {
+                enabled: raw.dm.enabled,
+                rate_limit: raw.dm.rate_limit,
+                rate_limit_window: Duration::from_secs(raw.dm.rate_limit_window_secs),
+                max_sessions: raw.dm.max_sessions,
+                session_idle: Duration::from_secs(raw.dm.session_idle_secs),
+            },
+            pairs: PairsConfig {
+                max_sessions: raw.pairs.max_sessions,
+                idle: Duration::from_secs(raw.pairs.idle_secs),
+            },
+            actions: ActionsConfig {
+                max_per_evaluation: raw.actions.max_per_evaluation,
+                rate_limit: raw.actions.rate_limit,
+                rate_limit_window: Duration::from_secs(raw.actions.rate_limit_window_secs),
+            },
+            http: HttpConfig {
+                enabled: raw.http.enabled,
+                address: raw.http.address,
+                token: raw.http.token,
+                ping_timeout: Duration::from_millis(raw.http.ping_timeout_ms),
+            },
+            sandbox: SandboxConfig {
+                enabled: raw.sandbox.enabled,
+                memory_limit: raw.sandbox.memory_limit_mb * 1024 * 1024,
+            },
+            logging: LoggingConfig {
+                level: raw.logging.level,
+                json: raw.logging.json,
+            },
+            paste: PasteConfig {
+                enabled: raw.paste.enabled,
+                upload_url: Some(raw.paste.upload_url).filter(|url| !url.is_empty()),
+                allowed_hosts: raw.paste.allowed_hosts,
+                max_fetch_bytes: raw.paste.max_fetch_kb * 1024,
+                timeout: Duration::from_secs(raw.paste.timeout_secs),
+            },
+            startup: StartupConfig {
+                init_path: raw.init_path,
+                files: raw.startup_files,
+                libraries: raw.libraries,
+            },
+            preferences_path: Some(raw.preferences_path).filter(|path| !path.is_empty()),
+            preludes_path: Some(raw.preludes_path).filter(|path| !path.is_empty()),
+            proposal_approvals: raw.proposal_approvals,
+            status_reactions: raw.status_reactions,
+            webhook_results: raw.webhook_results,
+            language: raw.language,
+            languages_path: Some(raw.languages_path).filter(|path| !path.is_empty()),
+            aliases_path: Some(raw.aliases_path).filter(|path| !path.is_empty()),
+            features_path: Some(raw.features_path).filter(|path| !path.is_empty()),
+            default_features: raw.features,
+            admins,
+            owner: raw
+                .owner_id
+                .as_deref()
+                .map(parse_id)
+                .transpose()?
+                .map(UserId),
+            default_triggers: Triggers::new(&raw.prefixes)?,
+            default_printing,
+            guilds,
+            tiers,
+            user_tiers,
+        })
+    }
+
+    /// The limits for evaluations by `user` in `guild`: those of the user's tier if they
+    /// have one, else those of the guild's tier, else the defaults.
+    pub fn limits(&self, guild: Option<GuildId>, user: UserId) -> Limits {
+        let tier = self
+            .user_tiers
+            .get(&user)
+            .or_else(|| guild.and_then(|id| self.guilds.get(&id)?.tier.as_ref()))
+            .map_or(DEFAULT_TIER, String::as_str);
+        self.tiers[tier]
+    }
+
+    pub fn is_admin(&self, user: UserId) -> bool {
+        self.admins.contains(&user)
+    }
+
+    pub fn is_owner(&self, user: UserId) -> bool {
+        self.owner == Some(user)
+    }
+
+    /// The limits for evaluations that aren't tied to a guild or user.
+    pub fn default_limits(&self) -> Limits {
+        self.tiers[DEFAULT_TIER]
+    }
+
+    /// How much of programs evaluated in `guild` results quote, for authors who haven't
+    /// chosen.
+    pub fn quote_input(&self, guild: Option<GuildId>) -> QuoteInput {
+        guild
+            .and_then(|id| self.guilds.get(&id)?.quote_input)
+            .unwrap_or(self.quote_input)
+    }
+
+    /// How results of evaluations in `guild` are printed, for authors who haven't chosen.
+    pub fn printing(&self, guild: Option<GuildId>) -> Printing {
+        guild
+            .and_then(|id| self.guilds.get(&id))
+            .map_or(self.default_printing, |g| g.printing)
+    }
+
+    /// Where evaluations in `guild` are mirrored, if anywhere.
+    pub fn log_channel(&self, guild: GuildId) -> Option<ChannelId> {
+        self.guilds.get(&guild)?.log_channel
+    }
+
+    /// Where scheduled evaluations of `guild` post their results, if not in the channel
+    /// they were scheduled from.
+    pub fn schedule_channel(&self, guild: GuildId) -> Option<ChannelId> {
+        self.guilds.get(&guild)?.schedule_channel
+    }
+
+    /// The features on in `guild` unless its admins chose others.
+    pub fn features(&self, guild: GuildId) -> &[String] {
+        self.guilds
+            .get(&guild)
+            .and_then(|g| g.features.as_deref())
+            .unwrap_or(&self.default_features)
+    }
+
+    /// Every feature guilds can turn on, sorted.
+    pub fn feature_names(&self) -> Vec<&str> {
+        let mut names = self
+            .startup
+            .libraries
+            .keys()
+            .map(String::as_str)
+            .chain(Some(NO_MUTATION))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    /// The triggers in effect in `guild`, or outside of any guild.
+    pub fn triggers(&self, guild: Option<GuildId>) -> &Triggers {
+        guild
+            .and_then(|id| self.guilds.get(&id))
+            .map(|g| &g.triggers)
+            .unwrap_or(&self.default_triggers)
+    }
+}
+
+fn parse_id(id: &str) -> Result<u64, String> {
+    id.parse::<u64>()
+        .map_err(|e| format!("invalid id {}: {}", id, e))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_raw(RawConfig::default()).unwrap()
+    }
+}
+
+/// Reads the file at `path`, or returns `None` if there is none.
+fn read_file(path: &str) -> Result<Option<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("error reading {}: {}", path, e)),
+    }
+}
+
+/// The configuration file as written by the operator.
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawConfig {
+    channel_name: String,
+    eval_timeout_secs: u64,
+    cpu_fuel_ms: u64,
+    memory_fuel_mb: u64,
+    lock_timeout_secs: u64,
+    workers: usize,
+    queue_length: usize,
+    preempt_after_secs: u64,
+    shards: u64,
+    max_response_chars: usize,
+    print_depth: usize,
+    print_items: usize,
+    print_shared: bool,
+    input_timeout_secs: u64,
+    draft_max_chars: usize,
+    draft_timeout_secs: u64,
+    quote_input: QuoteInput,
+    long_output: LongOutput,
+    long_output_lines: usize,
+    result_cache_secs: u64,
+    guild_session_idle_secs: u64,
+    max_committed_programs: usize,
+    prefixes: Vec<String>,
+    dm: RawDmConfig,
+    pairs: RawPairsConfig,
+    actions: RawActionsConfig,
+    http: RawHttpConfig,
+    sandbox: RawSandboxConfig,
+    logging: RawLoggingConfig,
+    paste: RawPasteConfig,
+    init_path: Option<String>,
+    startup_files: Vec<String>,
+    /// User IDs, as strings like every other ID.
+    admins: Vec<String>,
+    owner_id: Option<String>,
+    preferences_path: String,
+    preludes_path: String,
+    proposal_approvals: usize,
+    status_reactions: bool,
+    webhook_results: bool,
+    language: Language,
+    languages_path: String,
+    aliases_path: String,
+    features_path: String,
+    features: Vec<String>,
+    /// Files by library name.
+    libraries: HashMap<String, Vec<String>>,
+    /// Keyed by guild ID; TOML keys are always strings.
+    guilds: HashMap<String, RawGuildConfig>,
+    tiers: HashMap<String, RawTier>,
+    /// Tier names keyed by user ID.
+    user_tiers: HashMap<String, String>,
+}
+
+impl Default for RawConfig {
+    fn default() -> Self {
+        Self {
+            channel_name: "lisp".into(),
+            eval_timeout_secs: 5,
+            cpu_fuel_ms: 4000,
+            memory_fuel_mb: 256,
+            lock_timeout_secs: 15,
+            workers: 1,
+            queue_length: 8,
+            preempt_after_secs: 0,
+            shards: 1,
+            max_response_chars: 1000,
+            print_depth: 16,
+            print_items: 100,
+            print_shared: false,
+            input_timeout_secs: 120,
+            draft_max_chars: 20_000,
+            draft_timeout_secs: 600,
+            quote_input: QuoteInput::Full,
+            long_output: LongOutput::Spoiler,
+            long_output_lines: 10,
+            result_cache_secs: 30,
+            guild_session_idle_secs: 30 * 60,
+            max_committed_programs: 200,
+            prefixes: vec!["¡cl".into(), "oo".into()],
+            dm: RawDmConfig::default(),
+            pairs: RawPairsConfig::default(),
+            actions: RawActionsConfig::default(),
+            http: RawHttpConfig::default(),
+            sandbox: RawSandboxConfig::default(),
+            logging: RawLoggingConfig::default(),
+            paste: RawPasteConfig::default(),
+            init_path: None,
+            startup_files: Vec::new(),
+            admins: Vec::new(),
+            owner_id: None,
+            preferences_path: "preferences.json".into(),
+            preludes_path: "preludes.json".into(),
+            proposal_approvals: 3,
+            status_reactions: true,
+            webhook_results: false,
+            language: Language::En,
+            languages_path: "languages.json".into(),
+            aliases_path: "aliases.json".into(),
+            features_path: "features.json".into(),
+            features: Vec::new(),
+            libraries: HashMap::new(),
+            guilds: HashMap::new(),
+            tiers: HashMap::new(),
+            user_tiers: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawDmConfig {
+    enabled: bool,
+    rate_limit: usize,
+    rate_limit_window_secs: u64,
+    max_sessions: usize,
+    session_idle_secs: u64,
+}
+
+impl Default for RawDmConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rate_limit: 10,
+            rate_limit_window_secs: 60,
+            max_sessions: 16,
+            session_idle_secs: 30 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawPairsConfig {
+    max_sessions: usize,
+    idle_secs: u64,
+}
+
+impl Default for RawPairsConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions: 8,
+            idle_secs: 15 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawActionsConfig {
+    max_per_evaluation: usize,
+    rate_limit: usize,
+    rate_limit_window_secs: u64,
+}
+
+impl Default for RawActionsConfig {
+    fn default() -> Self {
+        Self {
+            max_per_evaluation: 5,
+            rate_limit: 20,
+            rate_limit_window_secs: 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawHttpConfig {
+    enabled: bool,
+    address: String,
+    token: String,
+    ping_timeout_ms: u64,
+}
+
+impl Default for RawHttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:8080".into(),
+            token: String::new(),
+            ping_timeout_ms: 2000,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawSandboxConfig {
+    enabled: bool,
+    memory_limit_mb: u64,
+}
+
+impl Default for RawSandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            memory_limit_mb: 4096,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawLoggingConfig {
+    level: String,
+    json: bool,
+}
+
+impl Default for RawLoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".into(),
+            json: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawPasteConfig {
+    enabled: bool,
+    upload_url: String,
+    allowed_hosts: Vec<String>,
+    max_fetch_kb: u64,
+    timeout_secs: u64,
+}
+
+impl Default for RawPasteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            upload_url: "https://paste.rs/".into(),
+            allowed_hosts: vec![
+                "pastebin.com".into(),
+                "gist.github.com".into(),
+                "gist.githubusercontent.com".into(),
+                "paste.rs".into(),
+            ],
+            max_fetch_kb: 64,
+            timeout_secs: 10,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RawGuildConfig {
+    prefixes: Option<Vec<String>>,
+    tier: Option<String>,
+    quote_input: Option<QuoteInput>,
+    max_response_chars: Option<usize>,
+    print_depth: Option<usize>,
+    print_items: Option<usize>,
+    print_shared: Option<bool>,
+    log_channel_id: Option<String>,
+    schedule_channel_id: Option<String>,
+    features: Option<Vec<String>>,
+}
+
+/// Evaluation limits; unset values are taken from the top-level ones.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RawTier {
+    eval_timeout_secs: Option<u64>,
+    cpu_fuel_ms: Option<u64>,
+    memory_fuel_mb: Option<u64>,
+}
+
+impl RawTier {
+    fn limits(&self, defaults: &RawTier) -> Limits {
+        let pick = |value: Option<u64>, default: Option<u64>| value.or(default).unwrap_or(0);
+        Limits {
+            timeout: Duration::from_secs(pick(self.eval_timeout_secs, defaults.eval_timeout_secs)),
+            cpu_fuel: Duration::from_millis(pick(self.cpu_fuel_ms, defaults.cpu_fuel_ms)),
+            memory_fuel: pick(self.memory_fuel_mb, defaults.memory_fuel_mb) * 1024 * 1024,
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Hands evaluations to the interpreter threads, from event handlers on any shard or the
+//! HTTP API.
+//!
+//! Each worker thread owns the interpreters of some sessions: guild environments are spread
+//! across them by guild, DM sessions by user, and the admin environment is on the first
+//! worker. Challenge submissions don't need an existing session, so they take turns.
+//!
+//! The workers are a `repl_bot::dispatch::Pool`, which only lets each take so many jobs at
+//! once. Past that, callers are turned away straight away, with an estimate of when to try
+//! again, instead of waiting for the interpreter.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::SyncSender;
+use std::sync::Arc;
+use std::time::Duration;
+
+use repl_bot::dispatch::{Error, Pool};
+use repl_bot::limits::Limits;
+use serenity::model::id::{GuildId, UserId};
+
+use crate::challenge::{Judgement, TestCase};
+use crate::interpreter::{Evaluation, Request};
+use crate::worker::{Committed, Job, SessionKey};
+
+pub struct Dispatcher {
+    pool: Pool<Job>,
+    /// The worker the next challenge submission goes to.
+    next_judge: AtomicUsize,
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher for the workers behind `workers`, of which there must be at least
+    /// one. Each comes with the count of its queued jobs, which the dispatcher keeps.
+    pub fn new(
+        workers: Vec<(SyncSender<Job>, Arc<AtomicUsize>)>,
+        lock_timeout: Duration,
+        queue_length: usize,
+    ) -> Self {
+        Self {
+            pool: Pool::new(workers, lock_timeout, queue_length),
+            next_judge: AtomicUsize::new(0),
+        }
+    }
+
+    /// The worker owning the interpreter of `session`.
+    fn worker_for(&self, session: SessionKey) -> usize {
+        match session {
+            SessionKey::Guild(guild) => (guild.0 % self.pool.worker_count() as u64) as usize,
+            SessionKey::Direct(user) => (user.0 % self.pool.worker_count() as u64) as usize,
+            // Features are set on the guild's worker, so pairs must run there too.
+            SessionKey::Pair(guild, _) => (guild.0 % self.pool.worker_count() as u64) as usize,
+            SessionKey::Admin => 0,
+        }
+    }
+
+    /// How many jobs are being run or waiting for the interpreter.
+    pub fn pending(&self) -> usize {
+        self.pool.pending()
+    }
+
+    /// Whether every interpreter thread was still running at its last job.
+    pub fn is_alive(&self) -> bool {
+        self.pool.is_alive()
+    }
+
+    /// Sends a command to the interpreter thread of `session` and waits for its result.
+    pub fn evaluate(&self, session: SessionKey, request: Request) -> Evaluation {
+        self.pool
+            .send(self.worker_for(session), |response| Job::Evaluate {
+                session,
+                request,
+                response,
+            })
+            .unwrap_or_else(|e| Evaluation::failed(e.into()))
+    }
+
+    /// Runs a challenge submission against tests, in an interpreter of its own.
+    pub fn judge(
+        &self,
+        submission: &str,
+        tests: Vec<TestCase>,
+        limits: Limits,
+    ) -> Result<Judgement, String> {
+        let worker = self.next_judge.fetch_add(1, Ordering::SeqCst) % self.pool.worker_count();
+        self.pool
+            .send(worker, |response| Job::Judge {
+                submission: submission.to_string(),
+                tests,
+                limits,
+                response,
+            })
+            .map_err(String::from)
+    }
+
+    /// Evaluates `code` in an interpreter of its own, which nothing else sees.
+    pub fn evaluate_fresh(&self, code: &str, limits: Limits) -> Evaluation {
+        self.judge(code, Vec::new(), limits)
+            .map(|judgement| judgement.submission)
+            .unwrap_or_else(Evaluation::failed)
+    }
+
+    /// Runs `program` in the environment of `guild` and commits it whole if it succeeds.
+    pub fn install(&self, guild: GuildId, program: Request) -> Evaluation {
+        let worker = self.worker_for(SessionKey::Guild(guild));
+        self.pool
+            .send(worker, |response| Job::Install {
+                guild,
+                request: program,
+                response,
+            })
+            .unwrap_or_else(|e| Evaluation::failed(e.into()))
+    }
+
+    /// Commits the definitions of `user`'s last evaluation in the environment of `guild`.
+    pub fn commit(&self, guild: GuildId, user: UserId) -> Result<Committed, String> {
+        let worker = self.worker_for(SessionKey::Guild(guild));
+        self.pool
+            .send(worker, |response| Job::Commit {
+                guild,
+                user,
+                response,
+            })
+            .map_err(String::from)
+            .and_then(|r| r)
+    }
+
+    /// The source of the last definition of `name` in `session`, if an evaluation defined it.
+    pub fn source_of(&self, session: SessionKey, name: &str) -> Result<Option<String>, String> {
+        self.pool
+            .send(self.worker_for(session), |response| Job::SourceOf {
+                session,
+                name: name.to_string(),
+                response,
+            })
+            .map_err(String::from)
+            .and_then(|r| r)
+    }
+
+    /// The names defined by evaluations in `session`, sorted.
+    pub fn bindings(&self, session: SessionKey) -> Result<Vec<String>, String> {
+        self.pool
+            .send(self.worker_for(session), |response| Job::Bindings {
+                session,
+                response,
+            })
+            .map_err(String::from)
+            .and_then(|r| r)
+    }
+
+    /// The last definition of each name in `session`, as a program that makes them again.
+    pub fn export(&self, session: SessionKey) -> Result<String, String> {
+        self.pool
+            .send(self.worker_for(session), |response| Job::Export {
+                session,
+                response,
+            })
+            .map_err(String::from)
+            .and_then(|r| r)
+    }
+
+    /// Replaces the interpreter of `session` with a fresh one.
+    pub fn reset(&self, session: SessionKey) -> Result<(), String> {
+        self.pool
+            .send(self.worker_for(session), |response| Job::Reset {
+                session,
+                response,
+            })
+            .map_err(String::from)
+            .and_then(|r| r)
+    }
+
+    /// Turns on `features` in the environment of `guild`, which is replaced with a fresh one.
+    pub fn set_features(&self, guild: GuildId, features: Vec<String>) -> Result<(), String> {
+        let worker = self.worker_for(SessionKey::Guild(guild));
+        self.pool
+            .send(worker, |response| Job::SetFeatures {
+                guild,
+                features,
+                response,
+            })
+            .map_err(String::from)
+            .and_then(|r| r)
+    }
+
+    /// How many workers there are.
+    pub fn worker_count(&self) -> usize {
+        self.pool.worker_count()
+    }
+
+    /// How many of the workers have as many jobs queued as they take, and so turn further
+    /// ones away.
+    pub fn saturated_workers(&self) -> usize {
+        self.pool.saturated_workers()
+    }
+
+    /// How long `worker` took to answer a ping, or why it didn't within `deadline`.
+    pub fn ping(&self, worker: usize, deadline: Duration) -> Result<Duration, Error> {
+        self.pool.ping(worker, deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use crate::config::Config;
+    use crate::worker;
+
+    const SESSION: SessionKey = SessionKey::Direct(UserId(1));
+
+    /// A dispatcher with one worker thread running, and a sender of its own to that worker.
+    fn dispatcher() -> (Dispatcher, SyncSender<Job>, Limits) {
+        let config = Arc::new(Config::default());
+        let limits = config.default_limits();
+        let (sender, jobs) = mpsc::sync_channel(0);
+        let queued = Arc::new(AtomicUsize::new(0));
+        let worker_queued = queued.clone();
+        thread::spawn(move || worker::run(jobs, config, worker_queued));
+        let dispatcher =
+            Dispatcher::new(vec![(sender.clone(), queued)], Duration::from_secs(30), 8);
+        (dispatcher, sender, limits)
+    }
+
+    #[test]
+    fn keeps_serving_after_a_caller_stops_waiting() {
+        let (dispatcher, sender, limits) = dispatcher();
+        let (response, receiver) = mpsc::sync_channel(0);
+        let request = Request::new("(define x 42)".into(), limits);
+        sender
+            .send(Job::Evaluate {
+                session: SESSION,
+                request,
+                response,
+            })
+            .unwrap();
+        // The worker took the job, and its reply now has nowhere to go.
+        drop(receiver);
+
+        let evaluation = dispatcher.evaluate(SESSION, Request::new("(+ x 1)".into(), limits));
+        assert!(evaluation.error.is_none(), "{:?}", evaluation.error);
+        assert_eq!(evaluation.values, vec!["43".to_string()]);
+        assert!(dispatcher.is_alive());
+        assert!(dispatcher.ping(0, Duration::from_secs(30)).is_ok());
+        assert!(dispatcher.is_alive());
+    }
+
+    #[test]
+    fn notices_a_worker_that_is_gone() {
+        let (sender, jobs) = mpsc::sync_channel(0);
+        drop(jobs);
+        let dispatcher = Dispatcher::new(
+            vec![(sender, Arc::new(AtomicUsize::new(0)))],
+            Duration::from_secs(30),
+            8,
+        );
+        let limits = Config::default().default_limits();
+        let evaluation = dispatcher.evaluate(SESSION, Request::new("(+ 1 2)".into(), limits));
+        assert!(evaluation.error.is_some());
+        assert!(!dispatcher.is_alive());
+    }
+}
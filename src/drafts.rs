@@ -0,0 +1,99 @@
+//! Programs written over several messages, between `¡begin` and `¡end`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::{ChannelId, UserId};
+
+pub const BEGIN_COMMAND: &str = "¡begin";
+pub const END_COMMAND: &str = "¡end";
+pub const ABORT_COMMAND: &str = "¡abort";
+/// Reaction acknowledging a message added to a draft.
+pub const ADDED_EMOJI: &str = "📝";
+
+/// Drafts are kept for each user in each channel.
+pub type DraftKey = (ChannelId, UserId);
+
+struct Draft {
+    parts: Vec<String>,
+    chars: usize,
+    last_added: Instant,
+}
+
+pub enum Status {
+    Idle,
+    Composing,
+    /// The draft was dropped after going unused for too long.
+    Expired,
+}
+
+/// The drafts being written, each up to `max_chars` characters and dropped after `timeout`
+/// without a new message.
+pub struct Drafts {
+    drafts: HashMap<DraftKey, Draft>,
+    max_chars: usize,
+    timeout: Duration,
+}
+
+impl Drafts {
+    pub fn new(max_chars: usize, timeout: Duration) -> Self {
+        Self {
+            drafts: HashMap::new(),
+            max_chars,
+            timeout,
+        }
+    }
+
+    /// Whether `key` has a draft, dropping every draft that expired.
+    pub fn status(&mut self, key: DraftKey) -> Status {
+        let timeout = self.timeout;
+        let had_draft = self.drafts.contains_key(&key);
+        self.drafts
+            .retain(|_, draft| draft.last_added.elapsed() < timeout);
+        match (had_draft, self.drafts.contains_key(&key)) {
+            (_, true) => Status::Composing,
+            (true, false) => Status::Expired,
+            (false, false) => Status::Idle,
+        }
+    }
+
+    /// Starts an empty draft for `key`, replacing any other.
+    pub fn begin(&mut self, key: DraftKey) {
+        self.drafts.insert(
+            key,
+            Draft {
+                parts: Vec::new(),
+                chars: 0,
+                last_added: Instant::now(),
+            },
+        );
+    }
+
+    /// Adds `part` to the draft of `key`. Returns false, leaving the draft as it was, if
+    /// there is none or that would make it longer than `max_chars`.
+    pub fn add(&mut self, key: DraftKey, part: String) -> bool {
+        let draft = match self.drafts.get_mut(&key) {
+            Some(draft) => draft,
+            None => return false,
+        };
+        // Counting the line break joining it to the previous part.
+        let chars = draft.chars + part.chars().count() + 1;
+        if chars > self.max_chars {
+            return false;
+        }
+        draft.parts.push(part);
+        draft.chars = chars;
+        draft.last_added = Instant::now();
+        true
+    }
+
+    /// Ends the draft of `key`, returning the program it makes.
+    pub fn finish(&mut self, key: DraftKey) -> Option<String> {
+        self.drafts.remove(&key).map(|draft| draft.parts.join("\n"))
+    }
+
+    /// Drops the draft of `key`; returns whether there was one.
+    pub fn abort(&mut self, key: DraftKey) -> bool {
+        self.drafts.remove(&key).is_some()
+    }
+}
@@ -0,0 +1,33 @@
+//! Failures of the bot's own machinery, as opposed to errors in the programs it runs.
+//!
+//! None of these should take a thread down: they are logged where they happen and, when
+//! someone is waiting on the outcome, reported to them. Most of the bot reports errors as
+//! strings, which these convert to. Failures handing jobs to the workers are
+//! `repl_bot::dispatch::Error`s.
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A thread the bot started panicked.
+    ThreadPanicked { thread: &'static str },
+    /// One of the forms the bot runs around every evaluation failed.
+    Hook { form: String, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ThreadPanicked { thread } => write!(f, "the {} thread panicked", thread),
+            Error::Hook { form, message } => {
+                write!(f, "internal error running {}: {}", form, message)
+            }
+        }
+    }
+}
+
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
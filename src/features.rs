@@ -0,0 +1,73 @@
+//! Features chosen by guild admins with `¡features`, in place of the configured ones: the
+//! libraries loaded into the guild's environment, and whether it allows mutation.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+
+use serenity::model::id::GuildId;
+
+/// The features each guild chose, and the file they are saved to.
+pub struct FeatureStore {
+    path: Option<String>,
+    /// Keyed by guild ID, as JSON keys are strings.
+    guilds: HashMap<String, BTreeSet<String>>,
+}
+
+impl FeatureStore {
+    /// Loads the features saved at `path`, if any. Without a path, they are only kept in
+    /// memory.
+    pub fn load(path: Option<String>) -> Result<Self, String> {
+        let guilds = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("error parsing {}: {}", path, e))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(format!("error reading {}: {}", path, e)),
+            },
+            None => HashMap::new(),
+        };
+        Ok(Self { path, guilds })
+    }
+
+    /// The features `guild` chose, if it did.
+    pub fn guild(&self, guild: GuildId) -> Option<&BTreeSet<String>> {
+        self.guilds.get(&guild.to_string())
+    }
+
+    /// Every guild that chose its features, and those features.
+    pub fn guilds(&self) -> Vec<(GuildId, Vec<String>)> {
+        self.guilds
+            .iter()
+            .filter_map(|(id, features)| {
+                Some((
+                    GuildId(id.parse().ok()?),
+                    features.iter().cloned().collect(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Makes `features` those of `guild`, and saves every guild's features.
+    pub fn set(&mut self, guild: GuildId, features: BTreeSet<String>) -> Result<(), String> {
+        self.guilds.insert(guild.to_string(), features);
+        self.save()
+    }
+
+    /// Gives `guild` the configured features again, and saves every guild's features.
+    pub fn remove(&mut self, guild: GuildId) -> Result<(), String> {
+        if self.guilds.remove(&guild.to_string()).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = serde_json::to_string_pretty(&self.guilds).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("error writing {}: {}", path, e))
+    }
+}
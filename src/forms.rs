@@ -0,0 +1,813 @@
+//! Splitting source code into top-level forms, and finding the names they bind.
+//!
+//! This is a light-weight scanner that only knows enough Scheme syntax to find where each
+//! datum begins and ends; the forms themselves are read by peroxide. Doing this ourselves
+//! lets us report which form failed and where syntax errors are.
+
+use std::collections::HashSet;
+use std::mem;
+
+/// Deeper nesting than this is rejected rather than risking the scanner's stack.
+const MAX_DEPTH: usize = 512;
+
+/// A top-level form and its byte offset in the source.
+pub struct Form<'a> {
+    pub source: &'a str,
+    pub start: usize,
+}
+
+impl Form<'_> {
+    /// Whether the form is a `define`, or a `define-` form such as `define-syntax`.
+    pub fn is_definition(&self) -> bool {
+        match self.source.strip_prefix("(define") {
+            Some(rest) => rest.starts_with(|c: char| c.is_whitespace() || c == '-'),
+            None => false,
+        }
+    }
+
+    /// Whether the form is a `begin`, whose definitions are spliced into the enclosing body.
+    pub fn is_begin(&self) -> bool {
+        match self.source.strip_prefix("(begin") {
+            Some(rest) => rest.starts_with(|c: char| c.is_whitespace() || c == ')'),
+            None => false,
+        }
+    }
+
+    /// The name a definition binds, if it binds a single one: `f` in `(define (f x) ...)`.
+    pub fn defined_name(&self) -> Option<&str> {
+        if !self.is_definition() {
+            return None;
+        }
+        let rest = &self.source["(define".len()..];
+        let (keyword, rest) = rest.split_at(rest.find(char::is_whitespace)?);
+        if keyword == "-values" {
+            return None;
+        }
+        let name = rest.trim_start().trim_start_matches('(');
+        let end = name
+            .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .unwrap_or_else(|| name.len());
+        Some(&name[..end]).filter(|name| !name.is_empty())
+    }
+}
+
+/// Procedures that run code they are given, which may bind anything.
+const EVALUATING_NAMES: &[&str] = &["eval", "environment", "interaction-environment", "load"];
+
+/// What a program binds, as far as can be told from its code.
+pub struct Bindings<'a> {
+    /// Names defined anywhere in the program, at top level or not.
+    pub defined: Vec<&'a str>,
+    /// Names assigned with `set!` anywhere in the program.
+    pub assigned: Vec<&'a str>,
+    /// Syntax the program defines whose uses may define or assign names, which then can't
+    /// be told from the code using it.
+    pub binding_syntax: Vec<&'a str>,
+    /// Whether the program may bind names in ways that can't be told from it: by running
+    /// code it is given, with local syntax that may bind, with a `define-` form this
+    /// doesn't know, or with a symbol spelled with escapes.
+    pub opaque: bool,
+    /// The atoms the program evaluates or reads as code, outside quoted data, with their
+    /// byte offsets.
+    code_atoms: Vec<(&'a str, usize)>,
+}
+
+impl<'a> Bindings<'a> {
+    /// Whether the program refers to `name` outside quoted data.
+    pub fn mentions(&self, name: &str) -> bool {
+        self.code_atoms
+            .iter()
+            .any(|(atom, _)| symbol_name(atom) == Some(name))
+    }
+
+    fn code(&mut self, datum: &Datum<'a>) {
+        match datum {
+            Datum::Atom(atom, position) => {
+                match symbol_name(atom) {
+                    Some(name) if EVALUATING_NAMES.contains(&name) => self.opaque = true,
+                    Some(_) => {}
+                    None => self.opaque = true,
+                }
+                self.code_atoms.push((atom, *position));
+            }
+            Datum::List(items) => self.list(items),
+            Datum::Prefixed("`", datum) => self.template(datum, 1),
+            Datum::Prefixed(",", datum) | Datum::Prefixed(",@", datum) => self.code(datum),
+            Datum::Prefixed(_, _) | Datum::Other => {}
+        }
+    }
+
+    fn list(&mut self, items: &[Datum<'a>]) {
+        let keyword = match items.first() {
+            Some(Datum::Atom(atom, _)) => symbol_name(atom),
+            _ => None,
+        };
+        match keyword {
+            Some("quote") => return,
+            Some("quasiquote") => {
+                for item in &items[1..] {
+                    self.template(item, 1);
+                }
+                return;
+            }
+            Some("define") => {
+                let mut target = items.get(1);
+                // The innermost head of a procedure definition, as in (define ((f a) b) ...).
+                while let Some(Datum::List(items)) = target {
+                    target = items.first();
+                }
+                if let Some(Datum::Atom(atom, _)) = target {
+                    self.define(atom);
+                }
+            }
+            Some("define-values") => {
+                if let Some(formals) = items.get(1) {
+                    self.define_all(formals);
+                }
+            }
+            Some("define-record-type") => {
+                // (define-record-type type (constructor field ...) predicate
+                //   (field accessor [modifier]) ...)
+                for part in items.iter().take(4).skip(1) {
+                    match part {
+                        Datum::Atom(atom, _) => self.define(atom),
+                        Datum::List(items) => {
+                            if let Some(Datum::Atom(atom, _)) = items.first() {
+                                self.define(atom);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                for field in items.iter().skip(4) {
+                    if let Datum::List(procedures) = field {
+                        for procedure in procedures.iter().skip(1) {
+                            self.define_all(procedure);
+                        }
+                    }
+                }
+            }
+            Some(keyword @ "define-syntax")
+            | Some(keyword @ "let-syntax")
+            | Some(keyword @ "letrec-syntax") => {
+                let mut atoms = Vec::new();
+                for item in &items[1..] {
+                    flatten(item, &mut atoms);
+                }
+                let binds = atoms.iter().any(|atom| {
+                    symbol_name(atom).map_or(true, |name| {
+                        name == "set!" || name == "define" || name.starts_with("define-")
+                    })
+                });
+                if keyword != "define-syntax" {
+                    self.opaque |= binds;
+                } else if let Some(Datum::Atom(atom, _)) = items.get(1) {
+                    self.define(atom);
+                    if let (true, Some(name)) = (binds, symbol_name(atom)) {
+                        self.binding_syntax.push(name);
+                    }
+                }
+            }
+            Some("set!") => {
+                if let Some(Datum::Atom(atom, _)) = items.get(1) {
+                    match symbol_name(atom) {
+                        Some(name) => self.assigned.push(name),
+                        None => self.opaque = true,
+                    }
+                }
+            }
+            // Bot commands are kept in a table, not bound.
+            Some("define-command") => {}
+            Some(keyword) if keyword.starts_with("define-") => self.opaque = true,
+            _ => {}
+        }
+        for item in items {
+            self.code(item);
+        }
+    }
+
+    /// A quasiquoted `datum`, `level` quasiquotes deep, where only what is unquoted back to
+    /// level 0 is code.
+    fn template(&mut self, datum: &Datum<'a>, level: usize) {
+        match datum {
+            Datum::List(items) => {
+                for item in items {
+                    self.template(item, level);
+                }
+            }
+            Datum::Prefixed("`", datum) => self.template(datum, level + 1),
+            Datum::Prefixed(",", datum) | Datum::Prefixed(",@", datum) => {
+                if level == 1 {
+                    self.code(datum);
+                } else {
+                    self.template(datum, level - 1);
+                }
+            }
+            Datum::Prefixed(_, datum) => self.template(datum, level),
+            Datum::Atom(..) | Datum::Other => {}
+        }
+    }
+
+    fn define(&mut self, atom: &'a str) {
+        match symbol_name(atom) {
+            // Booleans and the dot of dotted formals aren't names.
+            Some(name) if name == "." || atom.starts_with('#') => {}
+            Some(name) => self.defined.push(name),
+            None => self.opaque = true,
+        }
+    }
+
+    fn define_all(&mut self, datum: &Datum<'a>) {
+        let mut atoms = Vec::new();
+        flatten(datum, &mut atoms);
+        for atom in atoms {
+            self.define(atom);
+        }
+    }
+}
+
+/// What `code` binds. It can only be told from code that isn't `opaque`.
+pub fn bindings(code: &str) -> Result<Bindings<'_>, SyntaxError> {
+    let mut bindings = Bindings {
+        defined: Vec::new(),
+        assigned: Vec::new(),
+        binding_syntax: Vec::new(),
+        opaque: false,
+        code_atoms: Vec::new(),
+    };
+    for datum in read(code)? {
+        bindings.code(&datum);
+    }
+    Ok(bindings)
+}
+
+/// `code` with each symbol among `names` it refers to outside quoted data replaced with
+/// what `renamed` gives for the symbol's name.
+pub fn rename(
+    code: &str,
+    names: &HashSet<&str>,
+    renamed: impl Fn(&str) -> String,
+) -> Result<String, SyntaxError> {
+    let bindings = bindings(code)?;
+    let mut result = String::with_capacity(code.len());
+    let mut copied = 0;
+    for (atom, position) in bindings.code_atoms {
+        match symbol_name(atom) {
+            Some(name) if names.contains(name) => {
+                result.push_str(&code[copied..position]);
+                result.push_str(&renamed(name));
+                copied = position + atom.len();
+            }
+            _ => {}
+        }
+    }
+    result.push_str(&code[copied..]);
+    Ok(result)
+}
+
+/// The name of the symbol `atom` spells, or `None` if it's spelled with escapes.
+fn symbol_name(atom: &str) -> Option<&str> {
+    match atom
+        .strip_prefix('|')
+        .and_then(|atom| atom.strip_suffix('|'))
+    {
+        Some(name) if !name.contains(|c| c == '|' || c == '\\') => Some(name),
+        Some(_) => None,
+        None if atom.contains(|c| c == '|' || c == '\\') => None,
+        None => Some(atom),
+    }
+}
+
+/// Every atom in `datum`, whatever its context.
+fn flatten<'a>(datum: &Datum<'a>, atoms: &mut Vec<&'a str>) {
+    match datum {
+        Datum::Atom(atom, _) => atoms.push(atom),
+        Datum::List(items) => {
+            for item in items {
+                flatten(item, atoms);
+            }
+        }
+        Datum::Prefixed(_, datum) => flatten(datum, atoms),
+        Datum::Other => {}
+    }
+}
+
+/// Lines longer than this are shortened around the error position when rendered.
+const MAX_CONTEXT_COLUMNS: usize = 50;
+
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub message: String,
+    /// Byte offset in the source where the problem was detected.
+    pub position: usize,
+    /// Advice on what usually causes this error.
+    pub hint: Option<&'static str>,
+    /// What appending to the source would balance it, when the only problem is lists left
+    /// open at its end.
+    pub closers: Option<String>,
+}
+
+impl SyntaxError {
+    /// Describes the error with its line and column, the offending line of `code` and a
+    /// caret under the error position.
+    pub fn render(&self, code: &str) -> String {
+        let before = &code[..self.position];
+        let line_number = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = code[line_start..].lines().next().unwrap_or("");
+        let column = code[line_start..self.position].chars().count();
+
+        let (line, caret_column) = if column > MAX_CONTEXT_COLUMNS {
+            let skipped = column - MAX_CONTEXT_COLUMNS + 10;
+            let rest = line.chars().skip(skipped).collect::<String>();
+            (format!("…{}", rest), column - skipped + 1)
+        } else {
+            (line.to_string(), column)
+        };
+
+        let mut rendered = format!(
+            "parse error at line {}, column {}: {}\n{}\n{}^",
+            line_number,
+            column + 1,
+            self.message,
+            line,
+            " ".repeat(caret_column)
+        );
+        if let Some(hint) = self.hint {
+            rendered.push_str("\nhint: ");
+            rendered.push_str(hint);
+        }
+        rendered
+    }
+}
+
+pub fn split_forms(code: &str) -> Result<Vec<Form<'_>>, SyntaxError> {
+    let mut scanner = Scanner::new(code);
+    let mut forms = Vec::new();
+    loop {
+        scanner.skip_atmosphere(0)?;
+        if scanner.peek().is_none() {
+            return Ok(forms);
+        }
+        let start = scanner.pos;
+        scanner.datum(0)?;
+        forms.push(Form {
+            source: &code[start..scanner.pos],
+            start,
+        });
+    }
+}
+
+/// The data `code` is made of, as far as the scanner reads them.
+fn read(code: &str) -> Result<Vec<Datum<'_>>, SyntaxError> {
+    let mut scanner = Scanner::new(code);
+    loop {
+        scanner.skip_atmosphere(0)?;
+        if scanner.peek().is_none() {
+            break;
+        }
+        scanner.datum(0)?;
+    }
+    // The lists being built, innermost last, each with the prefixes before it, its
+    // elements so far, and the prefixes before its next element.
+    let mut lists = vec![(Vec::new(), Vec::new(), Vec::new())];
+    for token in scanner.tokens {
+        let (datum, prefixes) = match token {
+            Token::Prefix(prefix) => {
+                lists.last_mut().unwrap().2.push(prefix);
+                continue;
+            }
+            Token::Open => {
+                let prefixes = mem::take(&mut lists.last_mut().unwrap().2);
+                lists.push((prefixes, Vec::new(), Vec::new()));
+                continue;
+            }
+            Token::Close => {
+                let (prefixes, items, _) = lists.pop().unwrap();
+                (Datum::List(items), prefixes)
+            }
+            Token::Atom(atom, position) => (
+                Datum::Atom(atom, position),
+                mem::take(&mut lists.last_mut().unwrap().2),
+            ),
+            Token::Other => (Datum::Other, mem::take(&mut lists.last_mut().unwrap().2)),
+        };
+        let datum = prefixes.into_iter().rev().fold(datum, |datum, prefix| {
+            Datum::Prefixed(prefix, Box::new(datum))
+        });
+        lists.last_mut().unwrap().1.push(datum);
+    }
+    Ok(lists.pop().unwrap().1)
+}
+
+/// The symbols, numbers and other atoms written in `code`, outside strings, characters and
+/// comments, in order. Symbols written between `|` keep their bars.
+pub fn atoms(code: &str) -> Result<Vec<&str>, SyntaxError> {
+    let mut scanner = Scanner::new(code);
+    loop {
+        scanner.skip_atmosphere(0)?;
+        if scanner.peek().is_none() {
+            return Ok(scanner.atoms);
+        }
+        scanner.datum(0)?;
+    }
+}
+
+/// The brackets that would close the lists `code` leaves open, if that is all that keeps it
+/// from being read.
+pub fn missing_closers(code: &str) -> Option<String> {
+    split_forms(code).err()?.closers
+}
+
+/// A datum, as far as the scanner reads it.
+enum Datum<'a> {
+    /// A symbol, number or other atom, with its byte offset.
+    Atom(&'a str, usize),
+    /// A list, with its elements; the dot of a dotted list is an atom.
+    List(Vec<Datum<'a>>),
+    /// A datum after a quote, quasiquote, unquote or `,@`, or after the `#` or `#u8` of a
+    /// vector or bytevector.
+    Prefixed(&'static str, Box<Datum<'a>>),
+    /// A string or character.
+    Other,
+}
+
+/// What the scanner read, in order, for building data from.
+enum Token<'a> {
+    Open,
+    Close,
+    Atom(&'a str, usize),
+    Prefix(&'static str),
+    Other,
+}
+
+struct Scanner<'a> {
+    code: &'a str,
+    pos: usize,
+    /// The closing bracket of each list being read, innermost last.
+    open: Vec<char>,
+    /// The atoms read so far.
+    atoms: Vec<&'a str>,
+    /// What was read so far, leaving out datum comments.
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(code: &'a str) -> Self {
+        Self {
+            code,
+            pos: 0,
+            open: Vec::new(),
+            atoms: Vec::new(),
+            tokens: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.code[self.pos..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.code[self.pos..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error<T>(
+        &self,
+        message: &str,
+        position: usize,
+        hint: Option<&'static str>,
+    ) -> Result<T, SyntaxError> {
+        Err(SyntaxError {
+            message: message.into(),
+            position,
+            hint,
+            closers: None,
+        })
+    }
+
+    /// Skips whitespace and comments, including datum comments, before a datum at `depth`.
+    fn skip_atmosphere(&mut self, depth: usize) -> Result<(), SyntaxError> {
+        // Each `#;` comments out the next datum, so `#; #; a b` comments out both.
+        let mut commented = 0;
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some(';'), _) => {
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                (Some('#'), Some('|')) => self.block_comment()?,
+                (Some('#'), Some(';')) => {
+                    self.bump();
+                    self.bump();
+                    commented += 1;
+                }
+                _ if commented > 0 => {
+                    let read = self.tokens.len();
+                    self.datum(depth + 1)?;
+                    self.tokens.truncate(read);
+                    commented -= 1;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn block_comment(&mut self) -> Result<(), SyntaxError> {
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some('#'), Some('|')) => {
+                    self.bump();
+                    self.bump();
+                    depth += 1;
+                }
+                (Some('|'), Some('#')) => {
+                    self.bump();
+                    self.bump();
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                (Some(_), _) => {
+                    self.bump();
+                }
+                (None, _) => {
+                    return self.error(
+                        "unterminated block comment",
+                        start,
+                        Some("`#|` comments must be closed with `|#`."),
+                    )
+                }
+            }
+        }
+    }
+
+    fn datum(&mut self, depth: usize) -> Result<(), SyntaxError> {
+        if depth > MAX_DEPTH {
+            return self.error("nesting too deep", self.pos, None);
+        }
+        let start = self.pos;
+        // Quote-like prefixes, and the # of vectors and bytevectors.
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some('\''), _) => self.prefix("'"),
+                (Some('`'), _) => self.prefix("`"),
+                (Some(','), Some('@')) => self.prefix(",@"),
+                (Some(','), _) => self.prefix(","),
+                (Some('#'), Some('(')) => self.prefix("#"),
+                (Some('#'), Some('u')) if self.code[self.pos..].starts_with("#u8(") => {
+                    self.prefix("#u8")
+                }
+                _ => break,
+            }
+            self.skip_atmosphere(depth)?;
+        }
+
+        match (self.peek(), self.peek_second()) {
+            (None, _) => self.error(
+                "expected a datum",
+                start,
+                Some("quotes and other prefixes must be followed by an expression."),
+            ),
+            (Some('('), _) | (Some('['), _) => self.list(depth),
+            (Some(c), _) if c == ')' || c == ']' => self.error(
+                &format!("unexpected `{}`", c),
+                self.pos,
+                Some("this closes more parentheses than were opened; remove it, or look for an earlier one that closes too soon."),
+            ),
+            (Some('"'), _) => {
+                self.tokens.push(Token::Other);
+                self.delimited(
+                    '"',
+                    "unterminated string",
+                    "strings end with `\"`; a quote inside a string is written `\\\"`.",
+                )
+            }
+            (Some('|'), _) => {
+                let start = self.pos;
+                self.delimited(
+                    '|',
+                    "unterminated symbol",
+                    "symbols written between `|` must end with another `|`.",
+                )?;
+                self.push_atom(start);
+                Ok(())
+            }
+            (Some('#'), Some('\\')) => {
+                self.bump();
+                self.bump();
+                // The character itself may be a delimiter, as in #\( or #\space.
+                self.bump();
+                self.atom();
+                self.tokens.push(Token::Other);
+                Ok(())
+            }
+            _ => {
+                let start = self.pos;
+                self.atom();
+                self.push_atom(start);
+                Ok(())
+            }
+        }
+    }
+
+    fn list(&mut self, depth: usize) -> Result<(), SyntaxError> {
+        let open_position = self.pos;
+        let close = match self.bump() {
+            Some('[') => ']',
+            _ => ')',
+        };
+        self.open.push(close);
+        self.tokens.push(Token::Open);
+        loop {
+            self.skip_atmosphere(depth)?;
+            match self.peek() {
+                None => {
+                    // Every enclosing list is left open too.
+                    let count = self.open.len();
+                    let last_line = self.code.trim_end().matches('\n').count() + 1;
+                    return Err(SyntaxError {
+                        message: format!(
+                            "missing {} closing paren{} after line {}",
+                            count,
+                            if count == 1 { "" } else { "s" },
+                            last_line
+                        ),
+                        position: open_position,
+                        hint: Some(
+                            "the innermost parenthesis left open is marked; add what is \
+                             missing, or turn on `¡set auto-close` to have it added for you.",
+                        ),
+                        closers: Some(self.open.iter().rev().collect()),
+                    });
+                }
+                Some(c) if c == close => {
+                    self.bump();
+                    self.open.pop();
+                    self.tokens.push(Token::Close);
+                    return Ok(());
+                }
+                Some(c) if c == ')' || c == ']' => {
+                    return self.error(
+                        &format!("expected `{}`, found `{}`", close, c),
+                        self.pos,
+                        Some("a list must be closed with the same kind of bracket that opened it."),
+                    )
+                }
+                Some(_) => self.datum(depth + 1)?,
+            }
+        }
+    }
+
+    /// A string or |symbol|, with backslash escapes.
+    fn delimited(
+        &mut self,
+        delimiter: char,
+        unterminated: &str,
+        hint: &'static str,
+    ) -> Result<(), SyntaxError> {
+        let start = self.pos;
+        self.bump();
+        loop {
+            match self.bump() {
+                Some('\\') => {
+                    self.bump();
+                }
+                Some(c) if c == delimiter => return Ok(()),
+                Some(_) => {}
+                None => return self.error(unterminated, start, Some(hint)),
+            }
+        }
+    }
+
+    fn prefix(&mut self, prefix: &'static str) {
+        self.pos += prefix.len();
+        self.tokens.push(Token::Prefix(prefix));
+    }
+
+    fn push_atom(&mut self, start: usize) {
+        let code = self.code;
+        self.atoms.push(&code[start..self.pos]);
+        self.tokens.push(Token::Atom(&code[start..self.pos], start));
+    }
+
+    fn atom(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "()[]\";".contains(c) {
+                return;
+            }
+            self.bump();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(code: &str) -> Vec<&str> {
+        split_forms(code)
+            .unwrap()
+            .iter()
+            .map(|form| form.source)
+            .collect()
+    }
+
+    #[test]
+    fn skips_datum_comments() {
+        assert_eq!(sources("#; (a b) c"), vec!["c"]);
+        assert_eq!(sources("#; #; a b c"), vec!["c"]);
+        assert_eq!(
+            sources("(a #; #| x |# b c) d"),
+            vec!["(a #; #| x |# b c)", "d"]
+        );
+        assert!(split_forms("(a #;)").is_err());
+        assert!(split_forms("a #;").is_err());
+    }
+
+    #[test]
+    fn finds_what_programs_bind() {
+        let bindings = bindings(
+            "(define (f x) (set! y x)) (define ((g a) b) a) (begin (define-values (p . q) 1)) \
+             (define-record-type point (make-point x) point? (x point-x set-point-x!)) \
+             '(define quoted 1) `(define ,(begin (define unquoted 2)) quasiquoted)",
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.defined,
+            vec![
+                "f",
+                "g",
+                "p",
+                "q",
+                "point",
+                "make-point",
+                "point?",
+                "point-x",
+                "set-point-x!",
+                "unquoted"
+            ]
+        );
+        assert_eq!(bindings.assigned, vec!["y"]);
+        assert!(!bindings.opaque);
+        assert!(bindings.mentions("unquoted"));
+        assert!(!bindings.mentions("quoted"));
+    }
+
+    #[test]
+    fn notices_what_it_cannot_tell() {
+        for code in &[
+            "(eval '(define x 1))",
+            "(define-thing x)",
+            "(define |a\\x41;| 1)",
+            "(let-syntax ((m (syntax-rules () ((_ x) (set! x 1))))) (m y))",
+        ] {
+            assert!(bindings(code).unwrap().opaque, "{}", code);
+        }
+        let macros =
+            bindings("(define-syntax def (syntax-rules () ((_ n) (define n 1))))").unwrap();
+        assert!(!macros.opaque);
+        assert_eq!(macros.binding_syntax, vec!["def"]);
+    }
+
+    #[test]
+    fn renames_symbols_outside_quoted_data() {
+        let names = ["x", "a b"].iter().copied().collect();
+        let renamed = rename("(define x '(x)) (f x |x| `(x ,x) |a b|)", &names, |name| {
+            format!("|new {}|", name)
+        });
+        assert_eq!(
+            renamed.unwrap(),
+            "(define |new x| '(x)) (f |new x| |new x| `(x ,|new x|) |new a b|)"
+        );
+    }
+
+    #[test]
+    fn limits_the_nesting_of_datum_comments() {
+        let nested = "(#;".repeat(666);
+        assert_eq!(
+            split_forms(&nested).err().unwrap().message,
+            "nesting too deep"
+        );
+        assert_eq!(missing_closers(&nested), None);
+        let commented = "#;(".repeat(666);
+        assert_eq!(
+            split_forms(&commented).err().unwrap().message,
+            "nesting too deep"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Whether the bot is working, for `/healthz` and `/readyz`.
+//!
+//! The bot is healthy while every interpreter worker answers a ping in time; a worker that
+//! doesn't is stuck, or busy with an evaluation for longer than that. It is ready when it is
+//! also connected to the gateway on every shard, and no worker's queue is full.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::gateway::ConnectionStage;
+use serenity::prelude::Mutex;
+
+use crate::dispatch::Dispatcher;
+use crate::error::Error;
+
+#[derive(Serialize)]
+pub struct Report {
+    pub healthy: bool,
+    pub ready: bool,
+    pub gateway: Gateway,
+    pub workers: Vec<WorkerReport>,
+    pub queue: Queue,
+}
+
+#[derive(Serialize)]
+pub struct Gateway {
+    pub shards: usize,
+    pub connected: usize,
+}
+
+#[derive(Serialize)]
+pub struct WorkerReport {
+    /// How long the worker took to answer, if it did.
+    pub ping_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Queue {
+    /// Jobs being run or waiting for a worker.
+    pub pending: usize,
+    /// Workers turning further jobs away.
+    pub saturated_workers: usize,
+}
+
+/// Pings every worker at once, each within `deadline`, and looks at the shards and queues.
+pub fn check(
+    dispatcher: &Arc<Dispatcher>,
+    shard_manager: &Arc<Mutex<ShardManager>>,
+    deadline: Duration,
+) -> Report {
+    let pings = (0..dispatcher.worker_count())
+        .map(|worker| {
+            let dispatcher = dispatcher.clone();
+            thread::spawn(move || dispatcher.ping(worker, deadline))
+        })
+        .collect::<Vec<_>>();
+
+    let runners = shard_manager.lock().runners.clone();
+    let gateway = {
+        let runners = runners.lock();
+        Gateway {
+            shards: runners.len(),
+            connected: runners
+                .values()
+                .filter(|runner| runner.stage == ConnectionStage::Connected)
+                .count(),
+        }
+    };
+
+    let workers = pings
+        .into_iter()
+        .map(|ping| match ping.join() {
+            Ok(Ok(elapsed)) => WorkerReport {
+                ping_ms: Some(elapsed.as_secs_f64() * 1000.0),
+                error: None,
+            },
+            Ok(Err(e)) => WorkerReport {
+                ping_ms: None,
+                error: Some(e.to_string()),
+            },
+            Err(_) => WorkerReport {
+                ping_ms: None,
+                error: Some(Error::ThreadPanicked { thread: "ping" }.to_string()),
+            },
+        })
+        .collect::<Vec<_>>();
+    let queue = Queue {
+        pending: dispatcher.pending(),
+        saturated_workers: dispatcher.saturated_workers(),
+    };
+
+    let healthy = dispatcher.is_alive() && workers.iter().all(|worker| worker.error.is_none());
+    let ready = healthy
+        && gateway.shards > 0
+        && gateway.connected == gateway.shards
+        && queue.saturated_workers == 0;
+    Report {
+        healthy,
+        ready,
+        gateway,
+        workers,
+        queue,
+    }
+}
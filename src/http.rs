@@ -0,0 +1,173 @@
+//! Optional HTTP API, evaluating code in the same environment as a guild's channels.
+//!
+//! `POST /eval` with a JSON body `{"guild": "<guild ID>", "code": "..."}` and an
+//! `Authorization: Bearer <token>` header replies with the evaluation as JSON.
+//!
+//! `GET /healthz` and `GET /readyz` need no token. They reply with the state of the gateway,
+//! workers and queues as JSON, with status 200 if the bot is healthy (or ready), and 503
+//! otherwise.
+
+use std::io::Read;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::model::id::GuildId;
+use serenity::prelude::Mutex;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{error, info, info_span};
+
+use crate::config::Config;
+use crate::dispatch::Dispatcher;
+use crate::health;
+use crate::interpreter;
+use crate::worker::SessionKey;
+
+/// Request bodies larger than this are rejected.
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+#[derive(Deserialize)]
+struct EvalRequest {
+    /// The guild whose environment the code runs in.
+    guild: GuildId,
+    code: String,
+}
+
+#[derive(Serialize)]
+struct EvalResponse<'a> {
+    values: &'a [String],
+    error: Option<&'a str>,
+    /// Index of the top-level form that failed, if the error was in one.
+    failed_form: Option<usize>,
+    output: &'a str,
+    elapsed_ms: f64,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a str,
+}
+
+/// Serves the API forever on the configured address.
+pub fn serve(
+    config: Arc<Config>,
+    dispatcher: Arc<Dispatcher>,
+    shard_manager: Arc<Mutex<ShardManager>>,
+) -> Result<(), String> {
+    let server = Server::http(&config.http.address)
+        .map_err(|e| format!("error listening on {}: {}", config.http.address, e))?;
+    info!("HTTP API listening on {}", config.http.address);
+    for mut request in server.incoming_requests() {
+        let url = request.url().to_string();
+        let (status, body) = match url.as_str() {
+            "/healthz" | "/readyz" if *request.method() != Method::Get => {
+                error(405, "method not allowed")
+            }
+            "/healthz" | "/readyz" => {
+                let report = health::check(&dispatcher, &shard_manager, config.http.ping_timeout);
+                let up = if url == "/healthz" {
+                    report.healthy
+                } else {
+                    report.ready
+                };
+                let status = if up { 200 } else { 503 };
+                (status, serde_json::to_string(&report).unwrap())
+            }
+            _ => handle(&config, &dispatcher, &mut request),
+        };
+        let content_type =
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(content_type);
+        if let Err(why) = request.respond(response) {
+            error!("Error sending HTTP response: {:?}", why);
+        }
+    }
+    Ok(())
+}
+
+fn handle(config: &Config, dispatcher: &Dispatcher, request: &mut Request) -> (u16, String) {
+    if request.url() != "/eval" {
+        return error(404, "not found");
+    }
+    if *request.method() != Method::Post {
+        return error(405, "method not allowed");
+    }
+    if !authorized(config, request) {
+        return error(401, "missing or invalid token");
+    }
+
+    let mut body = String::new();
+    if request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_string(&mut body)
+        .is_err()
+    {
+        return error(400, "request body is not valid UTF-8");
+    }
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return error(413, "request body too large");
+    }
+    let eval_request = match serde_json::from_str::<EvalRequest>(&body) {
+        Ok(eval_request) => eval_request,
+        Err(e) => return error(400, &format!("invalid request: {}", e)),
+    };
+
+    let span = info_span!("http");
+    let _entered = span.enter();
+    info!(
+        guild = eval_request.guild.0,
+        command = eval_request.code.as_str(),
+        "evaluating"
+    );
+    let evaluation = dispatcher.evaluate(
+        SessionKey::Guild(eval_request.guild),
+        interpreter::Request {
+            printing: config.printing(Some(eval_request.guild)),
+            ..interpreter::Request::new(eval_request.code.clone(), config.default_limits())
+        },
+    );
+    info!(
+        duration_ms = evaluation.elapsed.as_millis() as u64,
+        outcome = evaluation.outcome(),
+        "evaluated"
+    );
+
+    let response = EvalResponse {
+        values: &evaluation.values,
+        error: evaluation.error.as_ref().map(|e| e.message.as_str()),
+        failed_form: evaluation
+            .error
+            .as_ref()
+            .and_then(|e| e.form.as_ref())
+            .map(|(index, _)| *index),
+        output: &evaluation.output,
+        elapsed_ms: evaluation.elapsed.as_secs_f64() * 1000.0,
+    };
+    (200, serde_json::to_string(&response).unwrap())
+}
+
+fn authorized(config: &Config, request: &Request) -> bool {
+    let expected = format!("Bearer {}", config.http.token);
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .is_some_and(|h| constant_time_eq(h.value.as_str(), &expected))
+}
+
+/// Compares strings without leaking, through timing, how much of a token was right.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn error(status: u16, message: &str) -> (u16, String) {
+    let body = serde_json::to_string(&ErrorResponse { error: message }).unwrap();
+    (status, body)
+}
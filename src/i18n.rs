@@ -0,0 +1,383 @@
+//! The bot's messages in each language it speaks, and the language each guild chose with
+//! `¡lang`.
+//!
+//! A catalog holds every message the bot sends, as it lives in one language. Messages with
+//! `{}` placeholders are completed with `fill`, which takes the arguments in order. Details
+//! coming from the interpreter or from other services, such as Scheme error messages, are
+//! shown as they are.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::ErrorKind;
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+
+mod en;
+mod fr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Language {
+    En,
+    Fr,
+}
+
+impl Language {
+    pub const ALL: &'static [Language] = &[Language::En, Language::Fr];
+
+    /// The language with code `code`, such as `fr`.
+    pub fn parse(code: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|language| language.code() == code)
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Fr => "fr",
+        }
+    }
+
+    pub fn catalog(self) -> &'static Catalog {
+        match self {
+            Language::En => &en::CATALOG,
+            Language::Fr => &fr::CATALOG,
+        }
+    }
+}
+
+/// Replaces the `{}` placeholders of `template` with `args`, in order.
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut pieces = template.split("{}");
+    if let Some(first) = pieces.next() {
+        filled.push_str(first);
+    }
+    for piece in pieces {
+        if let Some(arg) = args.next() {
+            filled.push_str(&arg.to_string());
+        }
+        filled.push_str(piece);
+    }
+    filled
+}
+
+/// Every message the bot sends, in one language.
+pub struct Catalog {
+    /// The name of the language, in that language.
+    pub name: &'static str,
+
+    // Routing.
+    pub reload_denied: &'static str,
+    pub apropos_usage: &'static str,
+    pub schedule_in_servers: &'static str,
+    pub challenges_in_servers: &'static str,
+    pub proposals_in_servers: &'static str,
+    pub lang_in_servers: &'static str,
+    pub full_environment_denied: &'static str,
+    pub nothing_to_evaluate: &'static str,
+    pub owner_only: &'static str,
+    pub broadcast_usage: &'static str,
+    pub dm_rate_limited: &'static str,
+    pub paste_failed: &'static str,
+    pub link_foreign: &'static str,
+    pub replay_fetch_failed: &'static str,
+    pub replay_no_code: &'static str,
+    pub source_links: &'static str,
+
+    // Running programs.
+    pub still_running: &'static str,
+    pub input_prompt: &'static str,
+    pub input_limit: &'static str,
+    pub input_timeout: &'static str,
+    pub actions_skipped: &'static str,
+
+    // Results.
+    pub field_input: &'static str,
+    pub field_result: &'static str,
+    pub field_error: &'static str,
+    pub field_output: &'static str,
+    pub field_more_output: &'static str,
+    pub field_changed: &'static str,
+    pub field_failures: &'static str,
+    pub field_size: &'static str,
+    pub field_image: &'static str,
+    pub field_commands_defined: &'static str,
+    pub field_commands_ignored: &'static str,
+    pub field_replay_errors: &'static str,
+    pub field_full_result: &'static str,
+    pub field_auto_closed: &'static str,
+    pub auto_closed: &'static str,
+    pub tests_summary: &'static str,
+    pub no_checks: &'static str,
+    pub code_size: &'static str,
+    pub more_output_follows: &'static str,
+    pub footer_evaluated: &'static str,
+    pub footer_cached: &'static str,
+    pub upload_failed: &'static str,
+    pub commands_rejected: &'static str,
+    pub result_too_large: &'static str,
+    pub full_result_for: &'static str,
+
+    // Evaluations mirrored to a guild's log channel.
+    pub mirror_title: &'static str,
+    pub field_user: &'static str,
+    pub field_channel: &'static str,
+    pub field_duration: &'static str,
+
+    // Errors and interruptions.
+    pub backtrace: &'static str,
+    pub interrupted: &'static str,
+    pub interrupted_in: &'static str,
+    pub timed_out: &'static str,
+    pub out_of_cpu: &'static str,
+    pub out_of_memory: &'static str,
+    pub preempted: &'static str,
+    pub usage_elapsed: &'static str,
+    pub usage_cpu: &'static str,
+    pub usage_memory: &'static str,
+
+    // Help.
+    pub help_title: &'static str,
+    pub help_description: &'static str,
+    pub help_dm: &'static str,
+    pub help_prefix: &'static str,
+    pub help_prefix_separator: &'static str,
+    pub help_evaluating: &'static str,
+    pub help_limits: &'static str,
+    pub help_define_commands: &'static str,
+    pub help_defined_so_far: &'static str,
+    pub help_reactions: &'static str,
+    pub field_evaluating: &'static str,
+    pub field_limits: &'static str,
+    pub field_commands: &'static str,
+    pub field_reactions: &'static str,
+    /// Descriptions of the commands that don't evaluate code, in the order of
+    /// `META_COMMANDS`.
+    pub commands: [&'static str; 23],
+
+    // Settings.
+    pub settings_shown: &'static str,
+    pub setting_set: &'static str,
+    pub setting_failed: &'static str,
+    pub set_usage: &'static str,
+    pub setting_default: &'static str,
+    pub no_such_setting: &'static str,
+    pub number_range: &'static str,
+    pub quote_input_values: &'static str,
+    pub print_shared_values: &'static str,
+    pub auto_close_values: &'static str,
+    /// Descriptions of the settings, in the order of `preferences::SETTINGS`.
+    pub settings: [&'static str; 7],
+
+    // Languages.
+    pub lang_current: &'static str,
+    pub lang_set: &'static str,
+    pub lang_denied: &'static str,
+    pub lang_unknown: &'static str,
+    pub lang_save_failed: &'static str,
+
+    // Aliases.
+    pub aliases_in_servers: &'static str,
+    pub aliases_none: &'static str,
+    pub aliases_list: &'static str,
+    pub alias_denied: &'static str,
+    pub alias_set: &'static str,
+    pub alias_removed: &'static str,
+    pub no_such_alias: &'static str,
+    pub alias_invalid: &'static str,
+    pub too_many_aliases: &'static str,
+    pub alias_usage: &'static str,
+    pub alias_save_failed: &'static str,
+
+    // Features.
+    pub features_in_servers: &'static str,
+    pub features_list: &'static str,
+    pub features_denied: &'static str,
+    pub feature_unknown: &'static str,
+    pub features_usage: &'static str,
+    pub features_changed: &'static str,
+    pub features_none: &'static str,
+    pub features_failed: &'static str,
+    pub features_save_failed: &'static str,
+
+    // Programs written over several messages.
+    pub draft_started: &'static str,
+    pub draft_already: &'static str,
+    pub draft_too_long: &'static str,
+    pub draft_aborted: &'static str,
+    pub draft_empty: &'static str,
+    pub draft_expired: &'static str,
+    pub not_composing: &'static str,
+
+    // Pair sessions.
+    pub pairs_in_servers: &'static str,
+    pub pair_usage: &'static str,
+    pub pair_current: &'static str,
+    pub pair_self: &'static str,
+    pub pair_bot: &'static str,
+    pub pair_busy: &'static str,
+    pub pairs_full: &'static str,
+    pub pair_started: &'static str,
+    pub handoff_usage: &'static str,
+    pub pair_handed_off: &'static str,
+    pub pair_none: &'static str,
+    pub pair_left: &'static str,
+    pub pair_expired: &'static str,
+
+    // Environments.
+    pub reload_done: &'static str,
+    pub reload_failed: &'static str,
+    pub pages_from_one: &'static str,
+    pub no_name_contains: &'static str,
+    pub nothing_defined: &'static str,
+    pub only_pages: &'static str,
+    pub names_page: &'static str,
+    pub lookup_failed: &'static str,
+    pub source_of_usage: &'static str,
+    pub not_defined: &'static str,
+    pub commit_nothing: &'static str,
+    pub commit_one: &'static str,
+    pub commit_many: &'static str,
+    pub commit_failed: &'static str,
+    pub commit_overwrote: &'static str,
+    pub commit_in_dm: &'static str,
+
+    // Exports and imports.
+    pub export_nothing: &'static str,
+    pub export_done: &'static str,
+    pub export_too_large: &'static str,
+    pub export_failed: &'static str,
+    pub import_denied: &'static str,
+    pub import_usage: &'static str,
+    pub import_no_file: &'static str,
+    pub import_too_large: &'static str,
+    pub import_download_failed: &'static str,
+    pub import_not_text: &'static str,
+    pub import_definitions_only: &'static str,
+    pub import_failed: &'static str,
+    pub import_one: &'static str,
+    pub import_many: &'static str,
+
+    // Schedules.
+    pub nothing_scheduled: &'static str,
+    pub scheduled_entry: &'static str,
+    pub schedule_denied: &'static str,
+    pub schedule_removed: &'static str,
+    pub no_such_schedule: &'static str,
+    pub too_many_scheduled: &'static str,
+    pub scheduled_as: &'static str,
+    pub schedule_usage: &'static str,
+    pub invalid_schedule: &'static str,
+
+    // Proposals.
+    pub propose_definitions_only: &'static str,
+    pub proposal_title: &'static str,
+    pub proposed_by: &'static str,
+    pub field_definitions: &'static str,
+    pub proposal_footer: &'static str,
+    pub proposal_too_long: &'static str,
+    pub proposal_failed: &'static str,
+    pub proposal_added: &'static str,
+    pub proposal_unsaved: &'static str,
+
+    // Versions.
+    pub version_title: &'static str,
+    pub version_build: &'static str,
+    pub version_features_unknown: &'static str,
+    pub version_no_srfis: &'static str,
+    pub field_build: &'static str,
+    pub field_interpreter: &'static str,
+    pub field_features: &'static str,
+    pub field_srfis: &'static str,
+    pub field_uptime: &'static str,
+
+    // Statistics.
+    pub stats_title: &'static str,
+    pub stats_uptime: &'static str,
+    pub stats_counters: &'static str,
+    pub nobody_yet: &'static str,
+    pub field_this_server: &'static str,
+    pub field_overall: &'static str,
+
+    // Challenges.
+    pub challenge_shown: &'static str,
+    pub shortest_so_far: &'static str,
+    pub no_challenge: &'static str,
+    pub nobody_solved: &'static str,
+    pub challenge_add_denied: &'static str,
+    pub challenge_new: &'static str,
+    pub challenge_invalid: &'static str,
+    pub challenge_usage: &'static str,
+    pub challenge_needs_name: &'static str,
+    pub challenge_needs_test: &'static str,
+    pub test_without_expected: &'static str,
+    pub challenge_title: &'static str,
+    pub tests_passed: &'static str,
+    pub solved: &'static str,
+    pub new_shortest: &'static str,
+    pub test_status: &'static str,
+    pub test_passed: &'static str,
+    pub test_wrong: &'static str,
+    pub test_error: &'static str,
+    pub field_submission_error: &'static str,
+    pub field_tests: &'static str,
+
+    // The bot's owner.
+    pub owner_guild: &'static str,
+    pub owner_guilds: &'static str,
+    pub owner_no_guilds: &'static str,
+    pub owner_shutdown: &'static str,
+    pub owner_reloaded: &'static str,
+    pub owner_reload_failed: &'static str,
+    pub owner_broadcast_sent: &'static str,
+
+    // Presence.
+    pub presence_down: &'static str,
+    pub presence_busy: &'static str,
+    pub presence_queue: &'static str,
+}
+
+/// The language each guild chose, and the file they are saved to.
+pub struct LanguageStore {
+    path: Option<String>,
+    /// Keyed by guild ID, as JSON keys are strings.
+    guilds: HashMap<String, Language>,
+}
+
+impl LanguageStore {
+    /// Loads the choices saved at `path`, if any. Without a path, they are only kept in
+    /// memory.
+    pub fn load(path: Option<String>) -> Result<Self, String> {
+        let guilds = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("error parsing {}: {}", path, e))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(format!("error reading {}: {}", path, e)),
+            },
+            None => HashMap::new(),
+        };
+        Ok(Self { path, guilds })
+    }
+
+    pub fn get(&self, guild: GuildId) -> Option<Language> {
+        self.guilds.get(&guild.to_string()).copied()
+    }
+
+    /// Sets the language of `guild`, and saves every guild's.
+    pub fn set(&mut self, guild: GuildId, language: Language) -> Result<(), String> {
+        self.guilds.insert(guild.to_string(), language);
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = serde_json::to_string_pretty(&self.guilds).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("error writing {}: {}", path, e))
+    }
+}
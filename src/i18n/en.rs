@@ -0,0 +1,348 @@
+use super::Catalog;
+
+pub static CATALOG: Catalog = Catalog {
+    name: "English",
+
+    reload_denied: "Only admins can reload.",
+    apropos_usage: "Usage: `¡apropos text [page]`",
+    schedule_in_servers: "Evaluations are scheduled in servers.",
+    challenges_in_servers: "Challenges are run in servers.",
+    proposals_in_servers: "Proposals are made in servers.",
+    lang_in_servers: "Languages are chosen by servers; direct messages use the bot's default.",
+    full_environment_denied: "Only admins can use the full environment.",
+    nothing_to_evaluate: "Nothing to evaluate. To re-run an earlier message, follow the prefix \
+                          with a link to it.",
+    owner_only: "Only the bot's owner can do that.",
+    broadcast_usage: "Usage: `¡broadcast <message>`",
+    dm_rate_limited: "You are evaluating too quickly, try again in {}s.",
+    paste_failed: "Couldn't evaluate the paste: {}",
+    link_foreign: "Only messages from this conversation can be linked to.",
+    replay_fetch_failed: "Couldn't fetch that message: {}",
+    replay_no_code: "That message has no code to re-run.",
+    source_links: "peroxide interpreter: https://github.com/MattX/peroxide\n\
+                   discord bot: https://github.com/MattX/peroxide-discord",
+
+    still_running: "still running… {}s elapsed",
+    input_prompt: "{}, the program is waiting for input: your next message here is its next \
+                   line.",
+    input_limit: "the program read more than {} lines of input",
+    input_timeout: "timed out waiting for input",
+    actions_skipped: "Skipped {} action(s) over the rate limit, try again in {}s.",
+
+    field_input: "Input",
+    field_result: "Result",
+    field_error: "Error",
+    field_output: "Output",
+    field_more_output: "More output",
+    field_changed: "Changed since the last run",
+    field_failures: "Failures",
+    field_size: "Size",
+    field_image: "Image",
+    field_commands_defined: "Commands defined",
+    field_commands_ignored: "Commands ignored",
+    field_replay_errors: "Committed definitions that failed to load",
+    field_full_result: "Full result",
+    field_auto_closed: "Auto-corrected",
+    auto_closed: "added `{}` at the end to close what was left open",
+    tests_summary: "{} passed, {} failed",
+    no_checks: "No checks ran; use `(assert expr)` or `(check-equal? actual expected)`.",
+    code_size: "{} characters, {} bytes",
+    more_output_follows: "Continued in the next message.",
+    footer_evaluated: "evaluated in {}",
+    footer_cached: "cached result of an identical evaluation, which took {}",
+    upload_failed: "upload failed: {}",
+    commands_rejected: "{}\nOnly admins can define commands, in server channels. Names are \
+                        made of letters, digits and dashes, and can't shadow built-in commands.",
+    result_too_large: "The result is too large to show.\n{}",
+    full_result_for: "The whole result, for {}:",
+
+    mirror_title: "Evaluation",
+    field_user: "User",
+    field_channel: "Channel",
+    field_duration: "Duration",
+
+    backtrace: "\nbacktrace:\n  0: form {} of {}, line {}: {}",
+    interrupted: "evaluation interrupted: {}",
+    interrupted_in: "interrupted while in: {} ({}, {} form(s) completed)",
+    timed_out: "timed out after {}s",
+    out_of_cpu: "ran out of CPU fuel ({} ms)",
+    out_of_memory: "memory limit exceeded ({} MiB)",
+    preempted: "stopped after {}s to let other waiting evaluations run",
+    usage_elapsed: "after {}s",
+    usage_cpu: ", {}s of CPU time",
+    usage_memory: ", {} KiB held",
+
+    help_title: "peroxide help",
+    help_description: "Evaluates Scheme code in #{} using the peroxide interpreter.",
+    help_dm: "\nDirect messages to the bot are evaluated without a prefix, in a private session \
+              (at most {} evaluations every {}s).",
+    help_prefix: "`{} <code>`",
+    help_prefix_separator: " or ",
+    help_evaluating: "{}, or mention {} followed by the code.\n\
+                      The code may be wrapped in a fenced code block or in inline code \
+                      backticks.\n\
+                      Every top-level form is evaluated in turn, and each value is shown.\n\
+                      Programs can post with `(bot-say \"text\")`, react to your message with \
+                      `(bot-react \"emoji\")` and get your name with `(message-author)`.\n\
+                      `(read-line)` and `(read)` ask you for a line of input, which you send as \
+                      a message.\n\
+                      Results built with `(image width height shape ...)` are drawn; shapes are \
+                      `line`, `rect`, `circle` and `polyline`, and `(plot f from to)` graphs a \
+                      function.\n\
+                      Files, processes and the environment aren't accessible; admins can use \
+                      them with `<prefix>! <code>`, which runs in a separate environment.",
+    help_limits: "Your evaluations are interrupted after {}s, {} ms of CPU time, or {} MiB of \
+                  memory.\n\
+                  Each section of a reply is truncated to {} characters.",
+    help_define_commands: "\nAdmins can define more commands with \
+                           `(define-command \"name\" (lambda (args) ...))`.",
+    help_defined_so_far: "\nDefined so far: {}",
+    help_reactions: "{} rerun the expression\n{} show its full source\n{} send the whole of a \
+                     result that was cut, to whoever asked for it",
+    field_evaluating: "Evaluating",
+    field_limits: "Limits",
+    field_commands: "Commands",
+    field_reactions: "Reactions on results",
+    commands: [
+        "show this message",
+        "links to the source code of the interpreter and bot",
+        "the commit the bot was built from, the interpreter's version and the Scheme features \
+         it supports",
+        "usage statistics for this server and overall",
+        "reloads the standard library in a fresh environment (admins only, except in DMs)",
+        "shows the last definition of a name evaluated in this environment; in the shared \
+         environment, only committed definitions count",
+        "lists the names evaluations defined in this environment since the last reload; \
+         `¡bindings 2` shows the second page",
+        "like `¡bindings`, for the names containing some text: `¡apropos text [page]`",
+        "`¡schedule \"0 9 * * *\" <code>` evaluates code on a cron schedule, in UTC (admins \
+         only); `¡schedule list` shows this server's schedules and `¡schedule remove <id>` \
+         removes one",
+        "shows or changes your settings, like `¡set print-length 200`, `¡set quote-input off` \
+         or `¡set timeout 2`",
+        "shows the language the bot speaks in this server; admins change it with \
+         `¡lang <code>`, like `¡lang fr`",
+        "lists this server's aliases; admins define one with `¡alias <name> <text>`, after \
+         which messages starting with `¡<name>` read as if they started with the text, and \
+         remove it with `¡alias remove <name>`",
+        "lists the features this server can turn on, such as extra libraries or a teaching \
+         mode without mutation; admins switch one with `¡features on <name>` or \
+         `¡features off <name>`, which rebuilds the server's environment",
+        "keeps the definitions of your last evaluation in this server's environment, where \
+         everything else an evaluation changes is undone afterwards",
+        "sends the last definition of each name evaluated in this environment as a file; in \
+         the shared environment, only committed definitions count",
+        "`¡import` with a `.scm` file attached, or followed by a link to a message with one, \
+         loads its definitions into this environment (admins only, except in DMs)",
+        "`¡propose <definitions>` puts definitions to a vote; once enough people approve with \
+         a reaction, they are added to this server's prelude, kept across reloads",
+        "shows this server's challenge; `¡challenge leaderboard` shows who solved the most, \
+         and admins post new ones with `¡challenge add`",
+        "starts a program written over several messages: your next messages here are added \
+         to it, until `¡end` evaluates it or `¡abort` discards it",
+        "`¡pair @user` starts a session where the two of you share an environment of your own \
+         in this channel, as for mentoring; `¡handoff @user` gives your place to someone else \
+         and `¡leave` ends it",
+        "evaluates code like the prefixes do, and shows its size in characters and bytes",
+        "evaluates code and summarizes its `(assert expr)` and `(check-equal? actual expected)` \
+         checks",
+        "runs your solution to the challenge against its hidden tests",
+    ],
+
+    settings_shown: "Your settings:\n{}\nChange one with `¡set <name> <value>`, or \
+                     `¡set <name> default` to reset it. Settings:\n{}",
+    setting_set: "Set {} to {}.",
+    setting_failed: "Couldn't set {}: {}",
+    set_usage: "Usage: `¡set <name> <value>`",
+    setting_default: "default",
+    no_such_setting: "there is no setting called {}",
+    number_range: "expected a number from 1 to {}",
+    quote_input_values: "quote-input is `off`, `first-line` or `full`",
+    print_shared_values: "print-shared is `on` or `off`",
+    auto_close_values: "auto-close is `on` or `off`",
+    settings: [
+        "characters shown in each section of a reply",
+        "`off`, `first-line` or `full`: how much of your code replies quote",
+        "how deeply nested lists and vectors are shown",
+        "how many elements of a list or vector are shown",
+        "`on` or `off`: whether structure appearing several times is labelled",
+        "seconds your evaluations may run",
+        "`on` or `off`: whether code leaving parentheses open is closed and evaluated anyway",
+    ],
+
+    lang_current: "The bot speaks {} in this server. Admins can change it with `¡lang <code>`; \
+                   languages: {}.",
+    lang_set: "The bot now speaks {} in this server.",
+    lang_denied: "Only admins can change the language.",
+    lang_unknown: "There is no language `{}`; languages: {}.",
+    lang_save_failed: "Couldn't save the language: {}",
+
+    aliases_in_servers: "Aliases are defined in servers.",
+    aliases_none: "No aliases are defined here.",
+    aliases_list: "Aliases:\n{}",
+    alias_denied: "Only admins can define aliases.",
+    alias_set: "`¡{}` now stands for `{}`.",
+    alias_removed: "Removed the alias `¡{}`.",
+    no_such_alias: "There is no alias `¡{}`.",
+    alias_invalid: "Alias names are made of letters, digits and dashes, and can't shadow \
+                    built-in commands or prefixes.",
+    too_many_aliases: "At most {} aliases can be defined; remove one first.",
+    alias_usage: "Usage: `¡alias <name> <text>` or `¡alias remove <name>`.",
+    alias_save_failed: "Couldn't save the aliases: {}",
+
+    features_in_servers: "Features are chosen by servers.",
+    features_list: "Features, with those on here in bold: {}\nAdmins turn one on or off with \
+                    `¡features on <name>` or `¡features off <name>`, and go back to the \
+                    defaults with `¡features reset`.",
+    features_denied: "Only admins can change the features.",
+    feature_unknown: "There is no feature `{}`; features: {}.",
+    features_usage: "Usage: `¡features on <name>`, `¡features off <name>` or \
+                     `¡features reset`.",
+    features_changed: "Features on here: {}. The server's environment was rebuilt with them, \
+                       so only its prelude is left of its definitions.",
+    features_none: "none",
+    features_failed: "Couldn't build an environment with these features, keeping the \
+                      current one:\n{}",
+    features_save_failed: "The features are on, but couldn't be saved: {}",
+
+    draft_started: "Writing a program: your next messages here are added to it, up to {} \
+                    characters. Send `¡end` to evaluate it or `¡abort` to discard it; it is \
+                    discarded after {}s without a message.",
+    draft_already: "You are already writing a program; send `¡end` to evaluate it or `¡abort` to \
+                    discard it.",
+    draft_too_long: "That message would make the program longer than {} characters, so it \
+                     wasn't added. Send `¡end` to evaluate what you have, or `¡abort` to \
+                     discard it.",
+    draft_aborted: "Discarded your program.",
+    draft_empty: "Your program is empty, there is nothing to evaluate.",
+    draft_expired: "Your program was discarded after {}s without a message.",
+    not_composing: "You aren't writing a program; start one with `¡begin`.",
+
+    pairs_in_servers: "Pair sessions run in servers.",
+    pair_usage: "Usage: `¡pair @user` starts a pair session with someone here.",
+    pair_current: "{} and {} are in a pair session here.",
+    pair_self: "You can't pair with yourself.",
+    pair_bot: "Pair sessions are for people, not bots.",
+    pair_busy: "{} is already in a pair session here.",
+    pairs_full: "There are as many pair sessions as the bot runs at once; try again later.",
+    pair_started: "{} and {} now share an environment: your evaluations in this channel run in \
+                   it instead of the server's. `¡handoff @user` gives your place to someone \
+                   else and `¡leave` ends the session, as do {} minutes without a message \
+                   from either of you.",
+    handoff_usage: "Usage: `¡handoff @user` gives your place in your pair session to them.",
+    pair_handed_off: "{} gave their place in the pair session to {}, who now shares its \
+                      environment with {}.",
+    pair_none: "You aren't in a pair session here.",
+    pair_left: "{} left, ending the pair session with {}; its environment is gone.",
+    pair_expired: "The pair session of {} and {} ended after a while without messages; its \
+                   environment is gone.",
+
+    reload_done: "Reloaded the standard library in a fresh environment.",
+    reload_failed: "Reload failed, keeping the current environment:\n{}",
+    pages_from_one: "Pages are numbered from 1.",
+    no_name_contains: "No name defined here contains `{}`.",
+    nothing_defined: "Nothing was defined here since the last reload.",
+    only_pages: "There are only {} pages.",
+    names_page: "{} names (page {} of {}):\n{}",
+    lookup_failed: "Lookup failed: {}",
+    source_of_usage: "Usage: `¡source-of <name>`",
+    not_defined: "No evaluation in this environment defined `{}`; it may come from the \
+                  standard library, or it was never committed.",
+    commit_nothing: "Your last evaluation didn't define anything.",
+    commit_one: "Committed 1 definition.",
+    commit_many: "Committed {} definitions.",
+    commit_failed: "Commit failed: {}",
+    commit_overwrote: "⚠️ Since your evaluation, someone else committed {}; your definitions \
+                       replaced theirs.",
+    commit_in_dm: "DM sessions keep everything they define, there is nothing to commit.",
+
+    export_nothing: "Nothing was defined here since the last reload.",
+    export_done: "The definitions of this environment; load them with `¡import`.",
+    export_too_large: "The definitions of this environment are too large to send.",
+    export_failed: "Export failed: {}",
+    import_denied: "Only admins can import definitions into this server's environment.",
+    import_usage: "Usage: `¡import` with a `.scm` file attached, or `¡import <message link>`",
+    import_no_file: "There is no `.scm` file to import.",
+    import_too_large: "Files to import may be at most {} KiB.",
+    import_download_failed: "Couldn't download the file: {}",
+    import_not_text: "The file to import isn't UTF-8 text.",
+    import_definitions_only: "Only files made of definitions can be imported.",
+    import_failed: "Import failed:\n{}",
+    import_one: "Imported 1 definition.",
+    import_many: "Imported {} definitions.",
+
+    nothing_scheduled: "Nothing is scheduled.",
+    scheduled_entry: "**#{}** `{}` by {}, posting in {}: {}",
+    schedule_denied: "Only admins can schedule evaluations.",
+    schedule_removed: "Removed schedule #{}.",
+    no_such_schedule: "There is no schedule #{}.",
+    too_many_scheduled: "A server can have at most {} evaluations scheduled; remove one first.",
+    scheduled_as: "Scheduled as #{}.",
+    schedule_usage: "Usage: `¡schedule \"<minute> <hour> <day> <month> <weekday>\" <code>`",
+    invalid_schedule: "Invalid schedule: {}",
+
+    propose_definitions_only: "Only definitions can be proposed: `¡propose (define (f x) ...)`.",
+    proposal_title: "Proposed addition to the prelude",
+    proposed_by: "Proposed by {}",
+    field_definitions: "Definitions",
+    proposal_footer: "React with {} or {}; {} more approvals than rejections add it.",
+    proposal_too_long: "{} proposed definitions too long to show; react with {} or {} to vote.",
+    proposal_failed: "The proposal by {} was approved, but failed:\n{}",
+    proposal_added: "The proposal by {} was approved and added to the prelude.",
+    proposal_unsaved: "The proposal by {} was approved, but couldn't be saved; it will be lost \
+                       on reload.",
+
+    version_title: "peroxide version",
+    version_build: "commit `{}`, built {}",
+    version_features_unknown: "The interpreter doesn't report its features.",
+    version_no_srfis: "none reported",
+    field_build: "Bot",
+    field_interpreter: "Interpreter",
+    field_features: "Features",
+    field_srfis: "SRFIs",
+    field_uptime: "Uptime",
+
+    stats_title: "peroxide stats",
+    stats_uptime: "Up for {}.",
+    stats_counters: "{} evaluations\n{}% errors\n{} on average\nBusiest: {}",
+    nobody_yet: "nobody yet",
+    field_this_server: "This server",
+    field_overall: "Overall",
+
+    challenge_shown: "**{}**\n{}\n{} hidden tests. Submit a solution with `¡submit <code>`.",
+    shortest_so_far: "\nShortest solution so far: {} characters, by {}.",
+    no_challenge: "There is no challenge right now.",
+    nobody_solved: "Nobody has solved a challenge yet.",
+    challenge_add_denied: "Only admins can add challenges.",
+    challenge_new: "New challenge: **{}**\n{}\n{} hidden tests. Submit a solution with \
+                    `¡submit <code>`.",
+    challenge_invalid: "Invalid challenge: {}\nPut the name on the first line, then the \
+                        description, then tests on lines like `test (solution 1 2) => 3`.",
+    challenge_usage: "Usage: `¡challenge`, `¡challenge leaderboard` or `¡challenge add`.",
+    challenge_needs_name: "a challenge needs a name",
+    challenge_needs_test: "a challenge needs at least one test",
+    test_without_expected: "test without an expected value: {}",
+    challenge_title: "Challenge: {}",
+    tests_passed: "{}/{} tests passed.",
+    solved: " Solved, welcome to the leaderboard!",
+    new_shortest: " New shortest solution: {} characters!",
+    test_status: "Test {}: {}",
+    test_passed: "passed",
+    test_wrong: "wrong answer",
+    test_error: "error",
+    field_submission_error: "Error in submission",
+    field_tests: "Tests",
+
+    owner_guild: "{} ({}), {} members",
+    owner_guilds: "In {} server(s):\n{}",
+    owner_no_guilds: "Not in any server.",
+    owner_shutdown: "Shutting down.",
+    owner_reloaded: "Reloaded the configuration. Workers, shards, the sandbox, the HTTP API and \
+                     rate limits keep their settings until the bot restarts.",
+    owner_reload_failed: "Error reloading the configuration, keeping the current one: {}",
+    owner_broadcast_sent: "Sent to {} of {} channel(s).",
+
+    presence_down: "interpreter down",
+    presence_busy: "evaluating…",
+    presence_queue: "queue: {}",
+};
@@ -0,0 +1,378 @@
+use super::Catalog;
+
+pub static CATALOG: Catalog = Catalog {
+    name: "français",
+
+    reload_denied: "Seuls les admins peuvent recharger l'environnement.",
+    apropos_usage: "Utilisation : `¡apropos texte [page]`",
+    schedule_in_servers: "Les évaluations se programment dans les serveurs.",
+    challenges_in_servers: "Les défis ont lieu dans les serveurs.",
+    proposals_in_servers: "Les propositions se font dans les serveurs.",
+    lang_in_servers: "La langue se choisit par serveur ; les messages privés utilisent celle du \
+                      bot par défaut.",
+    full_environment_denied: "Seuls les admins peuvent utiliser l'environnement complet.",
+    nothing_to_evaluate: "Rien à évaluer. Pour relancer un message précédent, faites suivre le \
+                          préfixe d'un lien vers ce message.",
+    owner_only: "Seul le propriétaire du bot peut faire ça.",
+    broadcast_usage: "Utilisation : `¡broadcast <message>`",
+    dm_rate_limited: "Vous évaluez trop vite, réessayez dans {} s.",
+    paste_failed: "Impossible d'évaluer le paste : {}",
+    link_foreign: "Seuls les messages de cette conversation peuvent être cités.",
+    replay_fetch_failed: "Impossible de récupérer ce message : {}",
+    replay_no_code: "Ce message ne contient pas de code à relancer.",
+    source_links: "interpréteur peroxide : https://github.com/MattX/peroxide\n\
+                   bot discord : https://github.com/MattX/peroxide-discord",
+
+    still_running: "toujours en cours… {} s écoulées",
+    input_prompt: "{}, le programme attend une entrée : votre prochain message ici en sera la \
+                   ligne suivante.",
+    input_limit: "le programme a lu plus de {} lignes d'entrée",
+    input_timeout: "délai dépassé en attendant une entrée",
+    actions_skipped: "{} action(s) ignorée(s) au-delà de la limite, réessayez dans {} s.",
+
+    field_input: "Entrée",
+    field_result: "Résultat",
+    field_error: "Erreur",
+    field_output: "Sortie",
+    field_more_output: "Suite de la sortie",
+    field_changed: "Changements depuis la dernière exécution",
+    field_failures: "Échecs",
+    field_size: "Taille",
+    field_image: "Image",
+    field_commands_defined: "Commandes définies",
+    field_commands_ignored: "Commandes ignorées",
+    field_replay_errors: "Définitions conservées qui n'ont pas pu être chargées",
+    field_full_result: "Résultat complet",
+    field_auto_closed: "Corrigé automatiquement",
+    auto_closed: "`{}` ajouté à la fin pour fermer ce qui restait ouvert",
+    tests_summary: "{} réussi(s), {} échoué(s)",
+    no_checks: "Aucune vérification n'a eu lieu ; utilisez `(assert expr)` ou \
+                `(check-equal? actual expected)`.",
+    code_size: "{} caractères, {} octets",
+    more_output_follows: "Suite dans le message suivant.",
+    footer_evaluated: "évalué en {}",
+    footer_cached: "résultat en cache d'une évaluation identique, qui a pris {}",
+    upload_failed: "échec de l'envoi : {}",
+    commands_rejected: "{}\nSeuls les admins peuvent définir des commandes, dans les salons \
+                        des serveurs. Les noms sont faits de lettres, de chiffres et de tirets, \
+                        et ne peuvent pas masquer les commandes intégrées.",
+    result_too_large: "Le résultat est trop grand pour être affiché.\n{}",
+    full_result_for: "Le résultat complet, pour {} :",
+
+    mirror_title: "Évaluation",
+    field_user: "Utilisateur",
+    field_channel: "Salon",
+    field_duration: "Durée",
+
+    backtrace: "\ntrace :\n  0 : forme {} sur {}, ligne {} : {}",
+    interrupted: "évaluation interrompue : {}",
+    interrupted_in: "interrompue pendant : {} ({}, {} forme(s) terminée(s))",
+    timed_out: "délai dépassé après {} s",
+    out_of_cpu: "temps CPU épuisé ({} ms)",
+    out_of_memory: "limite de mémoire dépassée ({} Mio)",
+    preempted: "arrêtée après {} s pour laisser passer d'autres évaluations en attente",
+    usage_elapsed: "après {} s",
+    usage_cpu: ", {} s de temps CPU",
+    usage_memory: ", {} Kio occupés",
+
+    help_title: "Aide de peroxide",
+    help_description: "Évalue du code Scheme dans #{} avec l'interpréteur peroxide.",
+    help_dm: "\nLes messages privés au bot sont évalués sans préfixe, dans une session privée \
+              (au plus {} évaluations toutes les {} s).",
+    help_prefix: "`{} <code>`",
+    help_prefix_separator: " ou ",
+    help_evaluating: "{}, ou mentionnez {} suivi du code.\n\
+                      Le code peut être placé dans un bloc de code ou entre accents graves.\n\
+                      Chaque forme de premier niveau est évaluée à son tour, et chaque valeur \
+                      est affichée.\n\
+                      Les programmes peuvent poster avec `(bot-say \"texte\")`, réagir à votre \
+                      message avec `(bot-react \"emoji\")` et obtenir votre nom avec \
+                      `(message-author)`.\n\
+                      `(read-line)` et `(read)` vous demandent une ligne d'entrée, que vous \
+                      envoyez en message.\n\
+                      Les résultats construits avec `(image width height shape ...)` sont \
+                      dessinés ; les formes sont `line`, `rect`, `circle` et `polyline`, et \
+                      `(plot f from to)` trace une fonction.\n\
+                      Les fichiers, les processus et l'environnement ne sont pas accessibles ; \
+                      les admins peuvent s'en servir avec `<préfixe>! <code>`, qui s'exécute \
+                      dans un environnement séparé.",
+    help_limits: "Vos évaluations sont interrompues après {} s, {} ms de temps CPU, ou {} Mio \
+                  de mémoire.\n\
+                  Chaque partie d'une réponse est tronquée à {} caractères.",
+    help_define_commands: "\nLes admins peuvent définir d'autres commandes avec \
+                           `(define-command \"nom\" (lambda (args) ...))`.",
+    help_defined_so_far: "\nDéfinies jusqu'ici : {}",
+    help_reactions: "{} relance l'expression\n{} affiche son code complet\n{} envoie en entier \
+                     un résultat coupé, à qui l'a demandé",
+    field_evaluating: "Évaluer",
+    field_limits: "Limites",
+    field_commands: "Commandes",
+    field_reactions: "Réactions sur les résultats",
+    commands: [
+        "affiche ce message",
+        "liens vers le code source de l'interpréteur et du bot",
+        "le commit à partir duquel le bot a été compilé, la version de l'interpréteur et les \
+         fonctionnalités de Scheme qu'il prend en charge",
+        "statistiques d'utilisation pour ce serveur et en tout",
+        "recharge la bibliothèque standard dans un environnement neuf (admins seulement, sauf \
+         en message privé)",
+        "affiche la dernière définition d'un nom évaluée dans cet environnement ; dans \
+         l'environnement partagé, seules les définitions conservées comptent",
+        "liste les noms définis par des évaluations dans cet environnement depuis le dernier \
+         rechargement ; `¡bindings 2` affiche la deuxième page",
+        "comme `¡bindings`, pour les noms contenant un texte : `¡apropos texte [page]`",
+        "`¡schedule \"0 9 * * *\" <code>` évalue du code selon un calendrier cron, en UTC \
+         (admins seulement) ; `¡schedule list` affiche les programmations de ce serveur et \
+         `¡schedule remove <id>` en supprime une",
+        "affiche ou modifie vos réglages, comme `¡set print-length 200`, \
+         `¡set quote-input off` ou `¡set timeout 2`",
+        "affiche la langue du bot dans ce serveur ; les admins la changent avec \
+         `¡lang <code>`, comme `¡lang en`",
+        "liste les alias de ce serveur ; les admins en définissent un avec \
+         `¡alias <nom> <texte>`, après quoi les messages commençant par `¡<nom>` se lisent \
+         comme s'ils commençaient par le texte, et le retirent avec `¡alias remove <nom>`",
+        "liste les fonctionnalités que ce serveur peut activer, comme des bibliothèques \
+         supplémentaires ou un mode d'enseignement sans mutation ; les admins en basculent une \
+         avec `¡features on <nom>` ou `¡features off <nom>`, ce qui reconstruit \
+         l'environnement du serveur",
+        "conserve les définitions de votre dernière évaluation dans l'environnement de ce \
+         serveur, où tout ce qu'une évaluation modifie d'autre est annulé ensuite",
+        "envoie dans un fichier la dernière définition de chaque nom évaluée dans cet \
+         environnement ; dans l'environnement partagé, seules les définitions conservées \
+         comptent",
+        "`¡import` avec un fichier `.scm` joint, ou suivi d'un lien vers un message qui en a \
+         un, charge ses définitions dans cet environnement (admins seulement, sauf en message \
+         privé)",
+        "`¡propose <définitions>` soumet des définitions au vote ; une fois approuvées par \
+         assez de réactions, elles sont ajoutées au prélude de ce serveur, conservé entre les \
+         rechargements",
+        "affiche le défi de ce serveur ; `¡challenge leaderboard` montre qui en a résolu le \
+         plus, et les admins en publient de nouveaux avec `¡challenge add`",
+        "commence un programme écrit en plusieurs messages : vos prochains messages ici y sont \
+         ajoutés, jusqu'à ce que `¡end` l'évalue ou que `¡abort` l'abandonne",
+        "`¡pair @utilisateur` ouvre une session où vous partagez à deux un environnement à vous \
+         dans ce salon, comme pour du mentorat ; `¡handoff @utilisateur` donne votre place à \
+         quelqu'un d'autre et `¡leave` y met fin",
+        "évalue du code comme les préfixes, et affiche sa taille en caractères et en octets",
+        "évalue du code et résume ses vérifications `(assert expr)` et \
+         `(check-equal? actual expected)`",
+        "teste votre solution au défi avec ses tests cachés",
+    ],
+
+    settings_shown: "Vos réglages :\n{}\nModifiez-en un avec `¡set <nom> <valeur>`, ou \
+                     remettez-le par défaut avec `¡set <nom> default`. Réglages :\n{}",
+    setting_set: "{} vaut maintenant {}.",
+    setting_failed: "Impossible de régler {} : {}",
+    set_usage: "Utilisation : `¡set <nom> <valeur>`",
+    setting_default: "par défaut",
+    no_such_setting: "il n'y a pas de réglage {}",
+    number_range: "un nombre de 1 à {} est attendu",
+    quote_input_values: "quote-input vaut `off`, `first-line` ou `full`",
+    print_shared_values: "print-shared vaut `on` ou `off`",
+    auto_close_values: "auto-close vaut `on` ou `off`",
+    settings: [
+        "caractères affichés dans chaque partie d'une réponse",
+        "`off`, `first-line` ou `full` : quelle part de votre code les réponses citent",
+        "profondeur jusqu'à laquelle les listes et vecteurs imbriqués sont affichés",
+        "nombre d'éléments d'une liste ou d'un vecteur affichés",
+        "`on` ou `off` : si une structure qui apparaît plusieurs fois est étiquetée",
+        "secondes pendant lesquelles vos évaluations peuvent tourner",
+        "`on` ou `off` : si le code qui laisse des parenthèses ouvertes est fermé et évalué \
+         quand même",
+    ],
+
+    lang_current: "Le bot parle {} dans ce serveur. Les admins peuvent changer avec \
+                   `¡lang <code>` ; langues : {}.",
+    lang_set: "Le bot parle maintenant {} dans ce serveur.",
+    lang_denied: "Seuls les admins peuvent changer la langue.",
+    lang_unknown: "Il n'y a pas de langue `{}` ; langues : {}.",
+    lang_save_failed: "Impossible d'enregistrer la langue : {}",
+
+    aliases_in_servers: "Les alias se définissent dans les serveurs.",
+    aliases_none: "Aucun alias n'est défini ici.",
+    aliases_list: "Alias :\n{}",
+    alias_denied: "Seuls les admins peuvent définir des alias.",
+    alias_set: "`¡{}` remplace désormais `{}`.",
+    alias_removed: "L'alias `¡{}` a été retiré.",
+    no_such_alias: "Il n'y a pas d'alias `¡{}`.",
+    alias_invalid: "Les noms d'alias sont faits de lettres, de chiffres et de tirets, et ne \
+                    peuvent pas masquer les commandes intégrées ni les préfixes.",
+    too_many_aliases: "Au plus {} alias peuvent être définis ; retirez-en un d'abord.",
+    alias_usage: "Utilisation : `¡alias <nom> <texte>` ou `¡alias remove <nom>`.",
+    alias_save_failed: "Impossible d'enregistrer les alias : {}",
+
+    features_in_servers: "Les fonctionnalités se choisissent dans les serveurs.",
+    features_list: "Fonctionnalités, avec celles activées ici en gras : {}\nLes admins en \
+                    activent ou désactivent une avec `¡features on <nom>` ou \
+                    `¡features off <nom>`, et reviennent à celles par défaut avec \
+                    `¡features reset`.",
+    features_denied: "Seuls les admins peuvent changer les fonctionnalités.",
+    feature_unknown: "Il n'y a pas de fonctionnalité `{}` ; fonctionnalités : {}.",
+    features_usage: "Utilisation : `¡features on <nom>`, `¡features off <nom>` ou \
+                     `¡features reset`.",
+    features_changed: "Fonctionnalités activées ici : {}. L'environnement du serveur a été \
+                       reconstruit avec elles, il ne reste donc de ses définitions que son \
+                       prélude.",
+    features_none: "aucune",
+    features_failed: "Impossible de construire un environnement avec ces fonctionnalités, \
+                      l'environnement actuel est conservé :\n{}",
+    features_save_failed: "Les fonctionnalités sont activées, mais n'ont pas pu être \
+                           enregistrées : {}",
+
+    draft_started: "Écriture d'un programme : vos prochains messages ici y sont ajoutés, \
+                    jusqu'à {} caractères. Envoyez `¡end` pour l'évaluer ou `¡abort` pour \
+                    l'abandonner ; il est abandonné après {} s sans message.",
+    draft_already: "Vous écrivez déjà un programme ; envoyez `¡end` pour l'évaluer ou `¡abort` \
+                    pour l'abandonner.",
+    draft_too_long: "Ce message rendrait le programme plus long que {} caractères, il n'a donc \
+                     pas été ajouté. Envoyez `¡end` pour évaluer ce que vous avez, ou `¡abort` \
+                     pour l'abandonner.",
+    draft_aborted: "Votre programme a été abandonné.",
+    draft_empty: "Votre programme est vide, il n'y a rien à évaluer.",
+    draft_expired: "Votre programme a été abandonné après {} s sans message.",
+    not_composing: "Vous n'écrivez pas de programme ; commencez-en un avec `¡begin`.",
+
+    pairs_in_servers: "Les sessions à deux se déroulent dans les serveurs.",
+    pair_usage: "Utilisation : `¡pair @utilisateur` ouvre une session à deux avec quelqu'un ici.",
+    pair_current: "{} et {} sont en session à deux ici.",
+    pair_self: "Vous ne pouvez pas faire équipe avec vous-même.",
+    pair_bot: "Les sessions à deux sont pour les personnes, pas pour les bots.",
+    pair_busy: "{} est déjà en session à deux ici.",
+    pairs_full: "Le bot a déjà autant de sessions à deux qu'il peut en faire tourner ; \
+                 réessayez plus tard.",
+    pair_started: "{} et {} partagent maintenant un environnement : vos évaluations dans ce \
+                   salon s'y font au lieu de celui du serveur. `¡handoff @utilisateur` donne \
+                   votre place à quelqu'un d'autre et `¡leave` met fin à la session, tout \
+                   comme {} minutes sans message de l'un de vous.",
+    handoff_usage: "Utilisation : `¡handoff @utilisateur` lui donne votre place dans votre \
+                    session à deux.",
+    pair_handed_off: "{} a donné sa place dans la session à deux à {}, qui partage maintenant \
+                      son environnement avec {}.",
+    pair_none: "Vous n'êtes pas en session à deux ici.",
+    pair_left: "{} a quitté la session à deux avec {}, qui prend donc fin ; son environnement \
+                n'existe plus.",
+    pair_expired: "La session à deux de {} et {} a pris fin faute de messages ; son \
+                   environnement n'existe plus.",
+
+    reload_done: "Bibliothèque standard rechargée dans un environnement neuf.",
+    reload_failed: "Échec du rechargement, l'environnement actuel est conservé :\n{}",
+    pages_from_one: "Les pages sont numérotées à partir de 1.",
+    no_name_contains: "Aucun nom défini ici ne contient `{}`.",
+    nothing_defined: "Rien n'a été défini ici depuis le dernier rechargement.",
+    only_pages: "Il n'y a que {} pages.",
+    names_page: "{} noms (page {} sur {}) :\n{}",
+    lookup_failed: "Échec de la recherche : {}",
+    source_of_usage: "Utilisation : `¡source-of <nom>`",
+    not_defined: "Aucune évaluation dans cet environnement n'a défini `{}` ; il vient peut-être \
+                  de la bibliothèque standard, ou n'a jamais été conservé.",
+    commit_nothing: "Votre dernière évaluation n'a rien défini.",
+    commit_one: "1 définition conservée.",
+    commit_many: "{} définitions conservées.",
+    commit_failed: "Échec de la conservation : {}",
+    commit_overwrote: "⚠️ Depuis votre évaluation, quelqu'un d'autre a conservé {} ; vos \
+                       définitions ont remplacé les siennes.",
+    commit_in_dm: "Les sessions privées gardent tout ce qu'elles définissent, il n'y a rien à \
+                   conserver.",
+
+    export_nothing: "Rien n'a été défini ici depuis le dernier rechargement.",
+    export_done: "Les définitions de cet environnement ; chargez-les avec `¡import`.",
+    export_too_large: "Les définitions de cet environnement sont trop longues pour être \
+                       envoyées.",
+    export_failed: "Échec de l'export : {}",
+    import_denied: "Seuls les admins peuvent importer des définitions dans l'environnement de \
+                    ce serveur.",
+    import_usage: "Utilisation : `¡import` avec un fichier `.scm` joint, ou \
+                   `¡import <lien vers un message>`",
+    import_no_file: "Il n'y a pas de fichier `.scm` à importer.",
+    import_too_large: "Les fichiers à importer font au plus {} Kio.",
+    import_download_failed: "Impossible de télécharger le fichier : {}",
+    import_not_text: "Le fichier à importer n'est pas du texte UTF-8.",
+    import_definitions_only: "Seuls les fichiers faits de définitions peuvent être importés.",
+    import_failed: "Échec de l'import :\n{}",
+    import_one: "1 définition importée.",
+    import_many: "{} définitions importées.",
+
+    nothing_scheduled: "Rien n'est programmé.",
+    scheduled_entry: "**#{}** `{}` par {}, publiée dans {} : {}",
+    schedule_denied: "Seuls les admins peuvent programmer des évaluations.",
+    schedule_removed: "Programmation #{} supprimée.",
+    no_such_schedule: "Il n'y a pas de programmation #{}.",
+    too_many_scheduled: "Un serveur peut avoir au plus {} évaluations programmées ; \
+                         supprimez-en une d'abord.",
+    scheduled_as: "Programmée sous le numéro #{}.",
+    schedule_usage: "Utilisation : \
+                     `¡schedule \"<minute> <heure> <jour> <mois> <jour de la semaine>\" <code>`",
+    invalid_schedule: "Programmation invalide : {}",
+
+    propose_definitions_only: "Seules des définitions peuvent être proposées : \
+                               `¡propose (define (f x) ...)`.",
+    proposal_title: "Proposition d'ajout au prélude",
+    proposed_by: "Proposée par {}",
+    field_definitions: "Définitions",
+    proposal_footer: "Réagissez avec {} ou {} ; {} approbations de plus que de rejets \
+                      l'ajoutent.",
+    proposal_too_long: "{} a proposé des définitions trop longues pour être affichées ; \
+                        réagissez avec {} ou {} pour voter.",
+    proposal_failed: "La proposition de {} a été approuvée, mais a échoué :\n{}",
+    proposal_added: "La proposition de {} a été approuvée et ajoutée au prélude.",
+    proposal_unsaved: "La proposition de {} a été approuvée, mais n'a pas pu être enregistrée ; \
+                       elle sera perdue au rechargement.",
+
+    version_title: "Version de peroxide",
+    version_build: "commit `{}`, compilé le {}",
+    version_features_unknown: "L'interpréteur n'indique pas ses fonctionnalités.",
+    version_no_srfis: "aucune indiquée",
+    field_build: "Bot",
+    field_interpreter: "Interpréteur",
+    field_features: "Fonctionnalités",
+    field_srfis: "SRFI",
+    field_uptime: "En ligne depuis",
+
+    stats_title: "Statistiques de peroxide",
+    stats_uptime: "En ligne depuis {}.",
+    stats_counters: "{} évaluations\n{} % d'erreurs\n{} en moyenne\nLes plus actifs : {}",
+    nobody_yet: "personne pour l'instant",
+    field_this_server: "Ce serveur",
+    field_overall: "En tout",
+
+    challenge_shown: "**{}**\n{}\n{} tests cachés. Proposez une solution avec \
+                      `¡submit <code>`.",
+    shortest_so_far: "\nSolution la plus courte jusqu'ici : {} caractères, par {}.",
+    no_challenge: "Il n'y a pas de défi en ce moment.",
+    nobody_solved: "Personne n'a encore résolu de défi.",
+    challenge_add_denied: "Seuls les admins peuvent ajouter des défis.",
+    challenge_new: "Nouveau défi : **{}**\n{}\n{} tests cachés. Proposez une solution avec \
+                    `¡submit <code>`.",
+    challenge_invalid: "Défi invalide : {}\nMettez le nom sur la première ligne, puis la \
+                        description, puis les tests sur des lignes comme \
+                        `test (solution 1 2) => 3`.",
+    challenge_usage: "Utilisation : `¡challenge`, `¡challenge leaderboard` ou `¡challenge add`.",
+    challenge_needs_name: "un défi a besoin d'un nom",
+    challenge_needs_test: "un défi a besoin d'au moins un test",
+    test_without_expected: "test sans valeur attendue : {}",
+    challenge_title: "Défi : {}",
+    tests_passed: "{}/{} tests réussis.",
+    solved: " Résolu, bienvenue au classement !",
+    new_shortest: " Nouvelle solution la plus courte : {} caractères !",
+    test_status: "Test {} : {}",
+    test_passed: "réussi",
+    test_wrong: "mauvaise réponse",
+    test_error: "erreur",
+    field_submission_error: "Erreur dans la solution",
+    field_tests: "Tests",
+
+    owner_guild: "{} ({}), {} membres",
+    owner_guilds: "Sur {} serveur(s) :\n{}",
+    owner_no_guilds: "Sur aucun serveur.",
+    owner_shutdown: "Arrêt en cours.",
+    owner_reloaded: "Configuration rechargée. Les workers, les shards, le bac à sable, l'API \
+                     HTTP et les limites de débit gardent leurs réglages jusqu'au redémarrage \
+                     du bot.",
+    owner_reload_failed: "Erreur au rechargement de la configuration, l'actuelle est \
+                          conservée : {}",
+    owner_broadcast_sent: "Envoyé à {} salon(s) sur {}.",
+
+    presence_down: "interpréteur hors service",
+    presence_busy: "évaluation en cours…",
+    presence_queue: "file d'attente : {}",
+};
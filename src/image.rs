@@ -0,0 +1,372 @@
+//! Rendering of the images built with the drawing library (see `scheme/drawing.scm`).
+//!
+//! Images are recognized in the printed value of a result, so nothing about them has to
+//! cross the interpreter boundary other than text.
+
+/// Largest width or height we render.
+const MAX_SIZE: u32 = 1024;
+/// Most shapes an image may have.
+const MAX_SHAPES: usize = 10_000;
+/// Most points all the polylines of an image may have together.
+const MAX_POINTS: usize = 100_000;
+
+type Rgb = [u8; 3];
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    shapes: Vec<Shape>,
+}
+
+enum Shape {
+    Line(Point, Point, Rgb),
+    Rect(Point, Point, Rgb),
+    Circle(Point, f64, Rgb),
+    Polyline(Vec<Point>, Rgb),
+}
+
+#[derive(Clone, Copy)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+/// A value in the printed representation of a Scheme datum.
+enum Datum {
+    Number(f64),
+    Symbol(String),
+    Str(String),
+    List(Vec<Datum>),
+}
+
+impl Image {
+    /// Parses the printed value of a result. Returns `None` if it isn't an image, and an
+    /// error if it looks like one but is malformed.
+    pub fn parse(printed: &str) -> Option<Result<Self, String>> {
+        if !printed.starts_with("(%image ") {
+            return None;
+        }
+        Some(read(printed).and_then(|datum| Self::from_datum(&datum)))
+    }
+
+    fn from_datum(datum: &Datum) -> Result<Self, String> {
+        let items = match datum {
+            Datum::List(items) => items,
+            _ => return Err("an image must be a list".into()),
+        };
+        let (width, height) = match items.get(1..3) {
+            Some([Datum::Number(w), Datum::Number(h)]) => (*w, *h),
+            _ => return Err("an image needs a width and a height".into()),
+        };
+        if !(1.0..=f64::from(MAX_SIZE)).contains(&width)
+            || !(1.0..=f64::from(MAX_SIZE)).contains(&height)
+        {
+            return Err(format!(
+                "image sizes must be between 1 and {} pixels",
+                MAX_SIZE
+            ));
+        }
+        let shapes = &items[3..];
+        if shapes.len() > MAX_SHAPES {
+            return Err(format!("images may have at most {} shapes", MAX_SHAPES));
+        }
+        let mut points = 0;
+        let shapes = shapes
+            .iter()
+            .map(|shape| {
+                let shape = parse_shape(shape)?;
+                if let Shape::Polyline(line, _) = &shape {
+                    points += line.len();
+                    if points > MAX_POINTS {
+                        return Err(format!("images may have at most {} points", MAX_POINTS));
+                    }
+                }
+                Ok(shape)
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            width: width as u32,
+            height: height as u32,
+            shapes,
+        })
+    }
+
+    /// Draws the image on a white background and encodes it as a PNG.
+    pub fn render(&self) -> Result<Vec<u8>, String> {
+        let mut canvas = Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: vec![[255; 3]; (self.width * self.height) as usize],
+        };
+        for shape in &self.shapes {
+            match shape {
+                Shape::Line(from, to, colour) => canvas.line(*from, *to, *colour),
+                Shape::Rect(corner, size, colour) => canvas.rect(*corner, *size, *colour),
+                Shape::Circle(centre, radius, colour) => canvas.circle(*centre, *radius, *colour),
+                Shape::Polyline(points, colour) => {
+                    for pair in points.windows(2) {
+                        canvas.line(pair[0], pair[1], *colour);
+                    }
+                }
+            }
+        }
+        canvas.encode()
+    }
+}
+
+fn parse_shape(shape: &Datum) -> Result<Shape, String> {
+    let items = match shape {
+        Datum::List(items) => items.as_slice(),
+        _ => return Err("shapes must be lists".into()),
+    };
+    let (kind, args) = match items.split_first() {
+        Some((Datum::Symbol(kind), args)) => (kind.as_str(), args),
+        _ => return Err("shapes must start with their kind".into()),
+    };
+    let (colour, args) = match args.split_last() {
+        Some((Datum::Str(colour), args)) => (parse_colour(colour)?, args),
+        _ => return Err(format!("{}: expected a color", kind)),
+    };
+    let numbers = |count: usize| -> Result<Vec<f64>, String> {
+        match args {
+            [Datum::List(items)] if count == 0 => items.iter().map(number).collect(),
+            _ if args.len() == count => args.iter().map(number).collect(),
+            _ => Err(format!("{}: wrong number of arguments", kind)),
+        }
+    };
+    let point = |x: f64, y: f64| Point { x, y };
+    match kind {
+        "line" => {
+            let n = numbers(4)?;
+            Ok(Shape::Line(point(n[0], n[1]), point(n[2], n[3]), colour))
+        }
+        "rect" => {
+            let n = numbers(4)?;
+            Ok(Shape::Rect(point(n[0], n[1]), point(n[2], n[3]), colour))
+        }
+        "circle" => {
+            let n = numbers(3)?;
+            Ok(Shape::Circle(point(n[0], n[1]), n[2], colour))
+        }
+        "polyline" => {
+            let n = numbers(0)?;
+            if n.len() % 2 != 0 {
+                return Err("polyline: points must have two coordinates".into());
+            }
+            Ok(Shape::Polyline(
+                n.chunks(2).map(|c| point(c[0], c[1])).collect(),
+                colour,
+            ))
+        }
+        _ => Err(format!("unknown shape {}", kind)),
+    }
+}
+
+fn number(datum: &Datum) -> Result<f64, String> {
+    match datum {
+        Datum::Number(n) if n.is_finite() => Ok(*n),
+        _ => Err("expected a number".into()),
+    }
+}
+
+fn parse_colour(colour: &str) -> Result<Rgb, String> {
+    let named = match colour {
+        "black" => Some([0, 0, 0]),
+        "white" => Some([255, 255, 255]),
+        "gray" | "grey" => Some([128, 128, 128]),
+        "red" => Some([220, 40, 40]),
+        "green" => Some([40, 160, 60]),
+        "blue" => Some([40, 90, 220]),
+        "yellow" => Some([240, 200, 30]),
+        "orange" => Some([240, 130, 30]),
+        "purple" => Some([140, 60, 180]),
+        _ => None,
+    };
+    if let Some(rgb) = named {
+        return Ok(rgb);
+    }
+    let hex = colour
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| format!("unknown color {}", colour))?;
+    Ok([(hex >> 16) as u8, (hex >> 8) as u8, hex as u8])
+}
+
+/// Reads a single printed datum.
+fn read(printed: &str) -> Result<Datum, String> {
+    let mut chars = printed.chars().peekable();
+    let datum = read_datum(&mut chars)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(datum),
+        Some(_) => Err("unexpected text after the image".into()),
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Chars) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn read_datum(chars: &mut Chars) -> Result<Datum, String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        None => Err("unexpected end of image".into()),
+        Some('(') => {
+            let mut items = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.peek() == Some(&')') {
+                    chars.next();
+                    return Ok(Datum::List(items));
+                }
+                items.push(read_datum(chars)?);
+            }
+        }
+        Some(')') => Err("unexpected )".into()),
+        Some('"') => {
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err("unterminated string".into()),
+                    Some('"') => return Ok(Datum::Str(s)),
+                    Some('\\') => s.extend(chars.next()),
+                    Some(c) => s.push(c),
+                }
+            }
+        }
+        Some(first) => {
+            let mut atom = first.to_string();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                atom.push(c);
+                chars.next();
+            }
+            Ok(parse_number(&atom).map_or(Datum::Symbol(atom), Datum::Number))
+        }
+    }
+}
+
+/// Parses integers, decimals and exact fractions like `1/3`.
+fn parse_number(atom: &str) -> Option<f64> {
+    if let Ok(n) = atom.parse::<f64>() {
+        return Some(n);
+    }
+    let (numerator, denominator) = atom.split_at(atom.find('/')?);
+    Some(numerator.parse::<f64>().ok()? / denominator[1..].parse::<f64>().ok()?)
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgb>,
+}
+
+impl Canvas {
+    fn set(&mut self, x: i64, y: i64, colour: Rgb) {
+        if x >= 0 && y >= 0 && x < i64::from(self.width) && y < i64::from(self.height) {
+            self.pixels[(y * i64::from(self.width) + x) as usize] = colour;
+        }
+    }
+
+    fn line(&mut self, from: Point, to: Point, colour: Rgb) {
+        let (from, to) = match self.clip(from, to) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+        let steps = (to.x - from.x)
+            .abs()
+            .max((to.y - from.y).abs())
+            .ceil()
+            .max(1.0);
+        for i in 0..=steps as i64 {
+            let t = i as f64 / steps;
+            let x = from.x + (to.x - from.x) * t;
+            let y = from.y + (to.y - from.y) * t;
+            self.set(x.round() as i64, y.round() as i64, colour);
+        }
+    }
+
+    /// Clips a line to the canvas (Liang–Barsky), so that drawing it takes time
+    /// proportional to the visible part.
+    fn clip(&self, from: Point, to: Point) -> Option<(Point, Point)> {
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let (width, height) = (f64::from(self.width), f64::from(self.height));
+        let (mut start, mut end) = (0.0f64, 1.0f64);
+        for (p, q) in &[
+            (-dx, from.x),
+            (dx, width - 1.0 - from.x),
+            (-dy, from.y),
+            (dy, height - 1.0 - from.y),
+        ] {
+            if *p == 0.0 {
+                if *q < 0.0 {
+                    return None;
+                }
+            } else {
+                let t = q / p;
+                if *p < 0.0 {
+                    start = start.max(t);
+                } else {
+                    end = end.min(t);
+                }
+            }
+        }
+        if start > end {
+            return None;
+        }
+        let at = |t: f64| Point {
+            x: from.x + dx * t,
+            y: from.y + dy * t,
+        };
+        Some((at(start), at(end)))
+    }
+
+    fn rect(&mut self, corner: Point, size: Point, colour: Rgb) {
+        let x0 = corner.x.min(corner.x + size.x).max(0.0) as i64;
+        let y0 = corner.y.min(corner.y + size.y).max(0.0) as i64;
+        let x1 = corner.x.max(corner.x + size.x).min(f64::from(self.width)) as i64;
+        let y1 = corner.y.max(corner.y + size.y).min(f64::from(self.height)) as i64;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.set(x, y, colour);
+            }
+        }
+    }
+
+    fn circle(&mut self, centre: Point, radius: f64, colour: Rgb) {
+        let radius = radius.abs();
+        let x0 = (centre.x - radius).max(0.0) as i64;
+        let y0 = (centre.y - radius).max(0.0) as i64;
+        let x1 = (centre.x + radius).min(f64::from(self.width)) as i64;
+        let y1 = (centre.y + radius).min(f64::from(self.height)) as i64;
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let (dx, dy) = (x as f64 - centre.x, y as f64 - centre.y);
+                if dx * dx + dy * dy <= radius * radius {
+                    self.set(x, y, colour);
+                }
+            }
+        }
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+            encoder.set_color(png::ColorType::RGB);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+            writer
+                .write_image_data(&self.pixels.concat())
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,914 @@
+//! Wrapper around the peroxide interpreter that enforces limits on evaluations.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use peroxide::Interpreter;
+use repl_bot::format::Printing;
+use repl_bot::limits::{Interruption, Limits, Watchdog};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::actions::{Action, Invocation};
+use crate::config::StartupConfig;
+use crate::error::Error;
+use crate::forms::{self, split_forms, Form, SyntaxError};
+
+/// peroxide's standard library, bundled so the bot doesn't need a peroxide checkout at run
+/// time.
+const INIT_SCM: &str = include_str!("../../peroxide/src/scheme-lib/init.scm");
+/// Scheme definitions installed after the standard library, see the file for details.
+const BOT_PRELUDE: &str = include_str!("scheme/bot-prelude.scm");
+/// Procedures building images that the bot renders, see the file for details.
+const DRAWING_PRELUDE: &str = include_str!("scheme/drawing.scm");
+/// `assert` and `check-equal?`, see the file for details.
+const TEST_PRELUDE: &str = include_str!("scheme/test-prelude.scm");
+/// Error raised by the prelude's input procedures when the program has read every line it
+/// was given.
+const NEEDS_INPUT_MARKER: &str = "%bot-needs-input";
+/// What the bot's own procedures are named with in the preludes. Each interpreter renames
+/// them with a prefix of its own that programs can't guess, so that they can neither call
+/// them nor redefine them.
+const HIDDEN_PREFIX: &str = "%bot-";
+/// What follows the hidden prefix in the names a program running over an environment gets
+/// instead of those it binds, see `run_layered`.
+const LOCAL_PREFIX: &str = "local-";
+/// How calls of commands defined from Scheme start: the one use of the bot's own procedures
+/// allowed in programs, so that such a call can be shown and run again as code.
+pub const COMMAND_CALL: &str = "(%bot-run-command ";
+/// Output procedures the bot prelude redefines to write to its buffer when given no port.
+/// The originals are kept first, with `%bot-original-` before their names, for when a port
+/// is given.
+const OUTPUT_PROCEDURES: &[&str] = &["display", "write", "write-string", "write-char", "newline"];
+/// Primitives that reach outside the interpreter, to the file system or the process and its
+/// environment. Restricted interpreters have them replaced with stubs raising an error.
+const DANGEROUS_PRIMITIVES: &[&str] = &[
+    "open-input-file",
+    "open-output-file",
+    "open-binary-input-file",
+    "open-binary-output-file",
+    "call-with-input-file",
+    "call-with-output-file",
+    "with-input-from-file",
+    "with-output-to-file",
+    "file-exists?",
+    "delete-file",
+    "load",
+    "exit",
+    "emergency-exit",
+    "command-line",
+    "get-environment-variable",
+    "get-environment-variables",
+    "system",
+];
+/// Feature taking mutation away, for teaching: `set!` and the primitives changing pairs,
+/// vectors and strings raise an error instead.
+pub const NO_MUTATION: &str = "no-mutation";
+/// Primitives that change their arguments, stubbed out with `NO_MUTATION`.
+const MUTATING_PRIMITIVES: &[&str] = &[
+    "set-car!",
+    "set-cdr!",
+    "list-set!",
+    "vector-set!",
+    "vector-fill!",
+    "vector-copy!",
+    "string-set!",
+    "string-fill!",
+    "string-copy!",
+    "bytevector-u8-set!",
+    "bytevector-copy!",
+];
+/// Rough size of a vector or string element, used to turn the memory limit into a cap on
+/// the size of a single allocation.
+const ESTIMATED_ELEMENT_SIZE: u64 = 16;
+/// Procedures that can allocate far more than their arguments take up, with a Scheme
+/// expression for how many elements a call makes from its list of arguments `args`. The
+/// bot prelude guards each of them that is defined against making more than the memory
+/// limit allows at once. Procedures making something no bigger than their arguments are
+/// left to the watchdog.
+const ALLOCATING_PROCEDURES: &[(&str, &str)] = &[
+    ("make-vector", "(%bot-first-count args)"),
+    ("make-string", "(%bot-first-count args)"),
+    ("make-bytevector", "(%bot-first-count args)"),
+    ("make-list", "(%bot-first-count args)"),
+    (
+        "string-append",
+        "(%bot-total-size string? string-length args)",
+    ),
+    (
+        "vector-append",
+        "(%bot-total-size vector? vector-length args)",
+    ),
+    (
+        "bytevector-append",
+        "(%bot-total-size bytevector? bytevector-length args)",
+    ),
+    ("append", "(%bot-total-size list? length args)"),
+];
+/// Characters a value is printed to when printing it with the request's limits left
+/// something out, for showing more of it on request.
+const FULL_PRINT_CHARS: usize = 50_000;
+
+/// A program to evaluate, and what it may do.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub command: String,
+    pub limits: Limits,
+    /// Whether bot commands defined by the program are kept.
+    pub privileged: bool,
+    /// The Discord message the evaluation runs for, if any.
+    pub invocation: Option<Invocation>,
+    /// Lines of input for `read` and `read-line`, in order.
+    pub inputs: Vec<String>,
+    /// How the values of the program's forms are printed.
+    pub printing: Printing,
+}
+
+impl Request {
+    /// An unprivileged request that can't act on Discord and has no input, whose values
+    /// are printed in full.
+    pub fn new(command: String, limits: Limits) -> Self {
+        Self {
+            command,
+            limits,
+            privileged: false,
+            invocation: None,
+            inputs: Vec::new(),
+            printing: Printing::default(),
+        }
+    }
+}
+
+/// Everything we report back about a single evaluation.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// Pretty-printed values of the top-level forms that completed, in order.
+    pub values: Vec<String>,
+    /// The values printing left something out of, by index, printed again with only
+    /// `FULL_PRINT_CHARS` as limit.
+    pub full_values: Vec<(usize, String)>,
+    /// Why evaluation stopped, if it didn't run to completion.
+    pub error: Option<EvalError>,
+    /// How many top-level forms the program has.
+    pub form_count: usize,
+    /// What the program printed while running.
+    pub output: String,
+    pub elapsed: Duration,
+    /// Bot commands the program defined with `define-command` that were kept.
+    pub defined_commands: Vec<String>,
+    /// Bot commands the program tried to define without the privilege to do so.
+    pub rejected_commands: Vec<String>,
+    /// Discord actions the program queued, in order.
+    pub actions: Vec<Action>,
+    /// Outcome of the program's `assert` and `check-equal?` checks, if it ran any.
+    pub test_report: Option<TestReport>,
+    /// Whether the program stopped because it wanted more input than it was given. Its
+    /// actions and command definitions are dropped, as it will be run again.
+    pub needs_input: bool,
+    /// Whether this is the result of an earlier, identical evaluation rather than a new one.
+    pub cached: bool,
+    /// Failures of committed programs replayed to get the environment the program ran in,
+    /// whose definitions it may have lacked.
+    pub replay_errors: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TestReport {
+    pub passed: usize,
+    /// Descriptions of the failed checks, in order.
+    pub failures: Vec<String>,
+}
+
+impl TestReport {
+    /// Parses the report returned by `%bot-take-test-report`.
+    fn parse(report: &str) -> Option<Self> {
+        let mut lines = report.lines();
+        let report = Self {
+            passed: lines.next()?.parse().ok()?,
+            failures: lines.map(String::from).collect(),
+        };
+        if report.passed == 0 && report.failures.is_empty() {
+            None
+        } else {
+            Some(report)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EvalError {
+    pub message: String,
+    /// Index and source of the top-level form that failed, if the failure was in one.
+    pub form: Option<(usize, String)>,
+    /// Line of the program that form starts on.
+    pub line: Option<usize>,
+    /// Why the evaluation was interrupted and what it had used by then, if it was.
+    pub interruption: Option<Interruption>,
+}
+
+impl Evaluation {
+    /// An evaluation that never reached the interpreter.
+    pub fn failed(error: String) -> Self {
+        Self {
+            error: Some(EvalError {
+                message: error,
+                form: None,
+                line: None,
+                interruption: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// A word describing how the evaluation went, for logs.
+    pub fn outcome(&self) -> &'static str {
+        match &self.error {
+            _ if self.cached => "cached",
+            None => "ok",
+            Some(_) if self.needs_input => "needs input",
+            Some(error) if error.message.starts_with("evaluation interrupted") => "interrupted",
+            Some(_) => "error",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum FormError {
+    Parse(String),
+    Runtime(String),
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormError::Parse(message) => write!(f, "parse error: {}", message),
+            FormError::Runtime(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+pub struct InterruptingInterpreter {
+    interpreter: Interpreter,
+    /// What `HIDDEN_PREFIX` is replaced with in this interpreter.
+    prefix: String,
+}
+
+/// The standard library and startup files, read ahead of time so that interpreters can be
+/// created without touching the file system.
+#[derive(Clone)]
+pub struct StartupSources {
+    /// Name and source of the standard library.
+    init: (String, String),
+    /// Paths and sources of the startup files, in order.
+    files: Vec<(String, String)>,
+    /// Paths and sources of the files of each library, by feature name.
+    libraries: HashMap<String, Vec<(String, String)>>,
+}
+
+impl StartupSources {
+    pub fn read(startup: &StartupConfig) -> Result<Self, String> {
+        let init = match &startup.init_path {
+            Some(path) => (path.clone(), read_file(path)?),
+            None => ("init.scm".to_string(), INIT_SCM.to_string()),
+        };
+        let read_all = |paths: &[String]| {
+            paths
+                .iter()
+                .map(|path| Ok((path.clone(), read_file(path)?)))
+                .collect::<Result<Vec<_>, String>>()
+        };
+        let files = read_all(&startup.files)?;
+        let libraries = startup
+            .libraries
+            .iter()
+            .map(|(name, paths)| Ok((name.clone(), read_all(paths)?)))
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            init,
+            files,
+            libraries,
+        })
+    }
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("error reading {}: {}", path, e))
+}
+
+impl InterruptingInterpreter {
+    /// Creates an interpreter with the standard library, the bot prelude, the drawing
+    /// library, the test prelude, the configured startup files and the libraries of
+    /// `features` loaded, in that order. If it is `restricted`, the dangerous primitives are
+    /// then stubbed out, and so is mutation if `NO_MUTATION` is among the features.
+    pub fn new(
+        startup: &StartupConfig,
+        restricted: bool,
+        features: &[String],
+    ) -> Result<Self, String> {
+        Self::from_sources(&StartupSources::read(startup)?, restricted, features)
+    }
+
+    /// Like `new`, with startup files that were already read.
+    pub fn from_sources(
+        sources: &StartupSources,
+        restricted: bool,
+        features: &[String],
+    ) -> Result<Self, String> {
+        let interpreter = Self {
+            interpreter: Interpreter::new(),
+            prefix: secret_prefix(),
+        };
+        interpreter.load(&sources.init.0, &sources.init.1)?;
+        interpreter.keep_output_procedures()?;
+        interpreter.load("bot prelude", &interpreter.hide(BOT_PRELUDE))?;
+        interpreter.guard_allocations()?;
+        interpreter.load("drawing library", DRAWING_PRELUDE)?;
+        interpreter.load("test prelude", &interpreter.hide(TEST_PRELUDE))?;
+        for (path, source) in &sources.files {
+            interpreter.load(path, source)?;
+        }
+        for feature in features {
+            match sources.libraries.get(feature) {
+                Some(files) => {
+                    for (path, source) in files {
+                        interpreter.load(path, source)?;
+                    }
+                }
+                None if feature == NO_MUTATION => {}
+                None => return Err(format!("unknown feature {}", feature)),
+            }
+        }
+        if restricted {
+            interpreter.stub_out(DANGEROUS_PRIMITIVES, "in public evaluations")?;
+        }
+        if features.iter().any(|feature| feature == NO_MUTATION) {
+            interpreter.forbid_mutation()?;
+        }
+        Ok(interpreter)
+    }
+
+    /// Replaces those of `primitives` that are defined with procedures raising an error
+    /// saying they are not available `scope`, such as "in public evaluations".
+    fn stub_out(&self, primitives: &[&str], scope: &str) -> Result<(), String> {
+        for name in primitives {
+            if self.run_form(name).is_err() {
+                continue;
+            }
+            let message = format!("{} is not available {}", name, scope);
+            self.run_form(&format!(
+                "(set! {} (lambda args (error {})))",
+                name,
+                string_literal(&message)
+            ))
+            .map_err(|e| format!("error restricting {}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Binds each of `OUTPUT_PROCEDURES` under its name for the bot prelude, or a procedure
+    /// raising an error if it isn't defined.
+    fn keep_output_procedures(&self) -> Result<(), String> {
+        for name in OUTPUT_PROCEDURES {
+            let original = if self.run_form(name).is_ok() {
+                name.to_string()
+            } else {
+                format!(
+                    "(lambda args (error \"{} is not available with a port\"))",
+                    name
+                )
+            };
+            self.run_form(&format!(
+                "(define {}{} {})",
+                self.hide("%bot-original-"),
+                name,
+                original
+            ))
+            .map_err(|e| format!("error keeping {}: {}", name, self.reveal(&e.to_string())))?;
+        }
+        Ok(())
+    }
+
+    /// Wraps those of `ALLOCATING_PROCEDURES` that are defined in the prelude's guard.
+    fn guard_allocations(&self) -> Result<(), String> {
+        for (name, size) in ALLOCATING_PROCEDURES {
+            if self.run_form(name).is_err() {
+                continue;
+            }
+            self.run_form(&self.hide(&format!(
+                "(define {0} (%bot-guard-allocation '{0} {0} (lambda (args) {1})))",
+                name, size
+            )))
+            .map_err(|e| format!("error guarding {}: {}", name, self.reveal(&e.to_string())))?;
+        }
+        Ok(())
+    }
+
+    /// Stubs out the mutating primitives, then `set!` itself. Code loaded before keeps
+    /// working, as its uses of `set!` were already expanded.
+    fn forbid_mutation(&self) -> Result<(), String> {
+        let scope = "without mutation";
+        self.stub_out(MUTATING_PRIMITIVES, scope)?;
+        let message = format!("set! is not available {}", scope);
+        self.run_form(&format!(
+            "(define-syntax set! (syntax-rules () ((_ . args) (error {}))))",
+            string_literal(&message)
+        ))
+        .map(|_| ())
+        .map_err(|e| format!("error restricting set!: {}", e))
+    }
+
+    /// Runs trusted code from `source`, stopping at the first error.
+    fn load(&self, name: &str, source: &str) -> Result<(), String> {
+        let forms = split_forms(source)
+            .map_err(|e| format!("error loading {}: {}", name, e.render(source)))?;
+        for form in forms {
+            self.run_form(form.source)
+                .map_err(|e| format!("error loading {}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Runs each top-level form of the request's command in turn, stopping at the first
+    /// error. The limits apply to the program as a whole.
+    pub fn run_string(&mut self, request: &Request) -> Evaluation {
+        let command = self.hide_command_call(&request.command);
+        let evaluation = self.run_program(&command, request);
+        self.scrub(evaluation)
+    }
+
+    /// Runs the request's command as `run_string` does, but over the environment rather
+    /// than in it, as if in an environment of its own whose parent is this one: each name
+    /// the command defines or assigns is renamed throughout it to a name of the bot's own,
+    /// which starts out with the original's value, so the environment keeps its bindings.
+    /// Data changed in place, and what procedures from the environment assign, are shared.
+    ///
+    /// Returns `None` without running anything if what the command binds can't be told from
+    /// its code, as when it uses one of `binding_syntax`, the syntax in the environment
+    /// whose uses may bind names.
+    pub fn run_layered(
+        &mut self,
+        request: &Request,
+        binding_syntax: &HashSet<String>,
+    ) -> Option<Evaluation> {
+        let bindings = match forms::bindings(&request.command) {
+            Ok(bindings) => bindings,
+            // It won't run at all.
+            Err(_) => return Some(self.run_string(request)),
+        };
+        if bindings.opaque
+            || !bindings.binding_syntax.is_empty()
+            || binding_syntax.iter().any(|name| bindings.mentions(name))
+        {
+            return None;
+        }
+        // Assigning a name that isn't bound fails as it is.
+        let names = bindings
+            .assigned
+            .iter()
+            .filter(|name| self.is_bound(name))
+            .chain(&bindings.defined)
+            .copied()
+            .collect::<HashSet<_>>();
+        let command = forms::rename(&request.command, &names, |name| self.local_name(name)).ok()?;
+        for name in &names {
+            if self.is_bound(name) {
+                let seeded = format!("(define {} {})", self.local_name(name), symbol(name));
+                // Syntax keywords have no value to start from.
+                let _ = self.run_form(&seeded);
+            }
+        }
+        let evaluation = self.run_program(&self.hide_command_call(&command), request);
+        // What the program bound is garbage from now on.
+        for name in &names {
+            let _ = self.run_form(&format!("(define {} #f)", self.local_name(name)));
+        }
+        Some(self.scrub(evaluation))
+    }
+
+    /// `command`, with the bot's own name in the call it starts with if it calls a command
+    /// defined from Scheme.
+    fn hide_command_call(&self, command: &str) -> String {
+        match command.strip_prefix(COMMAND_CALL) {
+            Some(rest) => format!("{}{}", self.hide(COMMAND_CALL), rest),
+            None => command.to_string(),
+        }
+    }
+
+    /// The symbol `run_layered` renames `name` to.
+    fn local_name(&self, name: &str) -> String {
+        symbol(&format!("{}{}{}", self.prefix, LOCAL_PREFIX, name))
+    }
+
+    fn is_bound(&self, name: &str) -> bool {
+        self.run_form(&format!("(begin {} #t)", symbol(name)))
+            .is_ok()
+    }
+
+    /// Runs the request's command, an expression for the value challenge test `test`
+    /// expects, and keeps the value under a name of the bot's own.
+    pub fn keep_expected(&mut self, test: usize, request: &Request) -> Evaluation {
+        let command = format!("(define {} {})", self.expected_name(test), request.command);
+        let evaluation = self.run_program(&command, request);
+        self.scrub(evaluation)
+    }
+
+    /// Runs the request's command, the expression of challenge test `test`, and compares
+    /// its value with the one `keep_expected` kept, using the `equal?` the interpreter was
+    /// created with.
+    pub fn check_expected(&mut self, test: usize, request: &Request) -> Evaluation {
+        let command = format!(
+            "({} {} {})",
+            self.hide("%bot-equal?"),
+            request.command,
+            self.expected_name(test)
+        );
+        let evaluation = self.run_program(&command, request);
+        self.scrub(evaluation)
+    }
+
+    fn expected_name(&self, test: usize) -> String {
+        self.hide(&format!("%bot-expected-{}", test))
+    }
+
+    fn run_program(&mut self, command: &str, request: &Request) -> Evaluation {
+        let limits = request.limits;
+        let forms = match split_forms(command) {
+            Ok(forms) => forms,
+            Err(e) => return Evaluation::failed(e.render(command)),
+        };
+        let mut evaluation = Evaluation {
+            form_count: forms.len(),
+            ..Default::default()
+        };
+
+        let allocation_limit = limits.memory_fuel / ESTIMATED_ELEMENT_SIZE;
+        let prepared = self
+            .hook("%bot-set-allocation-limit!", &allocation_limit.to_string())
+            .and_then(|()| self.hook("%bot-reset-tests!", ""));
+        if let Err(e) = prepared {
+            error!("{}", e);
+            return Evaluation::failed(e.into());
+        }
+        let inputs = request
+            .inputs
+            .iter()
+            .map(|line| input_literal(line))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let inputs = format!("(list {})", inputs);
+        if let Err(e) = self.run_form(&self.bot_call("%bot-set-input!", &inputs)) {
+            return Evaluation::failed(format!("invalid input: {}", e));
+        }
+        if let Some(invocation) = &request.invocation {
+            let begun = self.hook(
+                "%bot-begin-invocation!",
+                &string_literal(&invocation.author_name),
+            );
+            if let Err(e) = begun {
+                error!("{}", e);
+                return Evaluation::failed(e.into());
+            }
+        }
+
+        let interruptor = self.interpreter.interruptor();
+        let watchdog = Watchdog::arm(move || interruptor.interrupt(), limits);
+        let start = Instant::now();
+        for (index, form) in forms.iter().enumerate() {
+            let result = if start.elapsed() >= limits.timeout {
+                Err("evaluation timed out".to_string())
+            } else {
+                self.run_value(form, &request.printing)
+                    .map_err(|e| match e {
+                        FormError::Parse(message) => SyntaxError {
+                            message,
+                            position: form.start,
+                            hint: None,
+                            closers: None,
+                        }
+                        .render(command),
+                        FormError::Runtime(message) => message,
+                    })
+            };
+            match result {
+                Ok((value, full)) => {
+                    if let Some(full) = full {
+                        evaluation.full_values.push((evaluation.values.len(), full));
+                    }
+                    evaluation.values.push(value);
+                }
+                Err(message) => {
+                    evaluation.error = Some(EvalError {
+                        message,
+                        form: Some((index, form.source.to_string())),
+                        line: Some(command[..form.start].matches('\n').count() + 1),
+                        interruption: None,
+                    });
+                    break;
+                }
+            }
+        }
+        evaluation.elapsed = start.elapsed();
+        if let (Some(interruption), Some(error)) = (watchdog.disarm(), &mut evaluation.error) {
+            error.message = format!("evaluation interrupted: {}", interruption.exceeded);
+            error.interruption = Some(interruption);
+        }
+        if let Some(error) = &mut evaluation.error {
+            if error.message.contains(&self.hide(NEEDS_INPUT_MARKER)) {
+                evaluation.needs_input = true;
+                error.message = "waiting for input".into();
+            }
+        }
+
+        evaluation.output = self.take_string("%bot-take-output");
+        evaluation.test_report = TestReport::parse(&self.take_string("%bot-take-test-report"));
+        // What the program did is kept even if the bot's own bookkeeping fails afterwards.
+        let mut internal_errors = Vec::new();
+        internal_errors.extend(self.hook("%bot-end-invocation!", "").err());
+        loop {
+            let queued = self.take_string("%bot-next-action");
+            if queued.is_empty() {
+                break;
+            }
+            evaluation.actions.extend(Action::parse(&queued));
+        }
+        // The count is kept here, where programs can't reset it.
+        if let Some(invocation) = &request.invocation {
+            if evaluation.actions.len() > invocation.max_actions {
+                evaluation.actions.truncate(invocation.max_actions);
+                if evaluation.error.is_none() {
+                    evaluation.error = Evaluation::failed(format!(
+                        "too many actions in one evaluation, only the first {} were done",
+                        invocation.max_actions
+                    ))
+                    .error;
+                }
+            }
+        }
+        if evaluation.needs_input {
+            evaluation.actions.clear();
+        }
+
+        let pending_commands = self.take_string("%bot-take-pending-commands");
+        let pending_commands = pending_commands
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        if !pending_commands.is_empty() {
+            if request.privileged && !evaluation.needs_input {
+                match self.hook("%bot-accept-commands!", "") {
+                    Ok(()) => evaluation.defined_commands = pending_commands,
+                    Err(e) => internal_errors.push(e),
+                }
+            } else {
+                internal_errors.extend(self.hook("%bot-discard-commands!", "").err());
+                if !evaluation.needs_input {
+                    evaluation.rejected_commands = pending_commands;
+                }
+            }
+        }
+        for e in internal_errors {
+            error!("{}", e);
+            if evaluation.error.is_none() {
+                evaluation.error = Evaluation::failed(e.into()).error;
+            }
+        }
+        evaluation
+    }
+
+    /// Runs a top-level form and prints its value. Definitions and `begin` forms must stay
+    /// at top level, so they are run as they are and printed by the interpreter; lists and
+    /// vectors returned by other forms are printed by the prelude, with the request's limits.
+    /// If those left something out, the value is also printed with only `FULL_PRINT_CHARS`
+    /// as limit.
+    fn run_value(
+        &self,
+        form: &Form,
+        printing: &Printing,
+    ) -> Result<(String, Option<String>), FormError> {
+        if form.is_definition() || form.is_begin() {
+            return self.run_form(form.source).map(|value| (value, None));
+        }
+        let value = self.run_form(&self.bot_call(
+            "%bot-print-result",
+            &format!(
+                "{} {} {} {} {}",
+                form.source,
+                printing.max_depth,
+                printing.max_items,
+                printing.max_chars,
+                if printing.shared { "#t" } else { "#f" }
+            ),
+        ))?;
+        let printed = self.take_string("%bot-take-printed");
+        if printed.is_empty() {
+            return Ok((value, None));
+        }
+        let full = if self.run_form(&self.bot_call("%bot-take-elided", ""))? == "#t" {
+            self.run_form(&self.bot_call(
+                "%bot-reprint-result",
+                &format!(
+                    "0 0 {} {}",
+                    FULL_PRINT_CHARS,
+                    if printing.shared { "#t" } else { "#f" }
+                ),
+            ))?;
+            Some(self.take_string("%bot-take-printed"))
+        } else {
+            None
+        };
+        Ok((printed, full))
+    }
+
+    /// Calls the bot helper `name`, which returns a string, and returns its contents.
+    fn take_string(&self, name: &str) -> String {
+        self.run_form(&self.bot_call(name, ""))
+            .map(|printed| unquote_string(&printed))
+            .unwrap_or_default()
+    }
+
+    /// Calls the bot's procedure `name` with `args`, which only fails if something is wrong
+    /// with the bot prelude or the environment it was loaded in.
+    fn hook(&self, name: &str, args: &str) -> Result<(), Error> {
+        self.run_form(&self.bot_call(name, args))
+            .map(|_| ())
+            .map_err(|e| Error::Hook {
+                form: call(name, args),
+                message: self.reveal(&e.to_string()),
+            })
+    }
+
+    /// A call of the bot's procedure `name`, as named in the preludes, with `args` written
+    /// out. Only the name is hidden, as the arguments may come from users.
+    fn bot_call(&self, name: &str, args: &str) -> String {
+        call(&self.hide(name), args)
+    }
+
+    /// Gives the bot's procedures their hidden names in `code`, which the bot wrote.
+    fn hide(&self, code: &str) -> String {
+        code.replace(HIDDEN_PREFIX, &self.prefix)
+    }
+
+    /// Gives the bot's procedures back their names in the preludes in `text`, and the names
+    /// `run_layered` renamed back theirs, so that programs never see the hidden ones.
+    fn reveal(&self, text: &str) -> String {
+        text.replace(&format!("{}{}", self.prefix, LOCAL_PREFIX), "")
+            .replace(&self.prefix, HIDDEN_PREFIX)
+    }
+
+    /// Reveals the bot's procedures wherever `evaluation` shows code or messages, as in a
+    /// backtrace through a prelude.
+    fn scrub(&self, mut evaluation: Evaluation) -> Evaluation {
+        for value in &mut evaluation.values {
+            *value = self.reveal(value);
+        }
+        for (_, full) in &mut evaluation.full_values {
+            *full = self.reveal(full);
+        }
+        if let Some(error) = &mut evaluation.error {
+            error.message = self.reveal(&error.message);
+            if let Some((_, source)) = &mut error.form {
+                *source = self.reveal(source);
+            }
+        }
+        evaluation.output = self.reveal(&evaluation.output);
+        evaluation
+    }
+
+    /// Reads and runs a single form. Callers running untrusted code must arm the interruptor.
+    fn run_form(&self, form: &str) -> Result<String, FormError> {
+        let read = peroxide::read::read(&self.interpreter.arena, form)
+            .map_err(|e| FormError::Parse(e.to_string()))?;
+        self.interpreter
+            .parse_compile_run(read)
+            .map(|p| p.pp().pretty_print())
+            .map_err(FormError::Runtime)
+    }
+}
+
+/// Whether `name` is one of those the preludes give the bot's own procedures.
+pub fn is_bot_name(name: &str) -> bool {
+    name.to_lowercase().starts_with(HIDDEN_PREFIX)
+}
+
+fn call(name: &str, args: &str) -> String {
+    if args.is_empty() {
+        format!("({})", name)
+    } else {
+        format!("({} {})", name, args)
+    }
+}
+
+/// The symbol named `name`, written between `|` if it has to be.
+fn symbol(name: &str) -> String {
+    let plain = !name.is_empty()
+        && !name.starts_with('#')
+        && !name.contains(|c: char| c.is_whitespace() || "()[]\";'`,|".contains(c));
+    if plain {
+        name.to_string()
+    } else {
+        format!("|{}|", name)
+    }
+}
+
+/// `HIDDEN_PREFIX` followed by random digits, different for each interpreter.
+fn secret_prefix() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    format!("{}{:016x}{:016x}-", HIDDEN_PREFIX, random(), random())
+}
+
+/// Writes `s` as a Scheme string literal.
+pub fn string_literal(s: &str) -> String {
+    let mut literal = String::with_capacity(s.len() + 2);
+    literal.push('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            '\n' => literal.push_str("\\n"),
+            _ => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// A line of input as the prelude expects it: the line, and a list holding what `read`
+/// returns for it, or an empty list if it isn't a single datum.
+fn input_literal(line: &str) -> String {
+    let datum = match split_forms(line) {
+        Ok(forms) if forms.len() == 1 => format!("(list (quote {}))", forms[0].source),
+        _ => "'()".to_string(),
+    };
+    format!("(cons {} {})", string_literal(line), datum)
+}
+
+/// Turns the printed representation of a Scheme string back into its contents.
+fn unquote_string(printed: &str) -> String {
+    let inner = printed
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(printed);
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn interpreter() -> (InterruptingInterpreter, Limits) {
+        let config = Config::default();
+        let interpreter = InterruptingInterpreter::new(&config.startup, true, &[]).unwrap();
+        (interpreter, config.default_limits())
+    }
+
+    #[test]
+    fn interrupts_programs_that_time_out() {
+        let (mut interpreter, limits) = interpreter();
+        let limits = Limits {
+            timeout: Duration::from_millis(100),
+            ..limits
+        };
+        let evaluation =
+            interpreter.run_string(&Request::new("(let loop () (loop))".into(), limits));
+        assert_eq!(evaluation.outcome(), "interrupted");
+        let message = evaluation
+            .error
+            .map(|error| error.message)
+            .unwrap_or_default();
+        assert!(
+            message.starts_with("evaluation interrupted: timed out"),
+            "{}",
+            message
+        );
+
+        // The interpreter is still usable afterwards.
+        let evaluation = interpreter.run_string(&Request::new("(+ 1 2)".into(), limits));
+        assert!(evaluation.succeeded(), "{:?}", evaluation.error);
+        assert_eq!(evaluation.values, vec!["3".to_string()]);
+    }
+}
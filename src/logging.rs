@@ -0,0 +1,33 @@
+//! Log output, through `tracing`.
+
+use std::env;
+use std::io;
+
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LoggingConfig;
+
+/// Where logs are written.
+#[derive(Clone, Copy)]
+pub enum Output {
+    Stdout,
+    /// For the sandbox child, whose stdout carries its replies.
+    Stderr,
+}
+
+/// Installs the global subscriber. `RUST_LOG` overrides the configured filter when it's set.
+pub fn init(config: &LoggingConfig, output: Output) -> Result<(), String> {
+    let filter = match env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(_) => EnvFilter::from_default_env(),
+        Err(_) => EnvFilter::try_new(&config.level)
+            .map_err(|e| format!("invalid log level {}: {}", config.level, e))?,
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match (output, config.json) {
+        (Output::Stdout, false) => builder.try_init(),
+        (Output::Stdout, true) => builder.json().try_init(),
+        (Output::Stderr, false) => builder.with_writer(io::stderr).try_init(),
+        (Output::Stderr, true) => builder.json().with_writer(io::stderr).try_init(),
+    };
+    result.map_err(|e| e.to_string())
+}
@@ -1,48 +1,2492 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod actions;
+mod aliases;
+mod bot;
+mod challenge;
+mod config;
+mod dispatch;
+mod drafts;
+mod error;
+mod features;
+mod forms;
+mod health;
+mod http;
+mod i18n;
+mod image;
+mod interpreter;
+mod logging;
+mod pairs;
+mod paste;
+mod preferences;
+mod presence;
+mod proposals;
+mod sandbox;
+mod stats;
+mod version;
+mod webhooks;
+mod worker;
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use std::{env, thread};
 
-use peroxide::Interpreter;
-use regex::Regex;
+use actions::{Action, Invocation};
+use aliases::AliasStore;
+use bot::{command_args, Bot, Mode, OwnerCommand, Route};
+use challenge::{Challenge, GuildChallenges, Judgement};
+use config::Config;
+use dispatch::Dispatcher;
+use drafts::{DraftKey, Drafts, Status, ABORT_COMMAND, ADDED_EMOJI, BEGIN_COMMAND, END_COMMAND};
+use features::FeatureStore;
+use i18n::{fill, Catalog, Language, LanguageStore};
+use image::Image;
+use interpreter::{string_literal, EvalError, Evaluation, Request, COMMAND_CALL};
+use pairs::{Pairs, Refusal};
+use preferences::{Ceilings, PreferenceStore, Preferences, SETTINGS};
+use proposals::{OpenProposals, PreludeStore, Proposal, APPROVE_EMOJI, REJECT_EMOJI};
+use stats::{Counters, Stats};
+use webhooks::Webhooks;
+use worker::{Job, SessionKey};
+
+use repl_bot::alloc::CountingAllocator;
+use repl_bot::cron;
+use repl_bot::format::{
+    self, code_block, format_duration, format_uptime, format_utc, LongOutput, Printing,
+    EMBED_FIELD_LIMIT, MESSAGE_LIMIT,
+};
+use repl_bot::limits::{LimitExceeded, Limits, Usage};
+use repl_bot::ratelimit::RateLimiter;
+use repl_bot::send;
+use repl_bot::transport::{SerenityEvent, Transport};
+use repl_bot::trigger::{self, MessageLink};
+
 use serenity::{
-    model::{channel::Message, gateway::Ready},
+    builder::CreateEmbed,
+    client::bridge::gateway::ShardManager,
+    http::Http,
+    model::{
+        channel::{Embed, Message, Reaction, ReactionType},
+        gateway::Ready,
+        id::{ChannelId, GuildId, MessageId, UserId},
+        user::User,
+    },
     prelude::*,
+    utils::Colour,
 };
-use std::sync::mpsc;
-use std::sync::mpsc::SyncSender;
-use std::time::Duration;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use tracing::{debug, error, info, info_span};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Reaction that re-evaluates the expression behind a result.
+const RERUN_EMOJI: &str = "🔁";
+/// Reaction that reveals the full source of an expression whose quote was truncated.
+const SOURCE_EMOJI: &str = "📜";
+/// Reaction, offered on results that were cut, that sends the whole result to whoever asked
+/// for it.
+const SHOW_MORE_EMOJI: &str = "➕";
+/// Reactions showing how an evaluation is going on the message that asked for it.
+const QUEUED_EMOJI: &str = "⏳";
+const SUCCEEDED_EMOJI: &str = "✅";
+const FAILED_EMOJI: &str = "❌";
+const TIMED_OUT_EMOJI: &str = "⏱️";
+/// Name of the attachment holding a whole result.
+const FULL_RESULT_FILE_NAME: &str = "result.txt";
+/// Name of the attachment holding the definitions of an environment.
+const EXPORT_FILE_NAME: &str = "definitions.scm";
+/// Largest file `¡import` loads.
+const MAX_IMPORT_BYTES: u64 = 256 * 1024;
+/// Name of the attachment holding a rendered image.
+const IMAGE_FILE_NAME: &str = "result.png";
+/// How many result messages we remember for reaction handling.
+const RESULT_HISTORY_SIZE: usize = 500;
+/// Characters of the value or error shown when a result is too large to send.
+const FALLBACK_LIMIT: usize = 1000;
+/// Commands that don't evaluate code. Catalogs describe them for `¡help`, in this order.
+const META_COMMANDS: [&str; 23] = [
+    "¡help",
+    "¡source",
+    "¡version",
+    "¡stats",
+    "¡reload",
+    "¡source-of",
+    "¡bindings",
+    "¡apropos",
+    "¡schedule",
+    "¡set",
+    "¡lang",
+    "¡alias",
+    "¡features",
+    "¡commit",
+    "¡export",
+    "¡import",
+    "¡propose",
+    "¡challenge",
+    "¡begin",
+    "¡pair",
+    "¡golf",
+    "¡test",
+    "¡submit",
+];
+/// Characters of a failing form shown in backtraces.
+const FRAME_CHARS: usize = 60;
+/// Longest name allowed for a bot command defined from Scheme.
+const MAX_COMMAND_NAME_LENGTH: usize = 32;
+/// How many users `¡stats` lists as the busiest.
+const BUSIEST_USERS: usize = 3;
+/// Most lines of input a program may read.
+const MAX_INPUT_LINES: usize = 20;
+/// How long an evaluation runs before we say it's still running.
+const PROGRESS_DELAY: Duration = Duration::from_secs(2);
+/// How often that message is updated; Discord rate limits edits.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+/// How many names `¡bindings` and `¡apropos` list per page.
+const BINDINGS_PER_PAGE: usize = 40;
+/// Set once the scheduler thread is started, as `ready` fires again on reconnections and
+/// on each shard.
+static SCHEDULER_STARTED: AtomicBool = AtomicBool::new(false);
+/// Most evaluations a guild can have scheduled at once.
+const MAX_SCHEDULED: usize = 20;
+/// Most aliases a guild can define.
+const MAX_ALIASES: usize = 50;
+/// How many users `¡challenge leaderboard` lists.
+const LEADERBOARD_SIZE: usize = 10;
+
+/// What we need to service reactions on a result message the bot posted.
+#[derive(Clone)]
+struct ResultRecord {
+    session: SessionKey,
+    command: String,
+    mode: Mode,
+    /// Whose preferences the result is shown with, if anyone's.
+    author: Option<UserId>,
+    /// Where the evaluation was asked for, for the guild's settings.
+    guild: Option<GuildId>,
+    /// The whole result, if the message had to cut it.
+    full: Option<Arc<String>>,
+    /// The closing brackets appended to `command`, if it left lists open and its author
+    /// asked for that.
+    auto_closed: Option<String>,
+    /// The result shown, as text, for a rerun to be compared with.
+    result: Option<Arc<String>>,
+    /// The result of the run this one reran, if it was a rerun.
+    rerun_of: Option<Arc<String>>,
+}
+
+/// Bounded map from result messages to the expression that produced them.
+#[derive(Default)]
+struct ResultHistory {
+    records: HashMap<MessageId, ResultRecord>,
+    order: VecDeque<MessageId>,
+}
+
+impl ResultHistory {
+    fn insert(&mut self, id: MessageId, record: ResultRecord) {
+        if self.order.len() >= RESULT_HISTORY_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.records.remove(&oldest);
+            }
+        }
+        self.order.push_back(id);
+        self.records.insert(id, record);
+    }
+
+    fn get(&self, id: MessageId) -> Option<&ResultRecord> {
+        self.records.get(&id)
+    }
+}
+
+/// Evaluates `request`. While the program wants more input, its author is asked for a line
+/// in `channel_id` and it is run again with the lines given so far.
+fn evaluate(
+    ctx: &Context,
+    session: SessionKey,
+    channel_id: ChannelId,
+    mut request: Request,
+    catalog: &'static Catalog,
+) -> Evaluation {
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    let deadline = Instant::now() + get_config(ctx).input_timeout;
+    loop {
+        let progress = Progress::start(ctx.http.clone(), channel_id, catalog);
+        let mut evaluation = dispatcher.evaluate(session, request.clone());
+        progress.finish();
+        let author = match &request.invocation {
+            Some(invocation) if evaluation.needs_input => invocation.author_id,
+            _ => return evaluation,
+        };
+        let failure = if request.inputs.len() >= MAX_INPUT_LINES {
+            fill(catalog.input_limit, &[&MAX_INPUT_LINES])
+        } else {
+            match wait_for_input(ctx, channel_id, author, deadline, catalog) {
+                Some(line) => {
+                    request.inputs.push(line);
+                    continue;
+                }
+                None => catalog.input_timeout.to_string(),
+            }
+        };
+        if let Some(error) = &mut evaluation.error {
+            error.message = failure;
+        }
+        return evaluation;
+    }
+}
+
+/// A "still running" message, posted once an evaluation has run for `PROGRESS_DELAY` and
+/// updated until it finishes.
+struct Progress {
+    done: mpsc::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Progress {
+    fn start(http: Arc<Http>, channel_id: ChannelId, catalog: &'static Catalog) -> Self {
+        let (done, finished) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let start = Instant::now();
+            let mut message = None;
+            let mut wait = PROGRESS_DELAY;
+            while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(wait) {
+                wait = PROGRESS_INTERVAL;
+                let elapsed = format!("{:.1}", start.elapsed().as_secs_f64());
+                let text = fill(catalog.still_running, &[&elapsed]);
+                let result = match message {
+                    None => channel_id.say(&http, text).map(|m| message = Some(m.id)),
+                    Some(id) => channel_id
+                        .edit_message(&http, id, |m| m.content(text))
+                        .map(|_| ()),
+                };
+                if let Err(why) = result {
+                    error!("Error updating progress: {:?}", why);
+                }
+            }
+            if let Some(id) = message {
+                if let Err(why) = channel_id.delete_message(&http, id) {
+                    error!("Error deleting progress message: {:?}", why);
+                }
+            }
+        });
+        Self { done, thread }
+    }
+
+    /// Removes the message, if it was posted.
+    fn finish(self) {
+        let _ = self.done.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// The reaction telling how `evaluation` ended.
+fn status_emoji(evaluation: &Evaluation) -> &'static str {
+    let interruption = evaluation
+        .error
+        .as_ref()
+        .and_then(|error| error.interruption.as_ref());
+    match interruption.map(|interruption| interruption.exceeded) {
+        Some(LimitExceeded::Timeout(_))
+        | Some(LimitExceeded::Cpu(_))
+        | Some(LimitExceeded::Preempted(_)) => TIMED_OUT_EMOJI,
+        _ if evaluation.succeeded() => SUCCEEDED_EMOJI,
+        _ => FAILED_EMOJI,
+    }
+}
+
+/// Reacts to `message_id` with `status`, replacing the bot's reaction `previous`.
+fn react_status(
+    ctx: &Context,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    previous: Option<&str>,
+    status: &str,
+) {
+    if let Some(previous) = previous {
+        let removed = send::with_retries(|| {
+            channel_id.delete_reaction(
+                &ctx.http,
+                message_id,
+                None,
+                ReactionType::Unicode(previous.into()),
+            )
+        });
+        if let Err(why) = removed {
+            error!("Error removing status reaction: {:?}", why);
+        }
+    }
+    let added = send::with_retries(|| {
+        channel_id.create_reaction(&ctx.http, message_id, ReactionType::Unicode(status.into()))
+    });
+    if let Err(why) = added {
+        error!("Error adding status reaction: {:?}", why);
+    }
+}
+
+/// Asks `author` for a line of input for their program, and waits until `deadline` for
+/// their next message in `channel_id`.
+fn wait_for_input(
+    ctx: &Context,
+    channel_id: ChannelId,
+    author: UserId,
+    deadline: Instant,
+    catalog: &Catalog,
+) -> Option<String> {
+    let (sender, receiver) = mpsc::sync_channel(1);
+    ctx.data
+        .read()
+        .get::<PendingInputContainer>()
+        .unwrap()
+        .lock()
+        .insert((channel_id, author), sender);
+    let prompt = fill(catalog.input_prompt, &[&author.mention()]);
+    ctx.say(channel_id, &prompt);
+    let line = receiver
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok();
+    ctx.data
+        .read()
+        .get::<PendingInputContainer>()
+        .unwrap()
+        .lock()
+        .remove(&(channel_id, author));
+    line
+}
+
+fn invocation(config: &Config, author: &User) -> Invocation {
+    Invocation {
+        author_id: author.id,
+        author_name: author.name.clone(),
+        max_actions: config.actions.max_per_evaluation,
+    }
+}
+
+/// Carries out the Discord actions an evaluation queued on behalf of `author`: messages go
+/// to `channel_id`, reactions to `message_id`. Actions over the author's rate limit are
+/// dropped, with a notice.
+fn perform_actions(
+    ctx: &Context,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    author: UserId,
+    actions: &[Action],
+    catalog: &Catalog,
+) {
+    let mut dropped = 0;
+    let mut retry_after = None;
+    for action in actions {
+        let limited = ctx
+            .data
+            .read()
+            .get::<ActionRateLimitContainer>()
+            .unwrap()
+            .lock()
+            .check(author);
+        if let Err(wait) = limited {
+            dropped += 1;
+            retry_after = Some(wait);
+            continue;
+        }
+        let result = send::with_retries(|| match action {
+            Action::Say(text) => channel_id.say(&ctx.http, text).map(|_| ()),
+            Action::React(emoji) => channel_id.create_reaction(
+                &ctx.http,
+                message_id,
+                ReactionType::Unicode(emoji.clone()),
+            ),
+        });
+        if let Err(why) = result {
+            error!("Error performing {:?}: {:?}", action, why);
+        }
+    }
+    if let Some(retry_after) = retry_after {
+        let notice = fill(
+            catalog.actions_skipped,
+            &[&dropped, &(retry_after.as_secs() + 1)],
+        );
+        ctx.say(channel_id, &notice);
+    }
+}
+
+/// Handles a message from someone writing a program with `¡begin`: adds it to their
+/// program, or ends it. Returns the program if it is to be evaluated.
+fn compose(ctx: &Context, msg: &Message, key: DraftKey, catalog: &Catalog) -> Option<String> {
+    let config = get_config(ctx);
+    let content = msg.content.trim();
+    let reply = {
+        let data = ctx.data.read();
+        let mut drafts = data.get::<DraftsContainer>().unwrap().lock();
+        match content {
+            END_COMMAND => match drafts.finish(key) {
+                Some(program) if !program.trim().is_empty() => return Some(program),
+                _ => Some(catalog.draft_empty.to_string()),
+            },
+            ABORT_COMMAND => {
+                drafts.abort(key);
+                Some(catalog.draft_aborted.to_string())
+            }
+            BEGIN_COMMAND => Some(catalog.draft_already.to_string()),
+            // A code block around the message is not part of the program.
+            _ if drafts.add(key, trigger::extract_direct(content)) => None,
+            _ => Some(fill(catalog.draft_too_long, &[&config.draft_max_chars])),
+        }
+    };
+    match reply {
+        Some(reply) => ctx.say(msg.channel_id, &reply),
+        None => {
+            let added = ReactionType::Unicode(ADDED_EMOJI.into());
+            if let Err(why) = send::with_retries(|| msg.react(ctx, added.clone())) {
+                error!("Error acknowledging a part of a program: {:?}", why);
+            }
+        }
+    }
+    None
+}
+
+/// Adds the bot commands an evaluation defined to the dispatch table. Commands with names
+/// that can't be used are moved to the evaluation's rejected commands.
+fn register_commands(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    owner: UserId,
+    evaluation: &mut Evaluation,
+) {
+    if evaluation.defined_commands.is_empty() {
+        return;
+    }
+    // Commands are called in their guild's environment.
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    let config = get_config(ctx);
+    let usable = |name: &str| usable_command_name(&config, guild_id, name);
+
+    let data = ctx.data.read();
+    let mut commands = data.get::<CustomCommandsContainer>().unwrap().lock();
+    let commands = commands.entry(guild_id).or_default();
+    let (defined, rejected) = evaluation
+        .defined_commands
+        .drain(..)
+        .partition::<Vec<_>, _>(|name| usable(name));
+    for name in &defined {
+        commands.insert(name.clone(), owner);
+    }
+    evaluation.defined_commands = defined;
+    evaluation.rejected_commands.extend(rejected);
+}
+
+/// Whether `name` can be given to a command defined in `guild_id`: it must be made of
+/// letters, digits and dashes, and not shadow a built-in command or a prefix.
+fn usable_command_name(config: &Config, guild_id: GuildId, name: &str) -> bool {
+    let prefixes = &config.triggers(Some(guild_id)).prefixes;
+    !name.is_empty()
+        && name.len() <= MAX_COMMAND_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !META_COMMANDS
+            .iter()
+            .any(|meta| meta.trim_start_matches('¡') == name)
+        && !prefixes.iter().any(|p| p.trim_start_matches('¡') == name)
+}
+
+/// Handles `¡alias`: lists the guild's aliases without arguments, else defines or removes
+/// one.
+fn alias_command(ctx: &Context, msg: &Message, guild_id: GuildId, args: &str, catalog: &Catalog) {
+    let config = get_config(ctx);
+    let data = ctx.data.read();
+    let mut aliases = data.get::<AliasesContainer>().unwrap().lock();
+    let mut words = args.splitn(2, char::is_whitespace);
+    let (name, rest) = (
+        words.next().unwrap_or(""),
+        words.next().unwrap_or("").trim(),
+    );
+    let saved = |result: Result<String, String>| {
+        result.unwrap_or_else(|e| {
+            error!("Error saving aliases: {}", e);
+            fill(catalog.alias_save_failed, &[&e])
+        })
+    };
+    let reply = if args.is_empty() {
+        match aliases
+            .guild(guild_id)
+            .filter(|defined| !defined.is_empty())
+        {
+            Some(defined) => {
+                let list = defined
+                    .iter()
+                    .map(|(name, expansion)| {
+                        format!("`¡{}` → {}", name, code_block("", expansion, 200))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                fill(catalog.aliases_list, &[&list])
+            }
+            None => catalog.aliases_none.to_string(),
+        }
+    } else if !config.is_admin(msg.author.id) {
+        catalog.alias_denied.to_string()
+    } else if name == "remove" && !rest.is_empty() {
+        let name = rest.trim_start_matches('¡');
+        saved(aliases.remove(guild_id, name).map(|removed| {
+            if removed {
+                fill(catalog.alias_removed, &[&name])
+            } else {
+                fill(catalog.no_such_alias, &[&name])
+            }
+        }))
+    } else if rest.is_empty() {
+        catalog.alias_usage.to_string()
+    } else {
+        let name = name.trim_start_matches('¡');
+        let defined = aliases.guild(guild_id);
+        let count = defined.map_or(0, |defined| defined.len());
+        let exists = defined.is_some_and(|defined| defined.contains_key(name));
+        if !usable_command_name(&config, guild_id, name) {
+            catalog.alias_invalid.to_string()
+        } else if !exists && count >= MAX_ALIASES {
+            fill(catalog.too_many_aliases, &[&MAX_ALIASES])
+        } else {
+            saved(
+                aliases
+                    .set(guild_id, name, rest)
+                    .map(|()| fill(catalog.alias_set, &[&name, &rest])),
+            )
+        }
+    };
+    drop(aliases);
+    drop(data);
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// If `content` invokes a bot command defined from Scheme, the code that runs it.
+fn custom_command_call(ctx: &Context, guild_id: GuildId, content: &str) -> Option<String> {
+    let mut words = content.strip_prefix('¡')?.split_whitespace();
+    let name = words.next()?;
+    let defined = ctx
+        .data
+        .read()
+        .get::<CustomCommandsContainer>()
+        .unwrap()
+        .lock()
+        .get(&guild_id)
+        .is_some_and(|commands| commands.contains_key(name));
+    if !defined {
+        return None;
+    }
+    let args = words.map(string_literal).collect::<Vec<_>>().join(" ");
+    Some(format!(
+        "{}{} (list {}))",
+        COMMAND_CALL,
+        string_literal(name),
+        args
+    ))
+}
+
+/// Logs how an evaluation went, in the current span.
+fn log_evaluation(evaluation: &Evaluation) {
+    info!(
+        duration_ms = evaluation.elapsed.as_millis() as u64,
+        outcome = evaluation.outcome(),
+        values = ?evaluation.values,
+        error = ?evaluation.error.as_ref().map(|e| &e.message),
+        "evaluated"
+    );
+}
+
+fn user_preferences(ctx: &Context, user: UserId) -> Preferences {
+    ctx.data
+        .read()
+        .get::<PreferencesContainer>()
+        .unwrap()
+        .lock()
+        .get(user)
+}
+
+/// The limits for evaluations by `user` in `guild`: those of their tier, with the timeout
+/// they set if it's shorter.
+fn user_limits(ctx: &Context, guild_id: Option<GuildId>, user: UserId) -> Limits {
+    let mut limits = get_config(ctx).limits(guild_id, user);
+    if let Some(timeout) = user_preferences(ctx, user).timeout {
+        limits.timeout = limits.timeout.min(timeout);
+    }
+    limits
+}
+
+/// How results of evaluations by `user` in `guild` are printed: as they chose, else as the
+/// guild's are.
+fn user_printing(ctx: &Context, guild_id: Option<GuildId>, user: Option<UserId>) -> Printing {
+    let defaults = get_config(ctx).printing(guild_id);
+    match user {
+        Some(user) => user_preferences(ctx, user).printing(defaults),
+        None => defaults,
+    }
+}
+
+/// Handles `¡set`: shows the author's settings without arguments, else changes one.
+fn set_command(ctx: &Context, msg: &Message, args: &str, catalog: &Catalog) {
+    let config = get_config(ctx);
+    let mut preferences = user_preferences(ctx, msg.author.id);
+    let mut words = args.split_whitespace();
+    let reply = match (words.next(), words.next(), words.next()) {
+        (None, _, _) => {
+            let settings = SETTINGS
+                .iter()
+                .zip(&catalog.settings)
+                .map(|(name, description)| format!("`{}`: {}", name, description))
+                .collect::<Vec<_>>()
+                .join("\n");
+            fill(
+                catalog.settings_shown,
+                &[
+                    &code_block("", &preferences.describe(catalog), EMBED_FIELD_LIMIT),
+                    &settings,
+                ],
+            )
+        }
+        (Some(name), Some(value), None) => {
+            let ceilings = Ceilings {
+                print_length: config.printing(msg.guild_id).max_chars,
+                timeout: config.limits(msg.guild_id, msg.author.id).timeout,
+            };
+            let stored = preferences
+                .set(name, value, &ceilings, catalog)
+                .and_then(|()| {
+                    ctx.data
+                        .read()
+                        .get::<PreferencesContainer>()
+                        .unwrap()
+                        .lock()
+                        .set(msg.author.id, preferences)
+                });
+            match stored {
+                Ok(()) => fill(catalog.setting_set, &[&name, &value]),
+                Err(e) => fill(catalog.setting_failed, &[&name, &e]),
+            }
+        }
+        _ => catalog.set_usage.to_string(),
+    };
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// The messages of the language spoken in `guild_id`: the one it chose, else the
+/// configured one.
+fn guild_catalog(ctx: &Context, guild_id: Option<GuildId>) -> &'static Catalog {
+    let chosen = guild_id.and_then(|guild_id| {
+        ctx.data
+            .read()
+            .get::<LanguagesContainer>()
+            .unwrap()
+            .lock()
+            .get(guild_id)
+    });
+    chosen.unwrap_or_else(|| get_config(ctx).language).catalog()
+}
+
+/// Handles `¡lang`: shows the guild's language without arguments, else changes it.
+fn lang_command(ctx: &Context, msg: &Message, guild_id: GuildId, args: &str, catalog: &Catalog) {
+    let available = Language::ALL
+        .iter()
+        .map(|language| format!("`{}` ({})", language.code(), language.catalog().name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let reply = if args.is_empty() {
+        fill(catalog.lang_current, &[&catalog.name, &available])
+    } else if !get_config(ctx).is_admin(msg.author.id) {
+        catalog.lang_denied.to_string()
+    } else {
+        match Language::parse(args) {
+            None => fill(catalog.lang_unknown, &[&args, &available]),
+            Some(language) => {
+                let stored = ctx
+                    .data
+                    .read()
+                    .get::<LanguagesContainer>()
+                    .unwrap()
+                    .lock()
+                    .set(guild_id, language);
+                match stored {
+                    Ok(()) => fill(language.catalog().lang_set, &[&language.catalog().name]),
+                    Err(e) => {
+                        error!("Error saving languages: {}", e);
+                        fill(catalog.lang_save_failed, &[&e])
+                    }
+                }
+            }
+        }
+    };
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// Posts the result of an evaluation as an embed, with reactions to rerun it or show its
+/// source, and remembers it so those reactions can be serviced.
+fn post_result(
+    ctx: &Context,
+    channel_id: ChannelId,
+    mut record: ResultRecord,
+    evaluation: Evaluation,
+) {
+    let config = get_config(ctx);
+    let catalog = guild_catalog(ctx, record.guild);
+    let preferences = record
+        .author
+        .map(|author| user_preferences(ctx, author))
+        .unwrap_or_default();
+    let limit = user_printing(ctx, record.guild, record.author)
+        .max_chars
+        .min(EMBED_FIELD_LIMIT);
+    let input = preferences
+        .quote_input
+        .unwrap_or_else(|| config.quote_input(record.guild))
+        .quote(&record.command)
+        .map(|quoted| code_block("scheme", &quoted, limit));
+    // Long output is cut after a few lines, and the rest hidden or sent separately.
+    let (shown_output, more_output) = match config.long_output {
+        LongOutput::Inline => (evaluation.output.as_str(), None),
+        _ => format::split_lines(&evaluation.output, config.long_output_lines),
+    };
+    let output = if evaluation.output.is_empty() {
+        None
+    } else {
+        Some(code_block("", shown_output, limit))
+    };
+    let (more_output, follow_up) = match (config.long_output, more_output) {
+        (LongOutput::Spoiler, Some(more)) => (
+            Some(format!(
+                "||{}||",
+                code_block("", more, limit.saturating_sub(4))
+            )),
+            None,
+        ),
+        (LongOutput::FollowUp, Some(more)) => (
+            Some(catalog.more_output_follows.to_string()),
+            Some(code_block("", more, MESSAGE_LIMIT)),
+        ),
+        _ => (None, None),
+    };
+    let elapsed = format_duration(evaluation.elapsed);
+    let footer = if evaluation.cached {
+        fill(catalog.footer_cached, &[&elapsed])
+    } else {
+        fill(catalog.footer_evaluated, &[&elapsed])
+    };
+    // Images are shown as a placeholder, and the last one is attached to the reply.
+    let mut picture = None;
+    let shown_values = evaluation
+        .values
+        .iter()
+        .map(|value| match Image::parse(value) {
+            None => value.clone(),
+            Some(Ok(image)) => {
+                let placeholder = format!("#<image {}x{}>", image.width, image.height);
+                picture = Some(image.render());
+                placeholder
+            }
+            Some(Err(e)) => {
+                picture = Some(Err(e));
+                "#<invalid image>".to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    let values = join_values(&shown_values, evaluation.form_count);
+
+    // What was cut can be asked for with a reaction, without running the program again.
+    let cut = !evaluation.full_values.is_empty()
+        || [values.as_str(), &evaluation.output]
+            .iter()
+            .any(|text| text.chars().count() > limit)
+        || more_output.is_some();
+    record.full = if cut {
+        let mut full_values = evaluation.values.clone();
+        for (index, full) in &evaluation.full_values {
+            full_values[*index] = full.clone();
+        }
+        Some(Arc::new(format!(
+            ";; Result\n{}\n\n;; Output\n{}",
+            join_values(&full_values, evaluation.form_count),
+            evaluation.output
+        )))
+    } else {
+        None
+    };
+
+    // What doesn't fit in the reply is uploaded, if a paste service is configured.
+    let paste_config = &config.paste;
+    let overflowing = [record.command.as_str(), &values, &evaluation.output]
+        .iter()
+        .any(|text| text.chars().count() > limit);
+    let full_result = if overflowing && paste_config.enabled && paste_config.upload_url.is_some() {
+        let full = format!(
+            ";; Input\n{}\n\n;; Result\n{}\n\n;; Output\n{}",
+            record.command, values, evaluation.output
+        );
+        Some(paste::upload(paste_config, &full).unwrap_or_else(|e| {
+            error!("Error uploading result: {}", e);
+            fill(catalog.upload_failed, &[&e])
+        }))
+    } else {
+        None
+    };
+
+    let rejected = if evaluation.rejected_commands.is_empty() {
+        None
+    } else {
+        Some(fill(
+            catalog.commands_rejected,
+            &[&command_list(&evaluation.rejected_commands)],
+        ))
+    };
+    let fallback = fill(
+        catalog.result_too_large,
+        &[&match &evaluation.error {
+            Some(error) => code_block(
+                "",
+                &describe_error(catalog, &evaluation, error),
+                FALLBACK_LIMIT,
+            ),
+            None => code_block("scheme", &values, FALLBACK_LIMIT),
+        }],
+    );
+    // A rerun shows what changed since the run it reran, as redefinitions may have.
+    let mut shown = values.clone();
+    if let Some(error) = &evaluation.error {
+        shown = format!("{}\n{}", shown, describe_error(catalog, &evaluation, error));
+    }
+    if !evaluation.output.is_empty() {
+        shown = format!("{}\n{}", shown, evaluation.output);
+    }
+    let changes = record
+        .rerun_of
+        .as_ref()
+        .and_then(|previous| format::diff_lines(previous, &shown))
+        .map(|diff| code_block("diff", &diff, limit));
+    record.result = Some(Arc::new(shown));
+    let mut reactions = vec![
+        ReactionType::Unicode(RERUN_EMOJI.into()),
+        ReactionType::Unicode(SOURCE_EMOJI.into()),
+    ];
+    if cut {
+        reactions.push(ReactionType::Unicode(SHOW_MORE_EMOJI.into()));
+    }
+    let build_embed = |e: &mut CreateEmbed| {
+        if let Some(input) = &input {
+            e.field(catalog.field_input, input, false);
+        }
+        if let Some(closers) = &record.auto_closed {
+            e.field(
+                catalog.field_auto_closed,
+                fill(catalog.auto_closed, &[closers]),
+                false,
+            );
+        }
+        let mut failed = evaluation.error.is_some();
+        if record.mode == Mode::Test {
+            match &evaluation.test_report {
+                Some(report) => {
+                    e.description(fill(
+                        catalog.tests_summary,
+                        &[&report.passed, &report.failures.len()],
+                    ));
+                    if !report.failures.is_empty() {
+                        failed = true;
+                        e.field(
+                            catalog.field_failures,
+                            code_block("scheme", &report.failures.join("\n"), limit),
+                            false,
+                        );
+                    }
+                }
+                None => {
+                    e.description(catalog.no_checks);
+                }
+            }
+        } else if !evaluation.values.is_empty() {
+            e.field(
+                catalog.field_result,
+                code_block("scheme", &values, limit),
+                false,
+            );
+        }
+        if let Some(error) = &evaluation.error {
+            e.field(
+                catalog.field_error,
+                code_block("", &describe_error(catalog, &evaluation, error), limit),
+                false,
+            );
+        }
+        e.colour(if failed {
+            Colour::RED
+        } else {
+            Colour::DARK_GREEN
+        });
+        if let Some(output) = &output {
+            e.field(catalog.field_output, output, false);
+        }
+        if let Some(more_output) = &more_output {
+            e.field(catalog.field_more_output, more_output, false);
+        }
+        if let Some(changes) = &changes {
+            e.field(catalog.field_changed, changes, false);
+        }
+        if record.mode == Mode::Golf {
+            e.field(
+                catalog.field_size,
+                fill(
+                    catalog.code_size,
+                    &[&code_size(&record.command), &record.command.trim().len()],
+                ),
+                false,
+            );
+        }
+        match &picture {
+            Some(Ok(_)) => {
+                e.image(format!("attachment://{}", IMAGE_FILE_NAME));
+            }
+            Some(Err(error)) => {
+                e.field(catalog.field_image, code_block("", error, limit), false);
+            }
+            None => {}
+        }
+        if !evaluation.defined_commands.is_empty() {
+            e.field(
+                catalog.field_commands_defined,
+                command_list(&evaluation.defined_commands),
+                false,
+            );
+        }
+        if let Some(rejected) = &rejected {
+            e.field(catalog.field_commands_ignored, rejected, false);
+        }
+        if !evaluation.replay_errors.is_empty() {
+            let failures = evaluation.replay_errors.join("\n\n");
+            e.field(
+                catalog.field_replay_errors,
+                code_block("", &failures, limit),
+                false,
+            );
+        }
+        if let Some(full_result) = &full_result {
+            e.field(catalog.field_full_result, full_result, false);
+        }
+        e.footer(|f| f.text(&footer));
+    };
+    // Webhook messages can't carry the image here, so those results are sent normally.
+    let through_webhook = match (record.guild, record.author) {
+        (Some(_), Some(author)) if config.webhook_results && !matches!(picture, Some(Ok(_))) => {
+            let embed = Embed::fake(|e| {
+                build_embed(e);
+                e
+            });
+            post_through_webhook(ctx, channel_id, author, &reactions, embed)
+        }
+        _ => None,
+    };
+    let posted = through_webhook.or_else(|| {
+        send::send(
+            &ctx.http,
+            channel_id,
+            |m| {
+                m.embed(|e| {
+                    build_embed(e);
+                    e
+                })
+                .reactions(reactions.clone());
+                if let Some(Ok(png)) = &picture {
+                    m.add_file((png.as_slice(), IMAGE_FILE_NAME));
+                }
+                m
+            },
+            &fallback,
+        )
+    });
+    if let Some(message) = posted {
+        ctx.data
+            .write()
+            .get_mut::<ResultStore>()
+            .unwrap()
+            .insert(message.id, record);
+        if let Some(follow_up) = follow_up {
+            ctx.say(channel_id, &follow_up);
+        }
+    }
+}
+
+/// Posts a result through the webhook of `channel_id`, under the name and avatar of `author`,
+/// and adds `reactions` to it. Returns `None` if it couldn't, for the result to be sent
+/// normally.
+fn post_through_webhook(
+    ctx: &Context,
+    channel_id: ChannelId,
+    author: UserId,
+    reactions: &[ReactionType],
+    embed: serde_json::Value,
+) -> Option<Message> {
+    let user = author
+        .to_user(ctx)
+        .map_err(|why| error!("Error fetching user: {:?}", why))
+        .ok()?;
+    let store = ctx.data.read().get::<WebhooksContainer>().unwrap().clone();
+    let webhook = store.lock().get(&ctx.http, channel_id)?;
+    let message = match webhooks::post(&ctx.http, &webhook, &user, &embed) {
+        Ok(message) => message,
+        Err(why) => {
+            error!("Error posting result through webhook: {:?}", why);
+            store.lock().forget(channel_id);
+            return None;
+        }
+    };
+    for reaction in reactions {
+        let added = send::with_retries(|| {
+            channel_id.create_reaction(&ctx.http, message.id, reaction.clone())
+        });
+        if let Err(why) = added {
+            error!("Error adding reaction: {:?}", why);
+        }
+    }
+    Some(message)
+}
+
+/// The values of a program's forms, one per line, numbered when there are several forms so
+/// they can be told apart.
+fn join_values(values: &[String], form_count: usize) -> String {
+    if form_count > 1 {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("{}. {}", i + 1, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        values.join("\n")
+    }
+}
+
+fn command_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("`¡{}`", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Size of code for golfing purposes, in characters, ignoring surrounding whitespace.
+fn code_size(code: &str) -> usize {
+    code.trim().chars().count()
+}
+
+/// Error message, followed by a backtrace, innermost frame first, and where an interrupted
+/// program was. peroxide only reports a message, so the one frame we know of is the failing
+/// top-level form, shown when the program has several forms or lines.
+fn describe_error(catalog: &Catalog, evaluation: &Evaluation, error: &EvalError) -> String {
+    let mut description = match &error.interruption {
+        Some(interruption) => fill(
+            catalog.interrupted,
+            &[&describe_limit(catalog, interruption.exceeded)],
+        ),
+        None => error.message.clone(),
+    };
+    let (index, source) = match &error.form {
+        Some(form) => form,
+        None => return description,
+    };
+    let frame = format::truncate(source.lines().next().unwrap_or(""), FRAME_CHARS);
+    if let Some(line) = error.line {
+        if evaluation.form_count > 1 || source.contains('\n') || line > 1 {
+            description.push_str(&fill(
+                catalog.backtrace,
+                &[&(index + 1), &evaluation.form_count, &line, &frame],
+            ));
+        }
+    }
+    if let Some(interruption) = &error.interruption {
+        description.push('\n');
+        description.push_str(&fill(
+            catalog.interrupted_in,
+            &[
+                &frame,
+                &describe_usage(catalog, &interruption.usage),
+                &evaluation.values.len(),
+            ],
+        ));
+    }
+    description
+}
+
+fn describe_limit(catalog: &Catalog, exceeded: LimitExceeded) -> String {
+    match exceeded {
+        LimitExceeded::Timeout(limit) => fill(catalog.timed_out, &[&limit.as_secs()]),
+        LimitExceeded::Cpu(limit) => fill(catalog.out_of_cpu, &[&limit.as_millis()]),
+        LimitExceeded::Memory(limit) => fill(catalog.out_of_memory, &[&(limit / (1024 * 1024))]),
+        LimitExceeded::Preempted(after) => fill(catalog.preempted, &[&after.as_secs()]),
+    }
+}
+
+fn describe_usage(catalog: &Catalog, usage: &Usage) -> String {
+    let mut description = fill(
+        catalog.usage_elapsed,
+        &[&format!("{:.2}", usage.elapsed.as_secs_f64())],
+    );
+    if let Some(cpu) = usage.cpu {
+        description.push_str(&fill(
+            catalog.usage_cpu,
+            &[&format!("{:.2}", cpu.as_secs_f64())],
+        ));
+    }
+    description.push_str(&fill(catalog.usage_memory, &[&(usage.memory / 1024)]));
+    description
+}
+
+/// Replies with a description of how to use the bot, built from the running configuration.
+fn send_help(
+    ctx: &Context,
+    channel_id: ChannelId,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    catalog: &Catalog,
+) {
+    let config = get_config(ctx);
+    let user_limits = config.limits(guild_id, user_id);
+    let bot_id = ctx.cache.read().user.id;
+    let prefixes = config
+        .triggers(guild_id)
+        .prefixes
+        .iter()
+        .map(|p| fill(catalog.help_prefix, &[p]))
+        .collect::<Vec<_>>()
+        .join(catalog.help_prefix_separator);
+    let evaluating = fill(catalog.help_evaluating, &[&prefixes, &bot_id.mention()]);
+    let limits = fill(
+        catalog.help_limits,
+        &[
+            &user_limits.timeout.as_secs(),
+            &user_limits.cpu_fuel.as_millis(),
+            &(user_limits.memory_fuel / (1024 * 1024)),
+            &user_printing(ctx, guild_id, Some(user_id)).max_chars,
+        ],
+    );
+    let mut commands = META_COMMANDS
+        .iter()
+        .zip(catalog.commands.iter())
+        .map(|(name, description)| format!("`{}`: {}", name, description))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut custom_commands = guild_id
+        .and_then(|guild_id| {
+            ctx.data
+                .read()
+                .get::<CustomCommandsContainer>()
+                .unwrap()
+                .lock()
+                .get(&guild_id)
+                .map(|commands| commands.keys().cloned().collect::<Vec<_>>())
+        })
+        .unwrap_or_default();
+    custom_commands.sort();
+    commands.push_str(catalog.help_define_commands);
+    if !custom_commands.is_empty() {
+        commands.push_str(&fill(
+            catalog.help_defined_so_far,
+            &[&command_list(&custom_commands)],
+        ));
+    }
+    let reactions = fill(
+        catalog.help_reactions,
+        &[&RERUN_EMOJI, &SOURCE_EMOJI, &SHOW_MORE_EMOJI],
+    );
+
+    let mut description = fill(catalog.help_description, &[&config.channel_name]);
+    if config.dm.enabled {
+        description.push_str(&fill(
+            catalog.help_dm,
+            &[
+                &config.dm.rate_limit,
+                &config.dm.rate_limit_window.as_secs(),
+            ],
+        ));
+    }
+
+    send::send(
+        &ctx.http,
+        channel_id,
+        |m| {
+            m.embed(|e| {
+                e.title(catalog.help_title)
+                    .description(&description)
+                    .field(catalog.field_evaluating, &evaluating, false)
+                    .field(catalog.field_limits, &limits, false)
+                    .field(catalog.field_commands, &commands, false)
+                    .field(catalog.field_reactions, &reactions, false)
+            })
+        },
+        &description,
+    );
+}
+
+fn record_stats(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    evaluation: &Evaluation,
+) {
+    let data = ctx.data.read();
+    data.get::<StatsContainer>()
+        .unwrap()
+        .lock()
+        .record(guild_id, user_id, evaluation);
+}
+
+/// Posts who ran `evaluation`, where, and how it went to the log channel of `guild_id`, if
+/// it has one.
+fn mirror_evaluation(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    user_id: UserId,
+    command: &str,
+    evaluation: &Evaluation,
+) {
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    let log_channel = match get_config(ctx).log_channel(guild_id) {
+        Some(log_channel) => log_channel,
+        None => return,
+    };
+    let catalog = guild_catalog(ctx, Some(guild_id));
+    let user = format!("{} ({})", user_id.mention(), user_id);
+    let input = code_block("scheme", command, EMBED_FIELD_LIMIT);
+    let (outcome_field, outcome) = match &evaluation.error {
+        Some(error) => (
+            catalog.field_error,
+            code_block(
+                "",
+                &describe_error(catalog, evaluation, error),
+                EMBED_FIELD_LIMIT,
+            ),
+        ),
+        None => (
+            catalog.field_result,
+            code_block(
+                "scheme",
+                &join_values(&evaluation.values, evaluation.form_count),
+                EMBED_FIELD_LIMIT,
+            ),
+        ),
+    };
+    let duration = format_duration(evaluation.elapsed);
+    // Embeds don't notify the users they mention, but messages do.
+    let fallback = format!(
+        "{} {} {}\n{}",
+        user_id,
+        channel_id.mention(),
+        duration,
+        code_block("scheme", command, 500)
+    );
+
+    send::send(
+        &ctx.http,
+        log_channel,
+        |m| {
+            m.embed(|e| {
+                e.title(catalog.mirror_title)
+                    .colour(if evaluation.succeeded() {
+                        Colour::DARK_GREEN
+                    } else {
+                        Colour::RED
+                    })
+                    .field(catalog.field_user, &user, true)
+                    .field(catalog.field_channel, channel_id.mention(), true)
+                    .field(catalog.field_duration, &duration, true)
+                    .field(catalog.field_input, &input, false)
+                    .field(outcome_field, &outcome, false)
+            })
+        },
+        &fallback,
+    );
+}
+
+/// Replaces the session's environment with a fresh one, reporting any error loading the
+/// standard library.
+fn reload(ctx: &Context, channel_id: ChannelId, session: SessionKey, catalog: &Catalog) {
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    let reply = match dispatcher.reset(session) {
+        Ok(()) => {
+            if let SessionKey::Guild(guild_id) = session {
+                restore_guild(ctx, &dispatcher, &get_config(ctx), guild_id);
+            }
+            catalog.reload_done.to_string()
+        }
+        Err(e) => fill(
+            catalog.reload_failed,
+            &[&code_block("", &e, EMBED_FIELD_LIMIT)],
+        ),
+    };
+    ctx.say(channel_id, &reply);
+}
+
+/// Gets a guild's environment that was just replaced with a fresh one going again: commands
+/// defined from Scheme lived in the old environment, and the prelude needs running again.
+fn restore_guild(ctx: &Context, dispatcher: &Dispatcher, config: &Config, guild_id: GuildId) {
+    let data = ctx.data.read();
+    data.get::<CustomCommandsContainer>()
+        .unwrap()
+        .lock()
+        .remove(&guild_id);
+    let preludes = data.get::<PreludesContainer>().unwrap().lock();
+    install_prelude(dispatcher, config, guild_id, preludes.guild(guild_id));
+}
+
+/// Handles `¡features`: lists the features the guild can turn on without arguments, else
+/// turns one on or off, or goes back to the configured ones.
+fn features_command(
+    ctx: &Context,
+    msg: &Message,
+    guild_id: GuildId,
+    args: &str,
+    catalog: &Catalog,
+) {
+    let config = get_config(ctx);
+    let names = config.feature_names();
+    let chosen = ctx
+        .data
+        .read()
+        .get::<FeaturesContainer>()
+        .unwrap()
+        .lock()
+        .guild(guild_id)
+        .cloned();
+    let current = chosen.unwrap_or_else(|| config.features(guild_id).iter().cloned().collect());
+    let mut words = args.split_whitespace();
+    let (action, name) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+    let switch = (action == "on" || action == "off") && !name.is_empty();
+    let reply = if args.is_empty() {
+        let list = names
+            .iter()
+            .map(|name| {
+                if current.contains(*name) {
+                    format!("**{}**", name)
+                } else {
+                    name.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        fill(catalog.features_list, &[&list])
+    } else if !config.is_admin(msg.author.id) {
+        catalog.features_denied.to_string()
+    } else if action == "reset" && name.is_empty() {
+        change_features(ctx, &config, guild_id, None, catalog)
+    } else if switch && names.contains(&name) {
+        let mut wanted = current;
+        if action == "on" {
+            wanted.insert(name.to_string());
+        } else {
+            wanted.remove(name);
+        }
+        change_features(ctx, &config, guild_id, Some(wanted), catalog)
+    } else if switch {
+        fill(catalog.feature_unknown, &[&name, &names.join(", ")])
+    } else {
+        catalog.features_usage.to_string()
+    };
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// Rebuilds the environment of `guild_id` with the features `wanted`, or the configured
+/// ones, and saves the choice if that works. Returns what to tell the admin who asked.
+fn change_features(
+    ctx: &Context,
+    config: &Config,
+    guild_id: GuildId,
+    wanted: Option<BTreeSet<String>>,
+    catalog: &Catalog,
+) -> String {
+    let features = match &wanted {
+        Some(wanted) => wanted.iter().cloned().collect::<Vec<_>>(),
+        None => config.features(guild_id).to_vec(),
+    };
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    if let Err(e) = dispatcher.set_features(guild_id, features.clone()) {
+        return fill(
+            catalog.features_failed,
+            &[&code_block("", &e, EMBED_FIELD_LIMIT)],
+        );
+    }
+    restore_guild(ctx, &dispatcher, config, guild_id);
+    let saved = {
+        let data = ctx.data.read();
+        let mut store = data.get::<FeaturesContainer>().unwrap().lock();
+        match wanted {
+            Some(wanted) => store.set(guild_id, wanted),
+            None => store.remove(guild_id),
+        }
+    };
+    if let Err(e) = saved {
+        error!("Error saving features: {}", e);
+        return fill(catalog.features_save_failed, &[&e]);
+    }
+    let on = if features.is_empty() {
+        catalog.features_none.to_string()
+    } else {
+        features.join(", ")
+    };
+    fill(catalog.features_changed, &[&on])
+}
+
+/// Handles `¡pair`: starts a pair session with the user mentioned, or tells the author
+/// about theirs.
+fn pair_command(ctx: &Context, msg: &Message, guild_id: GuildId, args: &str, catalog: &Catalog) {
+    let config = get_config(ctx);
+    let bot_id = ctx.cache.read().user.id;
+    let partner = msg.mentions.iter().find(|user| user.id != bot_id);
+    let data = ctx.data.read();
+    let mut pairs = data.get::<PairsContainer>().unwrap().lock();
+    let reply = match partner {
+        None if args.is_empty() => match pairs.get(msg.channel_id, msg.author.id) {
+            Some((_, pair)) => fill(
+                catalog.pair_current,
+                &[&pair.members[0].mention(), &pair.members[1].mention()],
+            ),
+            None => catalog.pair_usage.to_string(),
+        },
+        None => catalog.pair_usage.to_string(),
+        Some(partner) if partner.id == msg.author.id => catalog.pair_self.to_string(),
+        Some(partner) if partner.bot => catalog.pair_bot.to_string(),
+        Some(partner) => {
+            let members = [msg.author.id, partner.id];
+            match pairs.start(guild_id, msg.channel_id, members) {
+                Ok(_) => fill(
+                    catalog.pair_started,
+                    &[
+                        &msg.author.mention(),
+                        &partner.mention(),
+                        &(config.pairs.idle.as_secs() / 60),
+                    ],
+                ),
+                Err(refusal) => pair_refusal(refusal, catalog),
+            }
+        }
+    };
+    drop(pairs);
+    drop(data);
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// Handles `¡handoff`: gives the author's place in their pair session to the user mentioned.
+fn hand_off(ctx: &Context, msg: &Message, catalog: &Catalog) {
+    let bot_id = ctx.cache.read().user.id;
+    let reply = match msg.mentions.iter().find(|user| user.id != bot_id) {
+        None => catalog.handoff_usage.to_string(),
+        Some(to) if to.bot => catalog.pair_bot.to_string(),
+        Some(to) => {
+            let data = ctx.data.read();
+            let mut pairs = data.get::<PairsContainer>().unwrap().lock();
+            let handed_off = pairs.hand_off(msg.channel_id, msg.author.id, to.id);
+            match handed_off {
+                Ok(Some(pair)) => fill(
+                    catalog.pair_handed_off,
+                    &[
+                        &msg.author.mention(),
+                        &to.mention(),
+                        &pair.partner_of(to.id).mention(),
+                    ],
+                ),
+                Ok(None) => catalog.pair_none.to_string(),
+                Err(refusal) => pair_refusal(refusal, catalog),
+            }
+        }
+    };
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// Handles `¡leave`: ends the author's pair session.
+fn leave_pair(ctx: &Context, msg: &Message, catalog: &Catalog) {
+    let left = ctx
+        .data
+        .read()
+        .get::<PairsContainer>()
+        .unwrap()
+        .lock()
+        .leave(msg.channel_id, msg.author.id);
+    let reply = match left {
+        Some(pair) => fill(
+            catalog.pair_left,
+            &[
+                &msg.author.mention(),
+                &pair.partner_of(msg.author.id).mention(),
+            ],
+        ),
+        None => catalog.pair_none.to_string(),
+    };
+    ctx.say(msg.channel_id, &reply);
+}
+
+fn pair_refusal(refusal: Refusal, catalog: &Catalog) -> String {
+    match refusal {
+        Refusal::Busy(user) => fill(catalog.pair_busy, &[&user.mention()]),
+        Refusal::Full => catalog.pairs_full.to_string(),
+    }
+}
+
+/// A recurring evaluation registered with `¡schedule`.
+struct Scheduled {
+    id: u32,
+    /// The cron schedule as the admin wrote it.
+    spec: String,
+    schedule: cron::Schedule,
+    code: String,
+    /// The guild it was scheduled in, whose environment it runs in and whose channels get
+    /// its results.
+    guild_id: GuildId,
+    /// Where it was scheduled from; results are posted there unless a channel is configured.
+    channel_id: ChannelId,
+    author: UserId,
+}
+
+#[derive(Default)]
+struct Schedules {
+    next_id: u32,
+    scheduled: Vec<Scheduled>,
+}
+
+/// Handles `¡schedule`, `¡schedule list` and `¡schedule remove <id>`.
+fn schedule_command(
+    ctx: &Context,
+    msg: &Message,
+    guild_id: GuildId,
+    args: &str,
+    catalog: &Catalog,
+) {
+    let config = get_config(ctx);
+    let data = ctx.data.read();
+    let mut schedules = data.get::<SchedulesContainer>().unwrap().lock();
+    let reply = if args == "list" {
+        let scheduled = schedules
+            .scheduled
+            .iter()
+            .filter(|s| s.guild_id == guild_id)
+            .collect::<Vec<_>>();
+        if scheduled.is_empty() {
+            catalog.nothing_scheduled.to_string()
+        } else {
+            scheduled
+                .iter()
+                .map(|s| {
+                    let channel = config.schedule_channel(guild_id).unwrap_or(s.channel_id);
+                    fill(
+                        catalog.scheduled_entry,
+                        &[
+                            &s.id,
+                            &s.spec,
+                            &s.author.mention(),
+                            &channel.mention(),
+                            &code_block("scheme", &s.code, 200),
+                        ],
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    } else if !config.is_admin(msg.author.id) {
+        catalog.schedule_denied.to_string()
+    } else if let Some(id) = args.strip_prefix("remove") {
+        let id = id.trim().trim_start_matches('#');
+        let before = schedules.scheduled.len();
+        schedules
+            .scheduled
+            .retain(|s| s.guild_id != guild_id || s.id.to_string() != id);
+        if schedules.scheduled.len() < before {
+            fill(catalog.schedule_removed, &[&id])
+        } else {
+            fill(catalog.no_such_schedule, &[&id])
+        }
+    } else {
+        let guild_scheduled = schedules
+            .scheduled
+            .iter()
+            .filter(|s| s.guild_id == guild_id)
+            .count();
+        match parse_schedule(args, catalog) {
+            Err(e) => e,
+            Ok(_) if guild_scheduled >= MAX_SCHEDULED => {
+                fill(catalog.too_many_scheduled, &[&MAX_SCHEDULED])
+            }
+            Ok((spec, schedule, code)) => {
+                schedules.next_id += 1;
+                let id = schedules.next_id;
+                schedules.scheduled.push(Scheduled {
+                    id,
+                    spec: spec.to_string(),
+                    schedule,
+                    code,
+                    guild_id,
+                    channel_id: msg.channel_id,
+                    author: msg.author.id,
+                });
+                fill(catalog.scheduled_as, &[&id])
+            }
+        }
+    };
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// Parses `"<cron spec>" <code>`.
+fn parse_schedule<'a>(
+    args: &'a str,
+    catalog: &Catalog,
+) -> Result<(&'a str, cron::Schedule, String), String> {
+    let usage = catalog.schedule_usage;
+    let rest = args.strip_prefix('"').ok_or(usage)?;
+    let end = rest.find('"').ok_or(usage)?;
+    let (spec, code) = (
+        &rest[..end],
+        trigger::extract_direct(rest[end + 1..].trim()),
+    );
+    if code.trim().is_empty() {
+        return Err(usage.into());
+    }
+    let schedule =
+        cron::Schedule::parse(spec).map_err(|e| fill(catalog.invalid_schedule, &[&e]))?;
+    Ok((spec, schedule, code))
+}
+
+/// Runs scheduled evaluations at the start of each minute they are due, forever.
+fn run_scheduler(ctx: Context) {
+    loop {
+        thread::sleep(cron::until_next_minute(SystemTime::now()));
+        let now = cron::Minute::of(SystemTime::now());
+        let due = ctx
+            .data
+            .read()
+            .get::<SchedulesContainer>()
+            .unwrap()
+            .lock()
+            .scheduled
+            .iter()
+            .filter(|s| s.schedule.matches(now))
+            .map(|s| (s.id, s.code.clone(), s.guild_id, s.channel_id, s.author))
+            .collect::<Vec<_>>();
+        for (id, code, guild_id, channel_id, author) in due {
+            let config = get_config(&ctx);
+            let channel_id = config.schedule_channel(guild_id).unwrap_or(channel_id);
+            let span = info_span!("schedule", id);
+            let _entered = span.enter();
+            info!(command = code.as_str(), "evaluating");
+            let request = Request {
+                printing: config.printing(Some(guild_id)),
+                ..Request::new(code.clone(), config.default_limits())
+            };
+            let session = SessionKey::Guild(guild_id);
+            let catalog = guild_catalog(&ctx, Some(guild_id));
+            let evaluation = evaluate(&ctx, session, channel_id, request, catalog);
+            log_evaluation(&evaluation);
+            mirror_evaluation(&ctx, Some(guild_id), channel_id, author, &code, &evaluation);
+            let record = ResultRecord {
+                session,
+                command: code,
+                mode: Mode::Evaluate,
+                author: None,
+                guild: Some(guild_id),
+                full: None,
+                auto_closed: None,
+                result: None,
+                rerun_of: None,
+            };
+            post_result(&ctx, channel_id, record, evaluation);
+        }
+    }
+}
+
+/// Replies with a page of the names defined in `session`, only those containing `filter`
+/// if it is given. `page` is the page number as the user typed it, if they did.
+fn list_bindings(
+    ctx: &Context,
+    channel_id: ChannelId,
+    session: SessionKey,
+    filter: Option<&str>,
+    page: &str,
+    catalog: &Catalog,
+) {
+    let page = match page.trim() {
+        "" => 1,
+        page => match page.parse::<usize>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                ctx.say(channel_id, catalog.pages_from_one);
+                return;
+            }
+        },
+    };
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    let reply = match dispatcher.bindings(session) {
+        Ok(mut names) => {
+            if let Some(filter) = filter {
+                names.retain(|name| name.contains(filter));
+            }
+            let pages = (names.len() + BINDINGS_PER_PAGE - 1) / BINDINGS_PER_PAGE;
+            if names.is_empty() {
+                match filter {
+                    Some(filter) => fill(catalog.no_name_contains, &[&filter]),
+                    None => catalog.nothing_defined.to_string(),
+                }
+            } else if page > pages {
+                fill(catalog.only_pages, &[&pages])
+            } else {
+                let shown = names
+                    .iter()
+                    .skip((page - 1) * BINDINGS_PER_PAGE)
+                    .take(BINDINGS_PER_PAGE)
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                fill(catalog.names_page, &[&names.len(), &page, &pages, &shown])
+            }
+        }
+        Err(e) => fill(catalog.lookup_failed, &[&e]),
+    };
+    ctx.say(channel_id, &reply);
+}
+
+/// Replies with the last definition of `name` evaluated in `session`.
+fn source_of(
+    ctx: &Context,
+    channel_id: ChannelId,
+    session: SessionKey,
+    name: &str,
+    catalog: &Catalog,
+) {
+    let reply = if name.is_empty() {
+        catalog.source_of_usage.to_string()
+    } else {
+        let dispatcher = ctx
+            .data
+            .read()
+            .get::<DispatcherContainer>()
+            .unwrap()
+            .clone();
+        match dispatcher.source_of(session, name) {
+            Ok(Some(source)) => code_block("scheme", &source, EMBED_FIELD_LIMIT),
+            Ok(None) => fill(catalog.not_defined, &[&name]),
+            Err(e) => fill(catalog.lookup_failed, &[&e]),
+        }
+    };
+    ctx.say(channel_id, &reply);
+}
+
+/// Commits the definitions of the author's last evaluation to the guild's environment.
+fn commit(ctx: &Context, msg: &Message, catalog: &Catalog) {
+    let reply = if let Some(guild_id) = msg.guild_id {
+        let dispatcher = ctx
+            .data
+            .read()
+            .get::<DispatcherContainer>()
+            .unwrap()
+            .clone();
+        match dispatcher.commit(guild_id, msg.author.id) {
+            Ok(committed) => {
+                let mut reply = match committed.count {
+                    0 => catalog.commit_nothing.to_string(),
+                    1 => catalog.commit_one.to_string(),
+                    count => fill(catalog.commit_many, &[&count]),
+                };
+                // Someone else committed some of the same names since the evaluation ran.
+                if !committed.overwritten.is_empty() {
+                    let names = committed
+                        .overwritten
+                        .iter()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    reply.push('\n');
+                    reply.push_str(&fill(catalog.commit_overwrote, &[&names]));
+                }
+                reply
+            }
+            Err(e) => fill(catalog.commit_failed, &[&e]),
+        }
+    } else {
+        catalog.commit_in_dm.to_string()
+    };
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// Sends the last definition of each name in `session` as a file, for `¡import` to load.
+fn export(ctx: &Context, channel_id: ChannelId, session: SessionKey, catalog: &Catalog) {
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    match dispatcher.export(session) {
+        Ok(program) if program.is_empty() => ctx.say(channel_id, catalog.export_nothing),
+        Ok(program) => {
+            send::send(
+                &ctx.http,
+                channel_id,
+                |m| {
+                    m.content(catalog.export_done)
+                        .add_file((program.as_bytes(), EXPORT_FILE_NAME))
+                },
+                catalog.export_too_large,
+            );
+        }
+        Err(e) => ctx.say(channel_id, &fill(catalog.export_failed, &[&e])),
+    }
+}
 
-type BackAndForth = (String, SyncSender<Result<String, String>>);
+/// Handles `¡import`: loads the definitions of a `.scm` file into `session`. Guild
+/// environments commit them whole, if they all run without error.
+fn import(ctx: &Context, msg: &Message, session: SessionKey, args: &str, catalog: &Catalog) {
+    if matches!(session, SessionKey::Guild(_)) && !get_config(ctx).is_admin(msg.author.id) {
+        ctx.say(msg.channel_id, catalog.import_denied);
+        return;
+    }
+    let code = match import_source(ctx, msg, args, catalog) {
+        Ok(code) => code,
+        Err(e) => {
+            ctx.say(msg.channel_id, &e);
+            return;
+        }
+    };
+    let count = match forms::split_forms(&code) {
+        Ok(forms) if !forms.is_empty() && forms.iter().all(|form| form.is_definition()) => {
+            forms.len()
+        }
+        _ => {
+            ctx.say(msg.channel_id, catalog.import_definitions_only);
+            return;
+        }
+    };
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    let request = Request::new(code, user_limits(ctx, msg.guild_id, msg.author.id));
+    let evaluation = match session {
+        SessionKey::Guild(guild_id) => dispatcher.install(guild_id, request),
+        _ => dispatcher.evaluate(session, request),
+    };
+    let reply = match evaluation.error {
+        Some(error) => fill(
+            catalog.import_failed,
+            &[&code_block("", &error.message, EMBED_FIELD_LIMIT)],
+        ),
+        None if count == 1 => catalog.import_one.to_string(),
+        None => fill(catalog.import_many, &[&count]),
+    };
+    ctx.say(msg.channel_id, &reply);
+}
 
-struct InterruptingInterpreter {
-    interpreter: Interpreter,
+/// The contents of the `.scm` file attached to `msg`, or to the message `args` links to.
+fn import_source(
+    ctx: &Context,
+    msg: &Message,
+    args: &str,
+    catalog: &Catalog,
+) -> Result<String, String> {
+    let linked;
+    let attachments = if args.is_empty() {
+        &msg.attachments
+    } else {
+        let link = trigger::parse_message_link(args).ok_or(catalog.import_usage)?;
+        linked = fetch_linked(ctx, msg, &link, catalog)?;
+        &linked.attachments
+    };
+    let attachment = attachments
+        .iter()
+        .find(|attachment| attachment.filename.ends_with(".scm"))
+        .ok_or(catalog.import_no_file)?;
+    if attachment.size > MAX_IMPORT_BYTES {
+        return Err(fill(
+            catalog.import_too_large,
+            &[&(MAX_IMPORT_BYTES / 1024)],
+        ));
+    }
+    let contents = attachment
+        .download()
+        .map_err(|e| fill(catalog.import_download_failed, &[&e]))?;
+    String::from_utf8(contents).map_err(|_| catalog.import_not_text.into())
 }
 
-impl InterruptingInterpreter {
-    fn new() -> Self {
-        let interpreter = Interpreter::new();
-        interpreter
-            .initialize("../peroxide/src/scheme-lib/init.scm")
-            .unwrap();
-        Self { interpreter }
+/// Handles `¡propose`: posts the definitions for members to vote on with reactions.
+fn propose(ctx: &Context, msg: &Message, guild_id: GuildId, code: &str, catalog: &Catalog) {
+    let code = trigger::extract_direct(code);
+    let all_definitions = match forms::split_forms(&code) {
+        Ok(forms) => !forms.is_empty() && forms.iter().all(|form| form.is_definition()),
+        Err(_) => false,
+    };
+    if !all_definitions {
+        ctx.say(msg.channel_id, catalog.propose_definitions_only);
+        return;
     }
+    let needed = get_config(ctx).proposal_approvals;
+    let fallback = fill(
+        catalog.proposal_too_long,
+        &[&msg.author.mention(), &APPROVE_EMOJI, &REJECT_EMOJI],
+    );
+    if let Some(message) = send::send(
+        &ctx.http,
+        msg.channel_id,
+        |m| {
+            m.embed(|e| {
+                e.title(catalog.proposal_title)
+                    .description(fill(catalog.proposed_by, &[&msg.author.mention()]))
+                    .field(
+                        catalog.field_definitions,
+                        code_block("scheme", &code, EMBED_FIELD_LIMIT),
+                        false,
+                    )
+                    .footer(|f| {
+                        f.text(fill(
+                            catalog.proposal_footer,
+                            &[&APPROVE_EMOJI, &REJECT_EMOJI, &needed],
+                        ))
+                    })
+            })
+            .reactions(vec![
+                ReactionType::Unicode(APPROVE_EMOJI.into()),
+                ReactionType::Unicode(REJECT_EMOJI.into()),
+            ])
+        },
+        &fallback,
+    ) {
+        ctx.data
+            .read()
+            .get::<ProposalsContainer>()
+            .unwrap()
+            .lock()
+            .insert(message.id, Proposal::new(guild_id, msg.author.id, code));
+    }
+}
+
+/// Counts a vote on a proposal, and adds it to its guild's prelude once it has enough
+/// approvals. Returns whether `message_id` is a proposal.
+fn vote(
+    ctx: &Context,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    user: UserId,
+    emoji: &str,
+    added: bool,
+) -> bool {
+    let needed = get_config(ctx).proposal_approvals as i64;
+    let approved = {
+        let data = ctx.data.read();
+        let mut proposals = data.get::<ProposalsContainer>().unwrap().lock();
+        let proposal = match proposals.get_mut(&message_id) {
+            Some(proposal) => proposal,
+            None => return false,
+        };
+        proposal.vote(user, emoji, added);
+        if proposal.score() < needed {
+            return true;
+        }
+        proposals.remove(&message_id).unwrap()
+    };
 
-    fn run_string(&mut self, command: &str) -> Result<String, String> {
-        let read = peroxide::read::read(&self.interpreter.arena, command)
-            .map_err(|e| format!("parse error: {}", e))?;
-        let interruptor_clone = self.interpreter.interruptor();
-        let (send, recv) = mpsc::channel();
-        let interruptor_thread = thread::spawn(move || {
-            if recv.recv_timeout(Duration::from_secs(5)).is_err() {
-                interruptor_clone.interrupt();
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    let config = get_config(ctx);
+    let catalog = guild_catalog(ctx, Some(approved.guild));
+    let evaluation = dispatcher.install(
+        approved.guild,
+        Request::new(approved.code.clone(), config.default_limits()),
+    );
+    let reply = match evaluation.error {
+        Some(error) => fill(
+            catalog.proposal_failed,
+            &[
+                &approved.author.mention(),
+                &code_block("", &error.message, EMBED_FIELD_LIMIT),
+            ],
+        ),
+        None => {
+            let saved = ctx
+                .data
+                .read()
+                .get::<PreludesContainer>()
+                .unwrap()
+                .lock()
+                .append(approved.guild, approved.code);
+            match saved {
+                Ok(()) => fill(catalog.proposal_added, &[&approved.author.mention()]),
+                Err(e) => {
+                    error!("Error saving preludes: {}", e);
+                    fill(catalog.proposal_unsaved, &[&approved.author.mention()])
+                }
             }
+        }
+    };
+    ctx.say(channel_id, &reply);
+    true
+}
+
+/// Runs the prelude of `guild_id` in its environment.
+fn install_prelude(
+    dispatcher: &Dispatcher,
+    config: &Config,
+    guild_id: GuildId,
+    prelude: &[String],
+) {
+    for code in prelude {
+        let request = Request::new(code.clone(), config.default_limits());
+        if let Some(error) = dispatcher.install(guild_id, request).error {
+            error!(
+                guild = guild_id.0,
+                "Error installing prelude definitions: {}", error.message
+            );
+        }
+    }
+}
+
+/// Replies with usage statistics for the current guild and for the whole bot.
+fn send_stats(ctx: &Context, channel_id: ChannelId, guild_id: Option<GuildId>, catalog: &Catalog) {
+    let describe = |counters: &Counters| {
+        let busiest = counters
+            .busiest_users(BUSIEST_USERS)
+            .iter()
+            .map(|(user, count)| format!("<@{}> ({})", user, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        fill(
+            catalog.stats_counters,
+            &[
+                &counters.evaluations,
+                &format!("{:.1}", counters.error_rate() * 100.0),
+                &format_duration(counters.average_time()),
+                &if busiest.is_empty() {
+                    catalog.nobody_yet
+                } else {
+                    busiest.as_str()
+                },
+            ],
+        )
+    };
+
+    let (uptime, here, overall) = {
+        let data = ctx.data.read();
+        let stats = data.get::<StatsContainer>().unwrap().lock();
+        let here = guild_id.map(|id| {
+            stats
+                .guild(id)
+                .map_or_else(|| describe(&Counters::default()), &describe)
         });
-        let result = self.interpreter.parse_compile_run(read);
-        send.send(());
-        interruptor_thread.join().unwrap();
-        result.map(|p| p.pp().pretty_print())
+        (stats.uptime(), here, describe(&stats.global))
+    };
+
+    send::send(
+        &ctx.http,
+        channel_id,
+        |m| {
+            m.embed(|e| {
+                e.title(catalog.stats_title)
+                    .description(fill(catalog.stats_uptime, &[&format_uptime(uptime)]));
+                if let Some(here) = &here {
+                    e.field(catalog.field_this_server, here, true);
+                }
+                e.field(catalog.field_overall, &overall, true)
+            })
+        },
+        &overall,
+    );
+}
+
+/// Replies with what the bot was built from, what its interpreter supports, and how long it
+/// has been running.
+fn send_version(ctx: &Context, channel_id: ChannelId, catalog: &Catalog) {
+    let (dispatcher, uptime) = {
+        let data = ctx.data.read();
+        let uptime = data.get::<StatsContainer>().unwrap().lock().uptime();
+        (data.get::<DispatcherContainer>().unwrap().clone(), uptime)
+    };
+    let build = fill(
+        catalog.version_build,
+        &[&version::COMMIT, &format_utc(version::built())],
+    );
+    let interpreter = format!("peroxide {}", version::peroxide_version());
+    // A fresh interpreter, so that no program can redefine what it reports.
+    let reported =
+        dispatcher.evaluate_fresh(version::FEATURES_PROGRAM, get_config(ctx).default_limits());
+    let (features, srfis) = match reported.values.last() {
+        Some(printed) if reported.succeeded() => {
+            let (features, srfis) = version::parse_features(printed);
+            (
+                code_block("scheme", &features.join(" "), EMBED_FIELD_LIMIT),
+                if srfis.is_empty() {
+                    catalog.version_no_srfis.to_string()
+                } else {
+                    srfis.join(", ")
+                },
+            )
+        }
+        _ => (
+            catalog.version_features_unknown.to_string(),
+            catalog.version_no_srfis.to_string(),
+        ),
+    };
+    let fallback = format!("{}\n{}", build, interpreter);
+
+    send::send(
+        &ctx.http,
+        channel_id,
+        |m| {
+            m.embed(|e| {
+                e.title(catalog.version_title)
+                    .field(catalog.field_build, &build, false)
+                    .field(catalog.field_interpreter, &interpreter, false)
+                    .field(catalog.field_features, &features, false)
+                    .field(catalog.field_srfis, &srfis, false)
+                    .field(catalog.field_uptime, format_uptime(uptime), false)
+            })
+        },
+        &fallback,
+    );
+}
+
+/// Handles `¡challenge`, `¡challenge leaderboard` and `¡challenge add`.
+fn challenge_command(ctx: &Context, msg: &Message, args: &str, catalog: &Catalog) {
+    let guild_id = match msg.guild_id {
+        Some(id) => id,
+        None => {
+            ctx.say(msg.channel_id, catalog.challenges_in_servers);
+            return;
+        }
+    };
+    let config = get_config(ctx);
+    let data = ctx.data.read();
+    let mut challenges = data.get::<ChallengesContainer>().unwrap().lock();
+    let guild = challenges
+        .entry(guild_id)
+        .or_insert_with(GuildChallenges::default);
+
+    let mut posted_tests = false;
+    let reply = if args.is_empty() {
+        match guild.current() {
+            Some(challenge) => {
+                let mut reply = fill(
+                    catalog.challenge_shown,
+                    &[
+                        &challenge.name,
+                        &challenge.description,
+                        &challenge.tests.len(),
+                    ],
+                );
+                if let Some((user, length)) = guild.shortest() {
+                    reply.push_str(&fill(catalog.shortest_so_far, &[&length, &user.mention()]));
+                }
+                reply
+            }
+            None => catalog.no_challenge.to_string(),
+        }
+    } else if args == "leaderboard" {
+        let leaderboard = guild.leaderboard(LEADERBOARD_SIZE);
+        if leaderboard.is_empty() {
+            catalog.nobody_solved.to_string()
+        } else {
+            leaderboard
+                .iter()
+                .enumerate()
+                .map(|(i, (user, points))| format!("{}. <@{}>: {}", i + 1, user, points))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    } else if let Some(spec) = command_args(args, "add") {
+        if !config.is_admin(msg.author.id) {
+            catalog.challenge_add_denied.to_string()
+        } else {
+            match Challenge::parse(spec, catalog) {
+                Ok(challenge) => {
+                    let reply = fill(
+                        catalog.challenge_new,
+                        &[
+                            &challenge.name,
+                            &challenge.description,
+                            &challenge.tests.len(),
+                        ],
+                    );
+                    guild.set(challenge);
+                    posted_tests = true;
+                    reply
+                }
+                Err(e) => fill(catalog.challenge_invalid, &[&e]),
+            }
+        }
+    } else {
+        catalog.challenge_usage.to_string()
+    };
+    drop(challenges);
+    drop(data);
+
+    // The tests are in the message, so it must go for them to stay hidden.
+    if posted_tests {
+        if let Err(why) = msg.delete(ctx) {
+            error!("Error deleting challenge message: {:?}", why);
+        }
     }
+    ctx.say(msg.channel_id, &reply);
+}
+
+/// Runs a submission against the guild's current challenge and reports how it did.
+fn submit(ctx: &Context, msg: &Message, guild_id: GuildId, code: &str, catalog: &Catalog) {
+    let challenge = {
+        let data = ctx.data.read();
+        let challenges = data.get::<ChallengesContainer>().unwrap().lock();
+        challenges
+            .get(&guild_id)
+            .and_then(GuildChallenges::current)
+            .map(|c| (c.name.clone(), c.tests.clone()))
+    };
+    let (name, tests) = match challenge {
+        Some(challenge) => challenge,
+        None => {
+            ctx.say(msg.channel_id, catalog.no_challenge);
+            return;
+        }
+    };
+
+    let code = trigger::extract_direct(code);
+    let test_count = tests.len();
+    let limits = get_config(ctx).limits(msg.guild_id, msg.author.id);
+    let dispatcher = ctx
+        .data
+        .read()
+        .get::<DispatcherContainer>()
+        .unwrap()
+        .clone();
+    let judgement = match dispatcher.judge(&code, tests, limits) {
+        Ok(judgement) => judgement,
+        Err(e) => Judgement {
+            submission: Evaluation::failed(e),
+            tests: Vec::new(),
+        },
+    };
+
+    let passed = judgement.passed();
+    let solved = judgement.submission.succeeded() && passed == test_count;
+    let mut summary = fill(catalog.tests_passed, &[&passed, &test_count]);
+    if solved {
+        let length = code_size(&code);
+        let data = ctx.data.read();
+        let mut challenges = data.get::<ChallengesContainer>().unwrap().lock();
+        let guild = challenges
+            .entry(guild_id)
+            .or_insert_with(GuildChallenges::default);
+        // The challenge may have been replaced while the submission ran.
+        if guild.current().map(|c| c.name == name) == Some(true)
+            && guild.record_solve(msg.author.id)
+        {
+            summary.push_str(catalog.solved);
+        }
+        if guild.current().map(|c| c.name == name) == Some(true)
+            && guild.record_length(msg.author.id, length)
+        {
+            summary.push_str(&fill(catalog.new_shortest, &[&length]));
+        }
+    }
+    let tests = judgement
+        .tests
+        .iter()
+        .enumerate()
+        .map(|(i, test)| {
+            let status = if Judgement::test_passed(test) {
+                catalog.test_passed
+            } else if test.succeeded() {
+                catalog.test_wrong
+            } else {
+                catalog.test_error
+            };
+            fill(catalog.test_status, &[&(i + 1), &status])
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    send::send(
+        &ctx.http,
+        msg.channel_id,
+        |m| {
+            m.embed(|e| {
+                e.title(fill(catalog.challenge_title, &[&name]))
+                    .description(&summary)
+                    .colour(if solved {
+                        Colour::DARK_GREEN
+                    } else {
+                        Colour::RED
+                    });
+                if let Some(error) = &judgement.submission.error {
+                    e.field(
+                        catalog.field_submission_error,
+                        code_block(
+                            "",
+                            &describe_error(catalog, &judgement.submission, error),
+                            EMBED_FIELD_LIMIT,
+                        ),
+                        false,
+                    );
+                }
+                if !tests.is_empty() {
+                    e.field(catalog.field_tests, &tests, false);
+                }
+                e
+            })
+        },
+        &summary,
+    );
+}
+
+/// The message `link` points to, which must be in the same guild as `msg`, or in the same
+/// DM channel.
+fn fetch_linked(
+    ctx: &Context,
+    msg: &Message,
+    link: &MessageLink,
+    catalog: &Catalog,
+) -> Result<Message, String> {
+    if link.guild_id != msg.guild_id
+        || (msg.guild_id.is_none() && link.channel_id != msg.channel_id)
+    {
+        return Err(catalog.link_foreign.into());
+    }
+    link.channel_id
+        .message(&ctx.http, link.message_id)
+        .map_err(|e| fill(catalog.replay_fetch_failed, &[&e]))
+}
+
+/// The code of the message `link` points to, which must be in the same guild as `msg`, or
+/// in the same DM channel.
+fn replay_source(
+    ctx: &Context,
+    msg: &Message,
+    link: &MessageLink,
+    catalog: &Catalog,
+) -> Result<String, String> {
+    let linked = fetch_linked(ctx, msg, link, catalog)?;
+    let bot_id = ctx.cache.read().user.id;
+    let code = get_config(ctx)
+        .triggers(msg.guild_id)
+        .extract(&linked.content, bot_id)
+        .unwrap_or_else(|| trigger::extract_direct(&linked.content));
+    if code.trim().is_empty() || trigger::parse_message_link(&code).is_some() {
+        return Err(catalog.replay_no_code.into());
+    }
+    Ok(code)
+}
+
+/// Carries out a command of the bot's owner.
+fn owner_command(ctx: &Context, channel_id: ChannelId, command: OwnerCommand) {
+    let catalog = guild_catalog(ctx, None);
+    match command {
+        OwnerCommand::Guilds => {
+            let mut guilds = ctx
+                .cache
+                .read()
+                .guilds
+                .values()
+                .map(|guild| {
+                    let guild = guild.read();
+                    fill(
+                        catalog.owner_guild,
+                        &[&guild.name, &guild.id, &guild.member_count],
+                    )
+                })
+                .collect::<Vec<_>>();
+            guilds.sort();
+            let reply = if guilds.is_empty() {
+                catalog.owner_no_guilds.to_string()
+            } else {
+                fill(catalog.owner_guilds, &[&guilds.len(), &guilds.join("\n")])
+            };
+            ctx.say(channel_id, &reply);
+        }
+        OwnerCommand::Shutdown => {
+            info!("Shutting down at the owner's request");
+            ctx.say(channel_id, catalog.owner_shutdown);
+            let shard_manager = ctx
+                .data
+                .read()
+                .get::<ShardManagerContainer>()
+                .unwrap()
+                .clone();
+            shard_manager.lock().shutdown_all();
+        }
+        OwnerCommand::ReloadConfig => {
+            let reply = match Config::load(&config_path()) {
+                Ok(config) => {
+                    ctx.data.write().insert::<ConfigContainer>(Arc::new(config));
+                    info!("Reloaded the configuration at the owner's request");
+                    catalog.owner_reloaded.to_string()
+                }
+                Err(e) => fill(catalog.owner_reload_failed, &[&e]),
+            };
+            ctx.say(channel_id, &reply);
+        }
+        OwnerCommand::Broadcast(text) => {
+            let config = get_config(ctx);
+            // Gathered first, so the cache isn't locked while sending.
+            let channels = ctx
+                .cache
+                .read()
+                .guilds
+                .values()
+                .flat_map(|guild| {
+                    guild
+                        .read()
+                        .channels
+                        .values()
+                        .map(|channel| channel.read())
+                        .filter(|channel| channel.name == config.channel_name)
+                        .map(|channel| channel.id)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let sent = channels
+                .iter()
+                .filter(|channel| send::say(&ctx.http, **channel, text).is_some())
+                .count();
+            ctx.say(
+                channel_id,
+                &fill(catalog.owner_broadcast_sent, &[&sent, &channels.len()]),
+            );
+        }
+    }
+}
+
+/// Where the configuration is read from.
+fn config_path() -> String {
+    env::var("PEROXIDE_CONFIG").unwrap_or_else(|_| "config.toml".into())
+}
+
+fn get_config(ctx: &Context) -> Arc<Config> {
+    ctx.data.read().get::<ConfigContainer>().unwrap().clone()
 }
 
 struct Handler;
@@ -54,62 +2498,476 @@ impl EventHandler for Handler {
     // Event handlers are dispatched through a threadpool, and so multiple
     // events can be dispatched simultaneously.
     fn message(&self, ctx: Context, msg: Message) {
-        lazy_static! {
-            static ref CB_CMD_RE: Regex = Regex::new(r"(?s)\A(?:¡cl|oo)\s+```scheme\s+(.*)```\z").unwrap();
-            static ref CMD_RE: Regex = Regex::new(r"(?s)\A(?:¡cl|oo)\s+(.*)\z").unwrap();
-            static ref START_OF_LINE: Regex = Regex::new(r"(?m)^").unwrap();
+        let config = get_config(&ctx);
+        let catalog = guild_catalog(&ctx, msg.guild_id);
+        let bot = Bot::new(config.clone(), ctx.cache.read().user.id, catalog);
+        let event = SerenityEvent {
+            ctx: &ctx,
+            msg: &msg,
+        };
+        if !bot.accepts(&event) {
+            return;
         }
+        // Pair sessions end after a while without a message from either member.
+        let (pair, expired) = {
+            let data = ctx.data.read();
+            let mut pairs = data.get::<PairsContainer>().unwrap().lock();
+            let expired = pairs.expire();
+            (pairs.touch(msg.channel_id, msg.author.id), expired)
+        };
+        for ended in expired {
+            let notice = fill(
+                guild_catalog(&ctx, Some(ended.guild)).pair_expired,
+                &[&ended.members[0].mention(), &ended.members[1].mention()],
+            );
+            ctx.say(ended.channel, &notice);
+        }
+        let bot = bot.in_pair(pair);
+        let direct = msg.guild_id.is_none();
+        let trimmed_content = msg.content.trim();
+        let span = info_span!(
+            "message",
+            user = %msg.author.id,
+            guild = ?msg.guild_id.map(|id| id.0),
+            channel = %msg.channel_id
+        );
+        let _entered = span.enter();
 
-        if msg.channel_id.name(ctx.cache) != Some("lisp".into()) || msg.author.bot {
+        // Lines of input for a program waiting on this user are not commands.
+        let pending_input = ctx
+            .data
+            .read()
+            .get::<PendingInputContainer>()
+            .unwrap()
+            .lock()
+            .remove(&(msg.channel_id, msg.author.id));
+        if let Some(input) = pending_input {
+            let _ = input.try_send(trigger::extract_direct(trimmed_content));
             return;
         }
-        let trimmed_content = msg.content.trim();
 
-        println!("got message [{}]", trimmed_content);
+        debug!(content = trimmed_content, "got message");
 
-        if trimmed_content == "¡source" {
-            if let Err(why) = msg.channel_id.say(&ctx.http,
-            "peroxide interpreter: https://github.com/MattX/peroxide\n\
-            discord bot: https://github.com/MattX/peroxide-discord") {
-                println!("Error sending message: {:?}", why);
+        // Messages from someone writing a program with `¡begin` are parts of it.
+        let draft_key = (msg.channel_id, msg.author.id);
+        let status = ctx
+            .data
+            .read()
+            .get::<DraftsContainer>()
+            .unwrap()
+            .lock()
+            .status(draft_key);
+        let composed = match status {
+            Status::Composing => match compose(&ctx, &msg, draft_key, catalog) {
+                Some(program) => Some(program),
+                None => return,
+            },
+            Status::Expired => {
+                let notice = fill(catalog.draft_expired, &[&config.draft_timeout.as_secs()]);
+                ctx.say(msg.channel_id, &notice);
+                None
             }
-            return
-        }
+            Status::Idle => None,
+        };
+        // Aliases are read before anything else, so they can stand for any message.
+        let aliased = msg.guild_id.and_then(|guild_id| {
+            let content = ctx
+                .data
+                .read()
+                .get::<AliasesContainer>()
+                .unwrap()
+                .lock()
+                .expand(guild_id, &msg.content)?;
+            let mut aliased = msg.clone();
+            aliased.content = content;
+            Some(aliased)
+        });
+        let routed = SerenityEvent {
+            ctx: &ctx,
+            msg: aliased.as_ref().unwrap_or(&msg),
+        };
+        let route = match composed {
+            Some(program) => Route::Evaluate {
+                session: bot.environment(&event),
+                command: program,
+                mode: Mode::Evaluate,
+            },
+            None => bot.route(&routed, |content| {
+                custom_command_call(&ctx, msg.guild_id?, content)
+            }),
+        };
 
-        let command = match CB_CMD_RE
-            .captures(trimmed_content)
-            .or_else(|| CMD_RE.captures(trimmed_content))
-        {
-            Some(captures) => captures[1].to_string(),
-            None => return,
+        let (session, command, mode) = match route {
+            Route::Source => {
+                ctx.say(msg.channel_id, catalog.source_links);
+                return;
+            }
+            Route::Version => {
+                send_version(&ctx, msg.channel_id, catalog);
+                return;
+            }
+            Route::Help => {
+                send_help(&ctx, msg.channel_id, msg.guild_id, msg.author.id, catalog);
+                return;
+            }
+            Route::Reload(session) => {
+                reload(&ctx, msg.channel_id, session, catalog);
+                return;
+            }
+            Route::SourceOf(environment, name) => {
+                source_of(&ctx, msg.channel_id, environment, name, catalog);
+                return;
+            }
+            Route::Bindings(environment, page) => {
+                list_bindings(&ctx, msg.channel_id, environment, None, page, catalog);
+                return;
+            }
+            Route::Apropos(environment, text, page) => {
+                list_bindings(&ctx, msg.channel_id, environment, Some(text), page, catalog);
+                return;
+            }
+            Route::Schedule(args) => {
+                if let Some(guild_id) = msg.guild_id {
+                    schedule_command(&ctx, &msg, guild_id, args, catalog);
+                }
+                return;
+            }
+            Route::Set(args) => {
+                set_command(&ctx, &msg, args, catalog);
+                return;
+            }
+            Route::Alias(args) => {
+                if let Some(guild_id) = msg.guild_id {
+                    alias_command(&ctx, &msg, guild_id, args, catalog);
+                }
+                return;
+            }
+            Route::Lang(args) => {
+                if let Some(guild_id) = msg.guild_id {
+                    lang_command(&ctx, &msg, guild_id, args, catalog);
+                }
+                return;
+            }
+            Route::Features(args) => {
+                if let Some(guild_id) = msg.guild_id {
+                    features_command(&ctx, &msg, guild_id, args, catalog);
+                }
+                return;
+            }
+            Route::Commit => {
+                commit(&ctx, &msg, catalog);
+                return;
+            }
+            Route::Export(environment) => {
+                export(&ctx, msg.channel_id, environment, catalog);
+                return;
+            }
+            Route::Import(environment, args) => {
+                import(&ctx, &msg, environment, args, catalog);
+                return;
+            }
+            Route::Stats => {
+                send_stats(&ctx, msg.channel_id, msg.guild_id, catalog);
+                return;
+            }
+            Route::Challenge(args) => {
+                challenge_command(&ctx, &msg, args, catalog);
+                return;
+            }
+            Route::Submit(code) => {
+                if let Some(guild_id) = msg.guild_id {
+                    submit(&ctx, &msg, guild_id, code, catalog);
+                }
+                return;
+            }
+            Route::Propose(code) => {
+                if let Some(guild_id) = msg.guild_id {
+                    propose(&ctx, &msg, guild_id, code, catalog);
+                }
+                return;
+            }
+            Route::Begin => {
+                ctx.data
+                    .read()
+                    .get::<DraftsContainer>()
+                    .unwrap()
+                    .lock()
+                    .begin(draft_key);
+                let notice = fill(
+                    catalog.draft_started,
+                    &[&config.draft_max_chars, &config.draft_timeout.as_secs()],
+                );
+                ctx.say(msg.channel_id, &notice);
+                return;
+            }
+            Route::Pair(args) => {
+                if let Some(guild_id) = msg.guild_id {
+                    pair_command(&ctx, &msg, guild_id, args, catalog);
+                }
+                return;
+            }
+            Route::Handoff => {
+                hand_off(&ctx, &msg, catalog);
+                return;
+            }
+            Route::Leave => {
+                leave_pair(&ctx, &msg, catalog);
+                return;
+            }
+            Route::Owner(command) => {
+                owner_command(&ctx, msg.channel_id, command);
+                return;
+            }
+            Route::Reply(text) => {
+                ctx.say(msg.channel_id, text);
+                return;
+            }
+            Route::Ignore => return,
+            Route::Evaluate {
+                session,
+                command,
+                mode,
+            } => (session, command, mode),
         };
 
-        println!("command: [{}]", command);
+        // A prefix followed by nothing but a link to an earlier message re-runs its code.
+        let command = if let Some(link) = trigger::parse_message_link(&command) {
+            match replay_source(&ctx, &msg, &link, catalog) {
+                Ok(code) => code,
+                Err(e) => {
+                    ctx.say(msg.channel_id, &e);
+                    return;
+                }
+            }
+        } else {
+            command
+        };
 
-        let mut data = ctx.data.write();
-        let send_channel: &mut Mutex<SyncSender<BackAndForth>> =
-            data.get_mut::<SenderContainer>().unwrap();
-        let (response_sender, response_receiver) = mpsc::sync_channel(0);
-        let result = match send_channel.try_lock_for(Duration::from_secs(15)) {
-            Some(channel) => {
-                channel
-                    .try_send((command.clone(), response_sender))
-                    .unwrap();
-                response_receiver.recv().map_err(|e| e.to_string()).and_then(|r| r)
+        if direct {
+            let limited = ctx
+                .data
+                .read()
+                .get::<DmRateLimitContainer>()
+                .unwrap()
+                .lock()
+                .check(msg.author.id);
+            if let Err(retry_after) = limited {
+                let warning = fill(catalog.dm_rate_limited, &[&(retry_after.as_secs() + 1)]);
+                ctx.say(msg.channel_id, &warning);
+                return;
             }
-            None => Err("timeout waiting for interpreter lock".into()),
+        }
+
+        // Nothing but a link to a paste evaluates the paste.
+        let command = match paste::source_url(&config.paste, &command) {
+            Some(url) => match paste::fetch(&config.paste, url) {
+                Ok(code) => code,
+                Err(e) => {
+                    ctx.say(msg.channel_id, &fill(catalog.paste_failed, &[&e]));
+                    return;
+                }
+            },
+            None => command,
+        };
+
+        // Lists left open are closed for those who asked, and evaluated anyway.
+        let auto_close = user_preferences(&ctx, msg.author.id).auto_close == Some(true);
+        let (command, auto_closed) = match forms::missing_closers(&command) {
+            // On a line of its own, so that a trailing comment doesn't swallow it.
+            Some(closers) if auto_close => (
+                format!("{}\n{}", command.trim_end(), closers),
+                Some(closers),
+            ),
+            _ => (command, None),
+        };
+
+        info!(command = command.as_str(), session = ?session, "evaluating");
+
+        let request = Request {
+            command: command.clone(),
+            limits: user_limits(&ctx, msg.guild_id, msg.author.id),
+            privileged: matches!(session, SessionKey::Guild(_)) && config.is_admin(msg.author.id),
+            invocation: Some(invocation(&config, &msg.author)),
+            inputs: Vec::new(),
+            printing: user_printing(&ctx, msg.guild_id, Some(msg.author.id)),
         };
-        println!("Result: {:?}", result);
+        if config.status_reactions {
+            react_status(&ctx, msg.channel_id, msg.id, None, QUEUED_EMOJI);
+        }
+        let mut evaluation = evaluate(&ctx, session, msg.channel_id, request, catalog);
+        if config.status_reactions {
+            let status = status_emoji(&evaluation);
+            react_status(&ctx, msg.channel_id, msg.id, Some(QUEUED_EMOJI), status);
+        }
+        log_evaluation(&evaluation);
+        record_stats(&ctx, msg.guild_id, msg.author.id, &evaluation);
+        mirror_evaluation(
+            &ctx,
+            msg.guild_id,
+            msg.channel_id,
+            msg.author.id,
+            &command,
+            &evaluation,
+        );
+        register_commands(&ctx, msg.guild_id, msg.author.id, &mut evaluation);
+        perform_actions(
+            &ctx,
+            msg.channel_id,
+            msg.id,
+            msg.author.id,
+            &evaluation.actions,
+            catalog,
+        );
 
-        let quoted_content = START_OF_LINE.replace_all(trimmed_content, "> ");
-        let response = match result {
-            Ok(result_string) => format!("{}\n`{}`", quoted_content, result_string),
-            Err(error_string) => format!("{}\n*Error*: {}", quoted_content, error_string),
+        let record = ResultRecord {
+            session,
+            command,
+            mode,
+            author: Some(msg.author.id),
+            guild: msg.guild_id,
+            full: None,
+            auto_closed,
+            result: None,
+            rerun_of: None,
+        };
+        post_result(&ctx, msg.channel_id, record, evaluation);
+    }
+
+    // Reactions on our own result messages act as buttons: rerun the expression, or show
+    // its full source.
+    fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        if reaction.user_id == ctx.cache.read().user.id {
+            return;
+        }
+        let emoji = match reaction.emoji {
+            ReactionType::Unicode(ref emoji) => emoji.as_str(),
+            _ => return,
         };
+        if vote(
+            &ctx,
+            reaction.channel_id,
+            reaction.message_id,
+            reaction.user_id,
+            emoji,
+            true,
+        ) {
+            return;
+        }
+        let catalog = guild_catalog(&ctx, reaction.guild_id);
+        let record = match ctx
+            .data
+            .read()
+            .get::<ResultStore>()
+            .and_then(|history| history.get(reaction.message_id))
+        {
+            Some(record) => record.clone(),
+            None => return,
+        };
+
+        if emoji == RERUN_EMOJI {
+            let config = get_config(&ctx);
+            if record.session == SessionKey::Admin && !config.is_admin(reaction.user_id) {
+                return;
+            }
+            // Only its members evaluate in a pair's environment.
+            if let SessionKey::Pair(_, pair) = record.session {
+                let member = ctx
+                    .data
+                    .read()
+                    .get::<PairsContainer>()
+                    .unwrap()
+                    .lock()
+                    .get(reaction.channel_id, reaction.user_id)
+                    .is_some_and(|(id, _)| id == pair);
+                if !member {
+                    return;
+                }
+            }
+            let span = info_span!(
+                "rerun",
+                user = %reaction.user_id,
+                guild = ?reaction.guild_id.map(|id| id.0),
+                channel = %reaction.channel_id
+            );
+            let _entered = span.enter();
+            info!(command = record.command.as_str(), session = ?record.session, "evaluating");
+            // Actions apply to the result message that was reacted to.
+            let invocation = match reaction.user_id.to_user(&ctx) {
+                Ok(user) => invocation(&config, &user),
+                Err(why) => {
+                    error!("Error fetching user: {:?}", why);
+                    return;
+                }
+            };
+            let request = Request {
+                command: record.command.clone(),
+                limits: user_limits(&ctx, reaction.guild_id, reaction.user_id),
+                privileged: matches!(record.session, SessionKey::Guild(_))
+                    && config.is_admin(reaction.user_id),
+                invocation: Some(invocation),
+                inputs: Vec::new(),
+                printing: user_printing(&ctx, reaction.guild_id, Some(reaction.user_id)),
+            };
+            let mut evaluation =
+                evaluate(&ctx, record.session, reaction.channel_id, request, catalog);
+            log_evaluation(&evaluation);
+            record_stats(&ctx, reaction.guild_id, reaction.user_id, &evaluation);
+            mirror_evaluation(
+                &ctx,
+                reaction.guild_id,
+                reaction.channel_id,
+                reaction.user_id,
+                &record.command,
+                &evaluation,
+            );
+            register_commands(&ctx, reaction.guild_id, reaction.user_id, &mut evaluation);
+            perform_actions(
+                &ctx,
+                reaction.channel_id,
+                reaction.message_id,
+                reaction.user_id,
+                &evaluation.actions,
+                catalog,
+            );
+            let record = ResultRecord {
+                author: Some(reaction.user_id),
+                rerun_of: record.result.clone(),
+                ..record
+            };
+            post_result(&ctx, reaction.channel_id, record, evaluation);
+        } else if emoji == SOURCE_EMOJI {
+            let source = code_block("scheme", &record.command, MESSAGE_LIMIT);
+            ctx.say(reaction.channel_id, &source);
+        } else if emoji == SHOW_MORE_EMOJI && record.author == Some(reaction.user_id) {
+            if let Some(full) = &record.full {
+                let fallback = format::truncate(full, MESSAGE_LIMIT);
+                send::send(
+                    &ctx.http,
+                    reaction.channel_id,
+                    |m| {
+                        m.content(fill(
+                            catalog.full_result_for,
+                            &[&reaction.user_id.mention()],
+                        ))
+                        .add_file((full.as_bytes(), FULL_RESULT_FILE_NAME))
+                    },
+                    &fallback,
+                );
+            }
+        }
+    }
 
-        let limited_response = response.chars().take(1000).collect::<String>();
-        if let Err(why) = msg.channel_id.say(&ctx.http, limited_response) {
-            println!("Error sending message: {:?}", why);
+    // Taking back a vote on a proposal.
+    fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        if let ReactionType::Unicode(ref emoji) = reaction.emoji {
+            vote(
+                &ctx,
+                reaction.channel_id,
+                reaction.message_id,
+                reaction.user_id,
+                emoji,
+                false,
+            );
         }
     }
 
@@ -119,45 +2977,259 @@ impl EventHandler for Handler {
     // private channels, and more.
     //
     // In this case, just print what the current user's username is.
-    fn ready(&self, _: Context, ready: Ready) {
-        println!("{} is connected!", ready.user.name);
+    fn ready(&self, ctx: Context, ready: Ready) {
+        info!("{} is connected!", ready.user.name);
+        let dispatcher = ctx
+            .data
+            .read()
+            .get::<DispatcherContainer>()
+            .unwrap()
+            .clone();
+        if !SCHEDULER_STARTED.swap(true, Ordering::SeqCst) {
+            let scheduler_ctx = ctx.clone();
+            thread::spawn(move || run_scheduler(scheduler_ctx));
+        }
+        let catalog = get_config(&ctx).language.catalog();
+        presence::start(ctx, dispatcher, catalog);
     }
 }
 
-struct SenderContainer;
+struct ConfigContainer;
+
+impl TypeMapKey for ConfigContainer {
+    type Value = Arc<Config>;
+}
+
+struct DispatcherContainer;
+
+impl TypeMapKey for DispatcherContainer {
+    type Value = Arc<Dispatcher>;
+}
+
+struct DmRateLimitContainer;
+
+impl TypeMapKey for DmRateLimitContainer {
+    type Value = Mutex<RateLimiter>;
+}
+
+struct ActionRateLimitContainer;
 
-impl TypeMapKey for SenderContainer {
-    type Value = Mutex<SyncSender<BackAndForth>>;
+impl TypeMapKey for ActionRateLimitContainer {
+    type Value = Mutex<RateLimiter>;
+}
+
+/// Each guild's bot commands defined from Scheme, with the user who defined them.
+struct CustomCommandsContainer;
+
+impl TypeMapKey for CustomCommandsContainer {
+    type Value = Mutex<HashMap<GuildId, HashMap<String, UserId>>>;
+}
+
+struct DraftsContainer;
+
+impl TypeMapKey for DraftsContainer {
+    type Value = Mutex<Drafts>;
+}
+
+/// Programs waiting for a line of input from a user in a channel.
+struct PendingInputContainer;
+
+impl TypeMapKey for PendingInputContainer {
+    type Value = Mutex<HashMap<(ChannelId, UserId), mpsc::SyncSender<String>>>;
+}
+
+/// Shared so that webhooks are looked up without holding the data lock.
+struct WebhooksContainer;
+
+impl TypeMapKey for WebhooksContainer {
+    type Value = Arc<Mutex<Webhooks>>;
+}
+
+struct PreferencesContainer;
+
+impl TypeMapKey for PreferencesContainer {
+    type Value = Mutex<PreferenceStore>;
+}
+
+struct AliasesContainer;
+
+impl TypeMapKey for AliasesContainer {
+    type Value = Mutex<AliasStore>;
+}
+
+struct PairsContainer;
+
+impl TypeMapKey for PairsContainer {
+    type Value = Mutex<Pairs>;
+}
+
+struct FeaturesContainer;
+
+impl TypeMapKey for FeaturesContainer {
+    type Value = Mutex<FeatureStore>;
+}
+
+struct LanguagesContainer;
+
+impl TypeMapKey for LanguagesContainer {
+    type Value = Mutex<LanguageStore>;
+}
+
+struct SchedulesContainer;
+
+impl TypeMapKey for SchedulesContainer {
+    type Value = Mutex<Schedules>;
+}
+
+/// Each guild's challenge and leaderboard.
+struct ChallengesContainer;
+
+impl TypeMapKey for ChallengesContainer {
+    type Value = Mutex<HashMap<GuildId, GuildChallenges>>;
+}
+
+struct ProposalsContainer;
+
+impl TypeMapKey for ProposalsContainer {
+    type Value = Mutex<OpenProposals>;
+}
+
+struct PreludesContainer;
+
+impl TypeMapKey for PreludesContainer {
+    type Value = Mutex<PreludeStore>;
+}
+
+struct StatsContainer;
+
+impl TypeMapKey for StatsContainer {
+    type Value = Mutex<Stats>;
+}
+
+struct ShardManagerContainer;
+
+impl TypeMapKey for ShardManagerContainer {
+    type Value = Arc<Mutex<ShardManager>>;
+}
+
+struct ResultStore;
+
+impl TypeMapKey for ResultStore {
+    type Value = ResultHistory;
 }
 
 fn main() {
+    if env::args().nth(1).as_deref() == Some(sandbox::CHILD_FLAG) {
+        sandbox::child_main();
+        return;
+    }
+
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
 
-    let (send, recv) = mpsc::sync_channel::<BackAndForth>(0);
-
-    thread::spawn(move || {
-        let mut interpreter = InterruptingInterpreter::new();
-
-        while let Ok((command, rc)) = recv.recv() {
-            rc.send(interpreter.run_string(&command)).unwrap();
+    let config_path = config_path();
+    let config = Arc::new(Config::load(&config_path).expect("Error loading configuration"));
+    logging::init(&config.logging, logging::Output::Stdout).expect("Error setting up logging");
+    let mut senders = Vec::new();
+    for _ in 0..config.workers {
+        let (send, recv) = mpsc::sync_channel::<Job>(0);
+        let queued = Arc::new(AtomicUsize::new(0));
+        senders.push((send, queued.clone()));
+        if config.sandbox.enabled {
+            let config_path = config_path.clone();
+            thread::spawn(move || sandbox::run(recv, config_path));
+        } else {
+            let worker_config = config.clone();
+            thread::spawn(move || worker::run(recv, worker_config, queued));
         }
-    });
+    }
+    let dispatcher = Arc::new(Dispatcher::new(
+        senders,
+        config.lock_timeout,
+        config.queue_length,
+    ));
+    let features =
+        FeatureStore::load(config.features_path.clone()).expect("Error loading features");
+    // Before the preludes, as this replaces the environments they run in.
+    for (guild_id, chosen) in features.guilds() {
+        if let Err(e) = dispatcher.set_features(guild_id, chosen) {
+            error!(guild = guild_id.0, "Error turning on features: {}", e);
+        }
+    }
+    let preludes =
+        PreludeStore::load(config.preludes_path.clone()).expect("Error loading preludes");
+    for (guild_id, prelude) in preludes.guilds() {
+        install_prelude(&dispatcher, &config, guild_id, prelude);
+    }
 
     // Create a new instance of the Client, logging in as a bot. This will
     // automatically prepend your bot token with "Bot ", which is a requirement
     // by Discord for bot users.
+    let preferences =
+        PreferenceStore::load(config.preferences_path.clone()).expect("Error loading preferences");
+    let languages =
+        LanguageStore::load(config.languages_path.clone()).expect("Error loading languages");
+    let aliases = AliasStore::load(config.aliases_path.clone()).expect("Error loading aliases");
     let mut client = Client::new(&token, Handler).expect("Err creating client");
-    client
-        .data
-        .write()
-        .insert::<SenderContainer>(Mutex::new(send));
+    if config.http.enabled {
+        let http_config = config.clone();
+        let http_dispatcher = dispatcher.clone();
+        // For the health checks to see the shards.
+        let shard_manager = client.shard_manager.clone();
+        thread::spawn(move || {
+            if let Err(why) = http::serve(http_config, http_dispatcher, shard_manager) {
+                error!("HTTP API error: {}", why);
+            }
+        });
+    }
+    let shards = config.shards;
+    {
+        let mut data = client.data.write();
+        data.insert::<DmRateLimitContainer>(Mutex::new(RateLimiter::new(
+            config.dm.rate_limit,
+            config.dm.rate_limit_window,
+        )));
+        data.insert::<ActionRateLimitContainer>(Mutex::new(RateLimiter::new(
+            config.actions.rate_limit,
+            config.actions.rate_limit_window,
+        )));
+        data.insert::<ConfigContainer>(config.clone());
+        data.insert::<DispatcherContainer>(dispatcher);
+        data.insert::<ResultStore>(ResultHistory::default());
+        data.insert::<StatsContainer>(Mutex::new(Stats::new()));
+        data.insert::<CustomCommandsContainer>(Mutex::new(HashMap::new()));
+        data.insert::<ChallengesContainer>(Mutex::new(HashMap::new()));
+        data.insert::<SchedulesContainer>(Mutex::new(Schedules::default()));
+        data.insert::<PreferencesContainer>(Mutex::new(preferences));
+        data.insert::<LanguagesContainer>(Mutex::new(languages));
+        data.insert::<AliasesContainer>(Mutex::new(aliases));
+        data.insert::<FeaturesContainer>(Mutex::new(features));
+        data.insert::<PairsContainer>(Mutex::new(Pairs::new(
+            config.pairs.max_sessions,
+            config.pairs.idle,
+        )));
+        data.insert::<WebhooksContainer>(Arc::new(Mutex::new(Webhooks::default())));
+        data.insert::<PendingInputContainer>(Mutex::new(HashMap::new()));
+        data.insert::<DraftsContainer>(Mutex::new(Drafts::new(
+            config.draft_max_chars,
+            config.draft_timeout,
+        )));
+        data.insert::<ProposalsContainer>(Mutex::new(HashMap::new()));
+        data.insert::<PreludesContainer>(Mutex::new(preludes));
+        data.insert::<ShardManagerContainer>(client.shard_manager.clone());
+    }
 
-    // Finally, start a single shard, and start listening to events.
+    // Finally, start the shards, and start listening to events. Every shard shares the
+    // client's data, and so the dispatcher.
     //
     // Shards will automatically attempt to reconnect, and will perform
     // exponential backoff until it reconnects.
-    if let Err(why) = client.start() {
-        println!("Client error: {:?}", why);
+    let started = if shards == 0 {
+        client.start_autosharded()
+    } else {
+        client.start_shards(shards)
+    };
+    if let Err(why) = started {
+        error!("Client error: {:?}", why);
     }
 }
@@ -0,0 +1,149 @@
+//! Pair sessions started with `¡pair`: two users sharing an environment in a guild channel,
+//! as for mentoring. Their evaluations in that channel run in the pair's environment instead
+//! of the guild's.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+pub const PAIR_COMMAND: &str = "¡pair";
+pub const HANDOFF_COMMAND: &str = "¡handoff";
+pub const LEAVE_COMMAND: &str = "¡leave";
+
+pub struct Pair {
+    pub guild: GuildId,
+    pub channel: ChannelId,
+    pub members: [UserId; 2],
+    last_used: Instant,
+}
+
+impl Pair {
+    /// The member who isn't `user`.
+    pub fn partner_of(&self, user: UserId) -> UserId {
+        if self.members[0] == user {
+            self.members[1]
+        } else {
+            self.members[0]
+        }
+    }
+}
+
+/// Why a pair session couldn't be started or changed.
+pub enum Refusal {
+    /// The user is already in a pair session in the channel.
+    Busy(UserId),
+    /// There are as many sessions as allowed.
+    Full,
+}
+
+/// The pair sessions running, by ID, at most `max_sessions` of them, each ending after
+/// `idle` without a message from either member.
+pub struct Pairs {
+    pairs: HashMap<u64, Pair>,
+    next_id: u64,
+    max_sessions: usize,
+    idle: Duration,
+}
+
+impl Pairs {
+    pub fn new(max_sessions: usize, idle: Duration) -> Self {
+        Self {
+            pairs: HashMap::new(),
+            next_id: 0,
+            max_sessions,
+            idle,
+        }
+    }
+
+    /// Ends the sessions that went idle, returning them.
+    pub fn expire(&mut self) -> Vec<Pair> {
+        let idle = self.idle;
+        let expired = self
+            .pairs
+            .iter()
+            .filter(|(_, pair)| pair.last_used.elapsed() >= idle)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        expired
+            .into_iter()
+            .filter_map(|id| self.pairs.remove(&id))
+            .collect()
+    }
+
+    /// The ID of the session `user` is in in `channel`, if any, counting this as activity.
+    pub fn touch(&mut self, channel: ChannelId, user: UserId) -> Option<u64> {
+        let id = self.find(channel, user)?;
+        self.pairs.get_mut(&id).unwrap().last_used = Instant::now();
+        Some(id)
+    }
+
+    /// The session `user` is in in `channel`, if any.
+    pub fn get(&self, channel: ChannelId, user: UserId) -> Option<(u64, &Pair)> {
+        let id = self.find(channel, user)?;
+        Some((id, &self.pairs[&id]))
+    }
+
+    /// Starts a session of `members` in `channel`, returning its ID.
+    pub fn start(
+        &mut self,
+        guild: GuildId,
+        channel: ChannelId,
+        members: [UserId; 2],
+    ) -> Result<u64, Refusal> {
+        if let Some(&busy) = members.iter().find(|m| self.find(channel, **m).is_some()) {
+            return Err(Refusal::Busy(busy));
+        }
+        if self.pairs.len() >= self.max_sessions {
+            return Err(Refusal::Full);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let pair = Pair {
+            guild,
+            channel,
+            members,
+            last_used: Instant::now(),
+        };
+        self.pairs.insert(id, pair);
+        Ok(id)
+    }
+
+    /// Gives the place of `from` in their session in `channel` to `to`, returning the
+    /// session, or `None` if `from` isn't in one.
+    pub fn hand_off(
+        &mut self,
+        channel: ChannelId,
+        from: UserId,
+        to: UserId,
+    ) -> Result<Option<&Pair>, Refusal> {
+        let id = match self.find(channel, from) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        if self.find(channel, to).is_some() {
+            return Err(Refusal::Busy(to));
+        }
+        let pair = self.pairs.get_mut(&id).unwrap();
+        for member in pair.members.iter_mut() {
+            if *member == from {
+                *member = to;
+            }
+        }
+        pair.last_used = Instant::now();
+        Ok(Some(pair))
+    }
+
+    /// Ends the session `user` is in in `channel`, returning it.
+    pub fn leave(&mut self, channel: ChannelId, user: UserId) -> Option<Pair> {
+        let id = self.find(channel, user)?;
+        self.pairs.remove(&id)
+    }
+
+    fn find(&self, channel: ChannelId, user: UserId) -> Option<u64> {
+        self.pairs
+            .iter()
+            .find(|(_, pair)| pair.channel == channel && pair.members.contains(&user))
+            .map(|(id, _)| *id)
+    }
+}
@@ -0,0 +1,90 @@
+//! Fetching code from paste services, and uploading results too big to show.
+
+use std::io::Read;
+
+use reqwest::{Client, RedirectPolicy, Url};
+
+use crate::config::PasteConfig;
+
+/// The URL to fetch code from if `text` is nothing but a link to a paste on an allowed host.
+/// Links to pastes' pages are turned into links to their raw contents.
+pub fn source_url(config: &PasteConfig, text: &str) -> Option<Url> {
+    if !config.enabled {
+        return None;
+    }
+    let text = text.trim();
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+    let url = Url::parse(text).ok()?;
+    if url.scheme() != "https" || !is_allowed(config, &url) {
+        return None;
+    }
+    let segments = url.path_segments()?.collect::<Vec<_>>();
+    let raw = match (url.host_str()?, segments.as_slice()) {
+        ("pastebin.com", [id]) => format!("https://pastebin.com/raw/{}", id),
+        ("gist.github.com", [user, id]) => {
+            format!("https://gist.githubusercontent.com/{}/{}/raw", user, id)
+        }
+        _ => return Some(url),
+    };
+    Url::parse(&raw).ok().filter(|raw| is_allowed(config, raw))
+}
+
+fn is_allowed(config: &PasteConfig, url: &Url) -> bool {
+    url.host_str()
+        .is_some_and(|host| config.allowed_hosts.iter().any(|h| h == host))
+}
+
+fn client(config: &PasteConfig) -> Result<Client, String> {
+    // Redirects could lead off the allowed hosts.
+    Client::builder()
+        .timeout(config.timeout)
+        .redirect(RedirectPolicy::none())
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Fetches the code at `url`, refusing pastes longer than the configured limit.
+pub fn fetch(config: &PasteConfig, url: Url) -> Result<String, String> {
+    let response = client(config)?
+        .get(url)
+        .send()
+        .map_err(|e| format!("error fetching the paste: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("the paste service answered {}", response.status()));
+    }
+    let mut code = String::new();
+    response
+        .take(config.max_fetch_bytes + 1)
+        .read_to_string(&mut code)
+        .map_err(|e| format!("error reading the paste: {}", e))?;
+    if code.len() as u64 > config.max_fetch_bytes {
+        return Err(format!(
+            "pastes may be at most {} KiB",
+            config.max_fetch_bytes / 1024
+        ));
+    }
+    Ok(code)
+}
+
+/// Uploads `text` to the configured service, and returns the URL of the paste. The service
+/// must answer a POST of the text with the paste's URL, as paste.rs does.
+pub fn upload(config: &PasteConfig, text: &str) -> Result<String, String> {
+    let upload_url = match &config.upload_url {
+        Some(url) if config.enabled => url,
+        _ => return Err("uploads are disabled".into()),
+    };
+    let mut response = client(config)?
+        .post(upload_url.as_str())
+        .body(text.to_string())
+        .send()
+        .map_err(|e| format!("error uploading: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("the paste service answered {}", response.status()));
+    }
+    let url = response
+        .text()
+        .map_err(|e| format!("error reading the paste URL: {}", e))?;
+    Ok(url.trim().to_string())
+}
@@ -0,0 +1,194 @@
+//! Per-user settings changed with `¡set`, saved to a file so they survive restarts.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use repl_bot::format::{Printing, QuoteInput};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+
+use crate::i18n::{fill, Catalog};
+
+/// Deepest nesting and most elements users may have printed; the character limit caps what
+/// is printed anyway.
+const MAX_PRINT_DEPTH: u64 = 1000;
+const MAX_PRINT_ITEMS: u64 = 10_000;
+
+/// Settings that apply to a user's evaluations. Unset ones take the configured defaults.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Preferences {
+    /// Each section of the user's replies is truncated to this many characters.
+    pub print_length: Option<usize>,
+    /// Lists and vectors nested deeper than this are elided.
+    pub print_depth: Option<usize>,
+    /// Elements of lists and vectors past this many are elided.
+    pub print_items: Option<usize>,
+    /// Whether structure appearing several times in a value is labelled.
+    pub print_shared: Option<bool>,
+    /// How much of their code the user's replies quote.
+    pub quote_input: Option<QuoteInput>,
+    /// Wall-clock time the user's evaluations may take.
+    pub timeout: Option<Duration>,
+    /// Whether code leaving lists open is closed and evaluated anyway.
+    pub auto_close: Option<bool>,
+}
+
+/// What a user may set their preferences to.
+pub struct Ceilings {
+    pub print_length: usize,
+    pub timeout: Duration,
+}
+
+/// Names of the settings. Catalogs describe them for `¡set`, in this order.
+pub const SETTINGS: [&str; 7] = [
+    "print-length",
+    "quote-input",
+    "print-depth",
+    "print-items",
+    "print-shared",
+    "timeout",
+    "auto-close",
+];
+
+impl Preferences {
+    /// Changes the setting `name` to `value`, or back to its default if `value` is
+    /// `default`. Errors are in the language of `catalog`.
+    pub fn set(
+        &mut self,
+        name: &str,
+        value: &str,
+        ceilings: &Ceilings,
+        catalog: &Catalog,
+    ) -> Result<(), String> {
+        let reset = value == "default";
+        match name {
+            "print-length" if reset => self.print_length = None,
+            "print-length" => {
+                self.print_length =
+                    Some(parse_bounded(value, ceilings.print_length as u64, catalog)? as usize)
+            }
+            "quote-input" if reset => self.quote_input = None,
+            "quote-input" => {
+                self.quote_input = Some(QuoteInput::parse(value).ok_or(catalog.quote_input_values)?)
+            }
+            "print-depth" if reset => self.print_depth = None,
+            "print-depth" => {
+                self.print_depth = Some(parse_bounded(value, MAX_PRINT_DEPTH, catalog)? as usize)
+            }
+            "print-items" if reset => self.print_items = None,
+            "print-items" => {
+                self.print_items = Some(parse_bounded(value, MAX_PRINT_ITEMS, catalog)? as usize)
+            }
+            "print-shared" if reset => self.print_shared = None,
+            "print-shared" => {
+                self.print_shared = Some(match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(catalog.print_shared_values.into()),
+                })
+            }
+            "timeout" if reset => self.timeout = None,
+            "timeout" => {
+                let secs = parse_bounded(value, ceilings.timeout.as_secs(), catalog)?;
+                self.timeout = Some(Duration::from_secs(secs));
+            }
+            "auto-close" if reset => self.auto_close = None,
+            "auto-close" => {
+                self.auto_close = Some(match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(catalog.auto_close_values.into()),
+                })
+            }
+            _ => return Err(fill(catalog.no_such_setting, &[&name])),
+        }
+        Ok(())
+    }
+
+    /// Describes the settings, for `¡set` without arguments.
+    pub fn describe(&self, catalog: &Catalog) -> String {
+        let or_default =
+            |value: Option<String>| value.unwrap_or_else(|| catalog.setting_default.into());
+        format!(
+            "print-length: {}\nquote-input: {}\nprint-depth: {}\nprint-items: {}\n\
+             print-shared: {}\ntimeout: {}\nauto-close: {}",
+            or_default(self.print_length.map(|n| n.to_string())),
+            or_default(self.quote_input.map(|q| q.name().to_string())),
+            or_default(self.print_depth.map(|n| n.to_string())),
+            or_default(self.print_items.map(|n| n.to_string())),
+            or_default(
+                self.print_shared
+                    .map(|shared| if shared { "on" } else { "off" }.to_string())
+            ),
+            or_default(self.timeout.map(|t| format!("{}s", t.as_secs()))),
+            or_default(
+                self.auto_close
+                    .map(|close| if close { "on" } else { "off" }.to_string())
+            ),
+        )
+    }
+
+    /// How the user's results are printed, given how they are by default. The character
+    /// limit can only be lowered, as replies can't hold more.
+    pub fn printing(&self, defaults: Printing) -> Printing {
+        Printing {
+            max_chars: self
+                .print_length
+                .map_or(defaults.max_chars, |n| n.min(defaults.max_chars)),
+            max_depth: self.print_depth.unwrap_or(defaults.max_depth),
+            max_items: self.print_items.unwrap_or(defaults.max_items),
+            shared: self.print_shared.unwrap_or(defaults.shared),
+        }
+    }
+}
+
+fn parse_bounded(value: &str, max: u64, catalog: &Catalog) -> Result<u64, String> {
+    match value.parse::<u64>() {
+        Ok(n) if (1..=max).contains(&n) => Ok(n),
+        _ => Err(fill(catalog.number_range, &[&max])),
+    }
+}
+
+/// Everyone's preferences, and the file they are saved to.
+pub struct PreferenceStore {
+    path: Option<String>,
+    /// Keyed by user ID, as JSON keys are strings.
+    users: HashMap<String, Preferences>,
+}
+
+impl PreferenceStore {
+    /// Loads the preferences saved at `path`, if any. Without a path, preferences are only
+    /// kept in memory.
+    pub fn load(path: Option<String>) -> Result<Self, String> {
+        let users = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("error parsing {}: {}", path, e))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(format!("error reading {}: {}", path, e)),
+            },
+            None => HashMap::new(),
+        };
+        Ok(Self { path, users })
+    }
+
+    pub fn get(&self, user: UserId) -> Preferences {
+        self.users
+            .get(&user.to_string())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Replaces the preferences of `user`, and saves everyone's.
+    pub fn set(&mut self, user: UserId, preferences: Preferences) -> Result<(), String> {
+        self.users.insert(user.to_string(), preferences);
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = serde_json::to_string_pretty(&self.users).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("error writing {}: {}", path, e))
+    }
+}
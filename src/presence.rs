@@ -0,0 +1,55 @@
+//! The bot's Discord presence, which shows whether the interpreter is busy or down.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serenity::model::gateway::Activity;
+use serenity::model::user::OnlineStatus;
+use serenity::prelude::{Context, Mutex};
+
+use crate::dispatch::Dispatcher;
+use crate::i18n::{fill, Catalog};
+use crate::version;
+
+/// How often the presence is refreshed. Discord only allows a few updates a minute.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(15);
+
+lazy_static! {
+    /// Shards whose updating thread was started, as `ready` fires again on reconnections.
+    static ref STARTED: Mutex<HashSet<u64>> = Mutex::new(HashSet::new());
+}
+
+/// Sets the presence on the shard of `ctx`, then keeps it up to date with the interpreter's
+/// state. Presences are seen in every guild, so they are in the bot's default language.
+pub fn start(ctx: Context, dispatcher: Arc<Dispatcher>, catalog: &'static Catalog) {
+    if !STARTED.lock().insert(ctx.shard_id) {
+        return;
+    }
+    let version = version::peroxide_version();
+    thread::spawn(move || {
+        let mut current = None;
+        loop {
+            let presence = describe(&dispatcher, &version, catalog);
+            if current.as_ref() != Some(&presence) {
+                let (activity, status) = &presence;
+                ctx.set_presence(Some(Activity::playing(activity)), *status);
+                current = Some(presence);
+            }
+            thread::sleep(UPDATE_INTERVAL);
+        }
+    });
+}
+
+fn describe(dispatcher: &Dispatcher, version: &str, catalog: &Catalog) -> (String, OnlineStatus) {
+    if !dispatcher.is_alive() {
+        return (catalog.presence_down.into(), OnlineStatus::DoNotDisturb);
+    }
+    let activity = match dispatcher.pending() {
+        0 => format!("peroxide {}", version),
+        1 => catalog.presence_busy.into(),
+        pending => fill(catalog.presence_queue, &[&pending]),
+    };
+    (activity, OnlineStatus::Online)
+}
@@ -0,0 +1,110 @@
+//! Definitions proposed with `¡propose`, voted on with reactions, and the guild preludes
+//! the approved ones are added to.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+
+use serenity::model::id::{GuildId, MessageId, UserId};
+
+pub const APPROVE_EMOJI: &str = "👍";
+pub const REJECT_EMOJI: &str = "👎";
+
+pub struct Proposal {
+    pub guild: GuildId,
+    pub author: UserId,
+    pub code: String,
+    approvals: HashSet<UserId>,
+    rejections: HashSet<UserId>,
+}
+
+impl Proposal {
+    pub fn new(guild: GuildId, author: UserId, code: String) -> Self {
+        Self {
+            guild,
+            author,
+            code,
+            approvals: HashSet::new(),
+            rejections: HashSet::new(),
+        }
+    }
+
+    /// Approvals minus rejections.
+    pub fn score(&self) -> i64 {
+        self.approvals.len() as i64 - self.rejections.len() as i64
+    }
+
+    /// Records `user`'s reaction `emoji` being added or removed. The author's own votes
+    /// don't count.
+    pub fn vote(&mut self, user: UserId, emoji: &str, added: bool) {
+        if user == self.author {
+            return;
+        }
+        let votes = match emoji {
+            APPROVE_EMOJI => &mut self.approvals,
+            REJECT_EMOJI => &mut self.rejections,
+            _ => return,
+        };
+        if added {
+            votes.insert(user);
+        } else {
+            votes.remove(&user);
+        }
+    }
+}
+
+/// Proposals still being voted on, by the message presenting them.
+pub type OpenProposals = HashMap<MessageId, Proposal>;
+
+/// Approved definitions of each guild, and the file they are saved to. They are run in the
+/// guild's environment when the bot starts and when it is reloaded.
+pub struct PreludeStore {
+    path: Option<String>,
+    /// Keyed by guild ID, as JSON keys are strings.
+    guilds: HashMap<String, Vec<String>>,
+}
+
+impl PreludeStore {
+    /// Loads the preludes saved at `path`, if any. Without a path, they are only kept in
+    /// memory.
+    pub fn load(path: Option<String>) -> Result<Self, String> {
+        let guilds = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("error parsing {}: {}", path, e))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(format!("error reading {}: {}", path, e)),
+            },
+            None => HashMap::new(),
+        };
+        Ok(Self { path, guilds })
+    }
+
+    /// The approved definitions of `guild`, in the order they were approved.
+    pub fn guild(&self, guild: GuildId) -> &[String] {
+        self.guilds
+            .get(&guild.to_string())
+            .map_or(&[], |definitions| definitions.as_slice())
+    }
+
+    /// Every guild with approved definitions, and its definitions.
+    pub fn guilds(&self) -> Vec<(GuildId, &[String])> {
+        self.guilds
+            .iter()
+            .filter_map(|(id, definitions)| {
+                Some((GuildId(id.parse().ok()?), definitions.as_slice()))
+            })
+            .collect()
+    }
+
+    /// Adds `code` to the prelude of `guild`, and saves every guild's.
+    pub fn append(&mut self, guild: GuildId, code: String) -> Result<(), String> {
+        self.guilds.entry(guild.to_string()).or_default().push(code);
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = serde_json::to_string_pretty(&self.guilds).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("error writing {}: {}", path, e))
+    }
+}
@@ -0,0 +1,642 @@
+//! Running the interpreters in a separate, locked-down process.
+//!
+//! In sandbox mode the bot starts a copy of itself with `CHILD_FLAG`. The child owns every
+//! interpreter and serves jobs read from its stdin, one JSON object per line, answering each
+//! on its stdout. It starts with an empty environment, so it never sees the bot's token, and
+//! gets the configuration without the HTTP API's token either. It locks itself down before
+//! reading its first job:
+//!
+//! - its address space, file sizes and open files are capped with rlimits;
+//! - on Linux (x86-64 and ARM64), a seccomp filter makes every system call outside of what
+//!   the interpreter needs to compute, allocate and run the watchdog fail, which rules out
+//!   opening files, sockets or other processes.
+//!
+//! The parent side replaces the worker thread: it forwards jobs to the child, and restarts
+//! the child if it dies. It keeps a copy of each guild's chosen features and committed
+//! programs, which a restarted child gets back; DM and pair sessions start over.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{self, Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+use repl_bot::dispatch::reply;
+use repl_bot::limits::{self, Limits};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use tracing::{error, warn};
+
+use crate::challenge::{Judgement, TestCase};
+use crate::config::{Config, SandboxConfig};
+use crate::interpreter::{Evaluation, Request, StartupSources};
+use crate::logging;
+use crate::worker::{Committed, Job, SessionKey, Sessions};
+
+/// Command line flag that makes the bot run as a sandbox child.
+pub const CHILD_FLAG: &str = "--sandbox-child";
+/// Environment variable through which the child gets the configuration, without secrets.
+const CONFIG_VAR: &str = "PEROXIDE_CONFIG";
+
+#[derive(Serialize, Deserialize)]
+enum WireJob {
+    Evaluate {
+        session: SessionKey,
+        request: Request,
+    },
+    Judge {
+        submission: String,
+        tests: Vec<TestCase>,
+        limits: Limits,
+    },
+    Install {
+        guild: GuildId,
+        request: Request,
+    },
+    Commit {
+        guild: GuildId,
+        user: UserId,
+    },
+    SourceOf {
+        session: SessionKey,
+        name: String,
+    },
+    Bindings {
+        session: SessionKey,
+    },
+    Export {
+        session: SessionKey,
+    },
+    Reset {
+        session: SessionKey,
+    },
+    SetFeatures {
+        guild: GuildId,
+        features: Vec<String>,
+    },
+    /// Asks for the programs committed to a guild's environment.
+    Snapshot {
+        guild: GuildId,
+    },
+    /// Gives a guild back what a previous child had of it.
+    Restore {
+        guild: GuildId,
+        features: Option<Vec<String>>,
+        committed: Vec<Request>,
+    },
+    Ping,
+}
+
+#[derive(Serialize, Deserialize)]
+enum WireReply {
+    Evaluation(Evaluation),
+    Judgement(Judgement),
+    Commit(Result<Committed, String>),
+    SourceOf(Option<String>),
+    Bindings(Vec<String>),
+    Export(String),
+    Reset(Result<(), String>),
+    SetFeatures(Result<(), String>),
+    Snapshot(Vec<Request>),
+    Restored,
+    Pong,
+}
+
+struct Sandbox {
+    process: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Sandbox {
+    fn spawn(config_path: &str) -> Result<Self, String> {
+        let exe = env::current_exe().map_err(|e| format!("can't find the bot binary: {}", e))?;
+        let config = Config::without_secrets(config_path)?;
+        let mut process = Command::new(exe)
+            .arg(CHILD_FLAG)
+            .env_clear()
+            .env(CONFIG_VAR, config)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("can't start the sandbox: {}", e))?;
+        Ok(Self {
+            stdin: process.stdin.take().unwrap(),
+            stdout: BufReader::new(process.stdout.take().unwrap()),
+            process,
+        })
+    }
+
+    fn call(&mut self, job: &WireJob) -> Result<WireReply, String> {
+        let mut line = serde_json::to_string(job).map_err(|e| e.to_string())?;
+        line.push('\n');
+        self.stdin
+            .write_all(line.as_bytes())
+            .and_then(|()| self.stdin.flush())
+            .map_err(|e| format!("error writing to the sandbox: {}", e))?;
+        line.clear();
+        match self.stdout.read_line(&mut line) {
+            Ok(0) => Err("the sandbox exited".into()),
+            Ok(_) => serde_json::from_str(&line).map_err(|e| format!("bad reply: {}", e)),
+            Err(e) => Err(format!("error reading from the sandbox: {}", e)),
+        }
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// What the parent keeps of a guild's environment, for a restarted sandbox.
+#[derive(Default)]
+struct SavedGuild {
+    /// The features the guild's admins chose, if they did.
+    features: Option<Vec<String>>,
+    committed: Vec<Request>,
+}
+
+/// The sandbox jobs are forwarded to, started on first use and restarted after it fails.
+struct Forwarder {
+    config_path: String,
+    sandbox: Option<Sandbox>,
+    guilds: HashMap<GuildId, SavedGuild>,
+}
+
+impl Forwarder {
+    fn call(&mut self, job: WireJob) -> Result<WireReply, String> {
+        if self.sandbox.is_none() {
+            let mut sandbox = Sandbox::spawn(&self.config_path)?;
+            self.restore(&mut sandbox);
+            self.sandbox = Some(sandbox);
+        }
+        let result = self.sandbox.as_mut().unwrap().call(&job);
+        result.map_err(|e| {
+            warn!("Sandbox failed, restarting it: {}", e);
+            self.sandbox = None;
+            format!(
+                "{} (the sandbox was restarted: server environments are kept, but DM and \
+                 pair sessions start over)",
+                e
+            )
+        })
+    }
+
+    /// Gives a new sandbox the guild environments the previous ones had.
+    fn restore(&self, sandbox: &mut Sandbox) {
+        for (guild, saved) in &self.guilds {
+            let job = WireJob::Restore {
+                guild: *guild,
+                features: saved.features.clone(),
+                committed: saved.committed.clone(),
+            };
+            match sandbox.call(&job) {
+                Ok(WireReply::Restored) => {}
+                Ok(_) => error!(
+                    guild = guild.0,
+                    "Unexpected reply from the sandbox to a restore"
+                ),
+                Err(e) => error!(
+                    guild = guild.0,
+                    "Error restoring a guild in the sandbox: {}", e
+                ),
+            }
+        }
+    }
+
+    /// Keeps a copy of the programs committed to the environment of `guild`.
+    fn save(&mut self, guild: GuildId) {
+        match self.call(WireJob::Snapshot { guild }) {
+            Ok(WireReply::Snapshot(committed)) => {
+                self.guilds.entry(guild).or_default().committed = committed;
+            }
+            Ok(_) => error!(
+                guild = guild.0,
+                "Unexpected reply from the sandbox to a snapshot"
+            ),
+            Err(e) => error!(guild = guild.0, "Error saving a guild's environment: {}", e),
+        }
+    }
+}
+
+/// Runs jobs from `jobs` in a sandbox process until every sender is dropped. If the sandbox
+/// fails, the job fails and a new sandbox is started for the next one.
+pub fn run(jobs: Receiver<Job>, config_path: String) {
+    let mut forwarder = Forwarder {
+        config_path,
+        sandbox: None,
+        guilds: HashMap::new(),
+    };
+
+    while let Ok(job) = jobs.recv() {
+        match job {
+            Job::Evaluate {
+                session,
+                request,
+                response,
+            } => {
+                let evaluation = match forwarder.call(WireJob::Evaluate { session, request }) {
+                    Ok(WireReply::Evaluation(evaluation)) => evaluation,
+                    Ok(_) => Evaluation::failed("unexpected reply from the sandbox".into()),
+                    Err(e) => Evaluation::failed(format!("sandbox error: {}", e)),
+                };
+                // Bot commands are committed as soon as they are defined.
+                let committed = !evaluation.defined_commands.is_empty();
+                reply(response, evaluation, "evaluate");
+                if let (SessionKey::Guild(guild), true) = (session, committed) {
+                    forwarder.save(guild);
+                }
+            }
+            Job::Judge {
+                submission,
+                tests,
+                limits,
+                response,
+            } => {
+                let job = WireJob::Judge {
+                    submission,
+                    tests,
+                    limits,
+                };
+                let judgement = match forwarder.call(job) {
+                    Ok(WireReply::Judgement(judgement)) => judgement,
+                    Ok(_) => Judgement {
+                        submission: Evaluation::failed("unexpected reply from the sandbox".into()),
+                        tests: Vec::new(),
+                    },
+                    Err(e) => Judgement {
+                        submission: Evaluation::failed(format!("sandbox error: {}", e)),
+                        tests: Vec::new(),
+                    },
+                };
+                reply(response, judgement, "judge");
+            }
+            Job::Install {
+                guild,
+                request,
+                response,
+            } => {
+                let evaluation = match forwarder.call(WireJob::Install { guild, request }) {
+                    Ok(WireReply::Evaluation(evaluation)) => evaluation,
+                    Ok(_) => Evaluation::failed("unexpected reply from the sandbox".into()),
+                    Err(e) => Evaluation::failed(format!("sandbox error: {}", e)),
+                };
+                let installed = evaluation.succeeded();
+                reply(response, evaluation, "install");
+                if installed {
+                    forwarder.save(guild);
+                }
+            }
+            Job::Commit {
+                guild,
+                user,
+                response,
+            } => {
+                let result = match forwarder.call(WireJob::Commit { guild, user }) {
+                    Ok(WireReply::Commit(committed)) => committed,
+                    Ok(_) => Err("unexpected reply from the sandbox".into()),
+                    Err(e) => Err(format!("sandbox error: {}", e)),
+                };
+                let committed = matches!(&result, Ok(committed) if committed.count > 0);
+                reply(response, result, "commit");
+                if committed {
+                    forwarder.save(guild);
+                }
+            }
+            Job::SourceOf {
+                session,
+                name,
+                response,
+            } => {
+                let result = match forwarder.call(WireJob::SourceOf { session, name }) {
+                    Ok(WireReply::SourceOf(source)) => Ok(source),
+                    Ok(_) => Err("unexpected reply from the sandbox".into()),
+                    Err(e) => Err(format!("sandbox error: {}", e)),
+                };
+                reply(response, result, "source-of");
+            }
+            Job::Bindings { session, response } => {
+                let result = match forwarder.call(WireJob::Bindings { session }) {
+                    Ok(WireReply::Bindings(names)) => Ok(names),
+                    Ok(_) => Err("unexpected reply from the sandbox".into()),
+                    Err(e) => Err(format!("sandbox error: {}", e)),
+                };
+                reply(response, result, "bindings");
+            }
+            Job::Export { session, response } => {
+                let result = match forwarder.call(WireJob::Export { session }) {
+                    Ok(WireReply::Export(program)) => Ok(program),
+                    Ok(_) => Err("unexpected reply from the sandbox".into()),
+                    Err(e) => Err(format!("sandbox error: {}", e)),
+                };
+                reply(response, result, "export");
+            }
+            Job::Reset { session, response } => {
+                let result = match forwarder.call(WireJob::Reset { session }) {
+                    Ok(WireReply::Reset(result)) => result,
+                    Ok(_) => Err("unexpected reply from the sandbox".into()),
+                    Err(e) => Err(format!("sandbox error: {}", e)),
+                };
+                // A reset guild keeps its features.
+                if let (SessionKey::Guild(guild), Ok(())) = (session, &result) {
+                    if let Some(saved) = forwarder.guilds.get_mut(&guild) {
+                        saved.committed.clear();
+                    }
+                }
+                reply(response, result, "reset");
+            }
+            Job::SetFeatures {
+                guild,
+                features,
+                response,
+            } => {
+                let job = WireJob::SetFeatures {
+                    guild,
+                    features: features.clone(),
+                };
+                let result = match forwarder.call(job) {
+                    Ok(WireReply::SetFeatures(result)) => result,
+                    Ok(_) => Err("unexpected reply from the sandbox".into()),
+                    Err(e) => Err(format!("sandbox error: {}", e)),
+                };
+                if result.is_ok() {
+                    forwarder.guilds.insert(
+                        guild,
+                        SavedGuild {
+                            features: Some(features),
+                            committed: Vec::new(),
+                        },
+                    );
+                }
+                reply(response, result, "set-features");
+            }
+            // Only a sandbox that answers counts as alive.
+            Job::Ping { response } => match forwarder.call(WireJob::Ping) {
+                Ok(WireReply::Pong) => {
+                    let _ = response.send(());
+                }
+                Ok(_) => error!("Unexpected reply from the sandbox to a ping"),
+                Err(e) => error!("Sandbox error answering a ping: {}", e),
+            },
+        }
+    }
+}
+
+/// Entry point of the sandbox child. If it can't start, it exits with an error, and the
+/// parent starts another for the next job.
+pub fn child_main() {
+    let config = match env::var(CONFIG_VAR) {
+        Ok(config) => Config::from_toml(&config),
+        Err(_) => Err("the sandbox needs a configuration".into()),
+    };
+    let config = match config {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("Error loading configuration: {}", e);
+            process::exit(1);
+        }
+    };
+    // stdout carries the replies.
+    if let Err(e) = logging::init(&config.logging, logging::Output::Stderr) {
+        eprintln!("Error setting up logging: {}", e);
+        process::exit(1);
+    }
+    let (mut sessions, mut replies) = match start(config) {
+        Ok(started) => started,
+        Err(e) => {
+            error!("Error starting the sandbox: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let reply = match serde_json::from_str(&line) {
+            Ok(WireJob::Evaluate { session, request }) => {
+                WireReply::Evaluation(sessions.evaluate(session, &request))
+            }
+            Ok(WireJob::Judge {
+                submission,
+                tests,
+                limits,
+            }) => WireReply::Judgement(sessions.judge(&submission, &tests, limits)),
+            Ok(WireJob::Install { guild, request }) => {
+                WireReply::Evaluation(sessions.install(guild, &request))
+            }
+            Ok(WireJob::Commit { guild, user }) => WireReply::Commit(sessions.commit(guild, user)),
+            Ok(WireJob::SourceOf { session, name }) => {
+                WireReply::SourceOf(sessions.source_of(session, &name))
+            }
+            Ok(WireJob::Bindings { session }) => WireReply::Bindings(sessions.bindings(session)),
+            Ok(WireJob::Export { session }) => WireReply::Export(sessions.export(session)),
+            Ok(WireJob::Reset { session }) => WireReply::Reset(sessions.reset(session)),
+            Ok(WireJob::SetFeatures { guild, features }) => {
+                WireReply::SetFeatures(sessions.set_features(guild, features))
+            }
+            Ok(WireJob::Snapshot { guild }) => WireReply::Snapshot(sessions.committed(guild)),
+            Ok(WireJob::Restore {
+                guild,
+                features,
+                committed,
+            }) => {
+                sessions.restore(guild, features, committed);
+                WireReply::Restored
+            }
+            Ok(WireJob::Ping) => WireReply::Pong,
+            Err(e) => WireReply::Evaluation(Evaluation::failed(format!("bad job: {}", e))),
+        };
+        let written = serde_json::to_string(&reply)
+            .map_err(|e| e.to_string())
+            .and_then(|reply| writeln!(replies, "{}", reply).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Error writing sandbox reply: {}", e);
+            break;
+        }
+    }
+}
+
+/// Sets up the child's interpreters and its replies, then locks it down.
+fn start(config: Arc<Config>) -> Result<(Sessions, File), String> {
+    // Everything that needs the file system happens before locking down.
+    let sources = StartupSources::read(&config.startup)
+        .map_err(|e| format!("error reading startup files: {}", e))?;
+    let sessions = Sessions::new(config.clone(), Some(sources))
+        .map_err(|e| format!("error initializing interpreter: {}", e))?;
+    // Anything else printing to stdout would corrupt the replies, so they get a copy of it
+    // of their own and stdout goes to stderr.
+    let replies = os::take_stdout().map_err(|e| format!("error redirecting stdout: {}", e))?;
+    // The filter still lets a watchdog that panicked be restarted, but the first one starts
+    // before it is installed.
+    limits::start_watchdog();
+    lock_down(&config.sandbox).map_err(|e| format!("error locking down: {}", e))?;
+    Ok((sessions, replies))
+}
+
+fn lock_down(config: &SandboxConfig) -> Result<(), String> {
+    os::set_limit(libc::RLIMIT_AS, config.memory_limit)?;
+    os::set_limit(libc::RLIMIT_FSIZE, 0)?;
+    os::set_limit(libc::RLIMIT_CORE, 0)?;
+    // Only stdin, stdout, stderr and the replies.
+    os::set_limit(libc::RLIMIT_NOFILE, 4)?;
+    os::install_syscall_filter()
+}
+
+mod os {
+    use std::fs::File;
+    use std::os::unix::io::FromRawFd;
+
+    /// Points stdout at stderr, and returns a file writing to the original stdout.
+    pub fn take_stdout() -> Result<File, String> {
+        // Safe: the new descriptor is owned by the returned file alone.
+        unsafe {
+            let fd = libc::dup(1);
+            if fd < 0 || libc::dup2(2, 1) < 0 {
+                return Err(std::io::Error::last_os_error().to_string());
+            }
+            Ok(File::from_raw_fd(fd))
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    type Resource = libc::__rlimit_resource_t;
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    type Resource = libc::c_int;
+
+    pub fn set_limit(resource: Resource, value: u64) -> Result<(), String> {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        // Safe: the pointer is to a valid rlimit for the duration of the call.
+        if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "setrlimit({}): {}",
+                resource,
+                std::io::Error::last_os_error()
+            ))
+        }
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    pub fn install_syscall_filter() -> Result<(), String> {
+        const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+        const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+        const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+        const BPF_LD_W_ABS: u16 = 0x20;
+        const BPF_JEQ_K: u16 = 0x15;
+        const BPF_RET_K: u16 = 0x06;
+        // Offsets into struct seccomp_data.
+        const NR_OFFSET: u32 = 0;
+        const ARCH_OFFSET: u32 = 4;
+        #[cfg(target_arch = "x86_64")]
+        const AUDIT_ARCH: u32 = 0xc000_003e;
+        #[cfg(target_arch = "aarch64")]
+        const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+        let allowed = [
+            libc::SYS_read,
+            libc::SYS_write,
+            libc::SYS_close,
+            libc::SYS_brk,
+            libc::SYS_mmap,
+            libc::SYS_munmap,
+            libc::SYS_mremap,
+            libc::SYS_mprotect,
+            libc::SYS_madvise,
+            libc::SYS_futex,
+            // Starting threads, like a watchdog after one panicked: stacks are mapped, and
+            // the thread set up with these.
+            libc::SYS_clone,
+            libc::SYS_clone3,
+            libc::SYS_set_robust_list,
+            libc::SYS_rseq,
+            libc::SYS_sigaltstack,
+            libc::SYS_rt_sigaction,
+            libc::SYS_rt_sigprocmask,
+            libc::SYS_rt_sigreturn,
+            libc::SYS_sched_yield,
+            libc::SYS_nanosleep,
+            libc::SYS_clock_nanosleep,
+            libc::SYS_clock_gettime,
+            libc::SYS_getrandom,
+            libc::SYS_getpid,
+            libc::SYS_gettid,
+            libc::SYS_tgkill,
+            libc::SYS_exit,
+            libc::SYS_exit_group,
+        ];
+
+        let statement = |code: u16, k: u32| libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jump_if = |k: u32, jt: u8, jf: u8| libc::sock_filter {
+            code: BPF_JEQ_K,
+            jt,
+            jf,
+            k,
+        };
+        let mut program = vec![
+            statement(BPF_LD_W_ABS, ARCH_OFFSET),
+            jump_if(AUDIT_ARCH, 1, 0),
+            statement(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+            statement(BPF_LD_W_ABS, NR_OFFSET),
+        ];
+        for syscall in allowed.iter() {
+            program.push(jump_if(*syscall as u32, 0, 1));
+            program.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW));
+        }
+        program.push(statement(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        let filter = libc::sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_mut_ptr(),
+        };
+
+        // Safe: prctl only reads the filter, which outlives the calls.
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(format!(
+                    "PR_SET_NO_NEW_PRIVS: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &filter as *const libc::sock_fprog,
+            ) != 0
+            {
+                return Err(format!(
+                    "PR_SET_SECCOMP: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    )))]
+    pub fn install_syscall_filter() -> Result<(), String> {
+        tracing::warn!("No system call filter on this platform; the sandbox only has rlimits.");
+        Ok(())
+    }
+}
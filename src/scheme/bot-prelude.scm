@@ -0,0 +1,367 @@
+; Definitions the bot installs on top of the standard library.
+;
+; Names starting with %bot- are the bot's own. Each interpreter replaces that prefix with one
+; of its own that programs can't guess before loading this file, so that only the bot can
+; call or redefine them; programs use the procedures without the prefix.
+;
+; Output procedures are redefined to write to a buffer instead of the bot's stdout when they
+; are given no port, so that what a program prints can be shown alongside its result. The
+; buffer is drained by the bot after each evaluation with (%bot-take-output). Given a port,
+; they call the original procedure, which the bot keeps as %bot-original-display and so on
+; before loading this file.
+;
+; Bot commands can be defined with (define-command "name" handler); the handler is called
+; with the command's arguments as a list of strings when someone sends `¡name args...`.
+; Definitions are pending until the bot accepts them after the evaluation, which it only
+; does for admins. The tables live in a closure, reached only through the bot's procedures.
+;
+; (bot-say text), (bot-react emoji) and (message-author) act on the Discord message that
+; triggered the evaluation. The bot sets the invocation before each evaluation and clears it
+; afterwards, so these error outside of one; actions are queued and drained by the bot with
+; (%bot-next-action) once the evaluation is done. The bot keeps count of them, and only runs
+; as many as an evaluation may do.
+;
+; (read-line) and (read) take their input from lines the bot collects from the program's
+; author, set with (%bot-set-input! lines) before each evaluation; each line comes with what
+; read returns for it. A program that wants more lines than it has stops with the
+; %bot-needs-input error, and the bot asks for another line and runs it again from the start.
+;
+; Results that are lists or vectors are printed by (%bot-print-result value depth items chars
+; shared?), which the bot wraps around each top-level expression: it elides what is nested
+; deeper than depth or comes after the first items elements, stops after about chars
+; characters, and labels structure that refers back to itself, as in #0=(1 . #0#), so that
+; circular values can be shown. With shared?, structure that merely appears twice is labelled
+; too. Limits of 0 mean none. The printed text is taken with (%bot-take-printed), and is ""
+; for other values, which the interpreter prints itself. (%bot-take-elided) tells whether
+; anything was left out; if so, (%bot-reprint-result depth items chars shared?) prints the
+; same value again with other limits.
+;
+; Procedures that can allocate far more than their arguments take up are wrapped by the bot
+; with (%bot-guard-allocation name procedure size), so that they refuse calls that would
+; exceed the memory limit in one go, which the bot's watchdog can't catch in time as it only
+; notices after the fact.
+;
+; This file is a single (begin ...) form so that it can be read in one go.
+
+(begin
+  ; What challenge tests compare with, kept before submissions run so that they can't
+  ; redefine it.
+  (define %bot-equal? equal?)
+
+  (define %bot-output '())
+
+  (define (%bot-emit s)
+    (set! %bot-output (cons s %bot-output)))
+
+  (define (%bot-take-output)
+    (let ((output (apply string-append (reverse %bot-output))))
+      (set! %bot-output '())
+      output))
+
+  (define (%bot->string x write?)
+    (cond ((string? x) (if write? (string-append "\"" x "\"") x))
+          ((symbol? x) (symbol->string x))
+          ((number? x) (number->string x))
+          ((char? x) (if write? (string-append "#\\" (string x)) (string x)))
+          ((eq? x #t) "#t")
+          ((eq? x #f) "#f")
+          ((null? x) "()")
+          ((pair? x) (string-append "(" (%bot-list->string x write?) ")"))
+          ((vector? x) (string-append "#" (%bot->string (vector->list x) write?)))
+          ((procedure? x) "#<procedure>")
+          (else "#<object>")))
+
+  (define (%bot-list->string x write?)
+    (let ((head (%bot->string (car x) write?)))
+      (cond ((null? (cdr x)) head)
+            ((pair? (cdr x)) (string-append head " " (%bot-list->string (cdr x) write?)))
+            (else (string-append head " . " (%bot->string (cdr x) write?))))))
+
+  (define (display x . port)
+    (if (null? port)
+        (%bot-emit (%bot->string x #f))
+        (apply %bot-original-display x port)))
+  (define (write x . port)
+    (if (null? port)
+        (%bot-emit (%bot->string x #t))
+        (apply %bot-original-write x port)))
+  (define (write-string s . port)
+    (if (null? port)
+        (%bot-emit s)
+        (apply %bot-original-write-string s port)))
+  (define (write-char c . port)
+    (if (null? port)
+        (%bot-emit (string c))
+        (apply %bot-original-write-char c port)))
+  (define (newline . port)
+    (if (null? port)
+        (%bot-emit "\n")
+        (apply %bot-original-newline port)))
+
+  ; Largest number of elements a single allocation may have, set by the bot before each
+  ; evaluation.
+  (define %bot-allocation-limit 1000000)
+
+  (define (%bot-set-allocation-limit! n)
+    (set! %bot-allocation-limit n))
+
+  ; make, called name, refusing calls for which (size args) is more elements than the limit.
+  (define (%bot-guard-allocation name make size)
+    (lambda args
+      (let ((n (size args)))
+        (if (> n %bot-allocation-limit)
+            (error (string-append (symbol->string name)
+                                  ": memory limit exceeded: allocation too large")
+                   n)
+            (apply make args)))))
+
+  ; The element count args starts with, as in (make-vector k fill).
+  (define (%bot-first-count args)
+    (if (and (pair? args) (integer? (car args))) (car args) 0))
+
+  ; The sum of (size x) for each x in args for which (kind? x).
+  (define (%bot-total-size kind? size args)
+    (let loop ((args args) (total 0))
+      (cond ((null? args) total)
+            ((kind? (car args)) (loop (cdr args) (+ total (size (car args)))))
+            (else (loop (cdr args) total)))))
+
+  (define define-command #f)
+  (define %bot-take-pending-commands #f)
+  (define %bot-accept-commands! #f)
+  (define %bot-discard-commands! #f)
+  (define %bot-run-command #f)
+
+  (let ((commands '())
+        (pending '()))
+    (set! define-command
+          (lambda (name handler)
+            (if (and (string? name) (procedure? handler))
+                (set! pending (cons (cons name handler) pending))
+                (error "define-command: expected a name and a procedure" name))))
+    ; Names of the pending commands, one per line.
+    (set! %bot-take-pending-commands
+          (lambda ()
+            (apply string-append
+                   (map (lambda (command) (string-append (car command) "\n"))
+                        (reverse pending)))))
+    (set! %bot-accept-commands!
+          (lambda ()
+            (set! commands (append pending commands))
+            (set! pending '())))
+    (set! %bot-discard-commands!
+          (lambda ()
+            (set! pending '())))
+    (set! %bot-run-command
+          (lambda (name args)
+            (let ((command (assoc name commands)))
+              (if command
+                  ((cdr command) args)
+                  (error "unknown command" name))))))
+
+  (define bot-say #f)
+  (define bot-react #f)
+  (define message-author #f)
+  (define %bot-begin-invocation! #f)
+  (define %bot-end-invocation! #f)
+  (define %bot-next-action #f)
+
+  (let ((author #f)
+        (actions '()))
+    (define (queue! who kind text)
+      (cond ((not author) (error (string-append who ": no Discord message to act on")))
+            ((not (string? text)) (error (string-append who ": expected a string") text))
+            (else
+             (set! actions (append actions (list (string-append kind ":" text)))))))
+    (set! bot-say
+          (lambda (text)
+            (if (and (string? text) (> (string-length text) 2000))
+                (error "bot-say: message longer than 2000 characters")
+                (queue! "bot-say" "say" text))))
+    (set! bot-react
+          (lambda (emoji)
+            (queue! "bot-react" "react" emoji)))
+    (set! message-author
+          (lambda ()
+            (or author (error "message-author: no Discord message to act on"))))
+    (set! %bot-begin-invocation!
+          (lambda (name)
+            (set! author name)
+            (set! actions '())))
+    (set! %bot-end-invocation!
+          (lambda ()
+            (set! author #f)))
+    ; The oldest queued action as "kind:text", or "" when there are none left.
+    (set! %bot-next-action
+          (lambda ()
+            (if (null? actions)
+                ""
+                (let ((action (car actions)))
+                  (set! actions (cdr actions))
+                  action)))))
+
+  (define read-line #f)
+  (define read #f)
+  (define %bot-set-input! #f)
+
+  (let ((input '()))
+    (define (next-line!)
+      (if (null? input)
+          (error "%bot-needs-input")
+          (let ((line (car input)))
+            (set! input (cdr input))
+            line)))
+    (set! %bot-set-input!
+          (lambda (lines)
+            (set! input lines)))
+    (set! read-line
+          (lambda port
+            (car (next-line!))))
+    (set! read
+          (lambda port
+            (let ((line (next-line!)))
+              (if (null? (cdr line))
+                  (error "read: input is not a single datum" (car line))
+                  (cadr line))))))
+
+  (define %bot-printed "")
+  (define %bot-elided #f)
+  ; Kept until it's known whether it needs printing again.
+  (define %bot-last-result #f)
+
+  (define (%bot-take-printed)
+    (let ((printed %bot-printed))
+      (set! %bot-printed "")
+      printed))
+
+  (define (%bot-take-elided)
+    (let ((elided %bot-elided))
+      (set! %bot-elided #f)
+      (if (not elided) (set! %bot-last-result #f))
+      elided))
+
+  (define (%bot-reprint-result depth items chars shared?)
+    (set! %bot-printed (%bot-write-limited %bot-last-result depth items chars shared?))
+    (set! %bot-last-result #f)
+    (set! %bot-elided #f)
+    #t)
+
+  (define (%bot-compound? x)
+    (or (pair? x) (vector? x)))
+
+  ; Images are parsed by the bot, so the interpreter prints them in full.
+  (define (%bot-print-result x depth items chars shared?)
+    (if (and (%bot-compound? x) (not (and (pair? x) (eq? (car x) '%image))))
+        (begin
+          (set! %bot-last-result x)
+          (set! %bot-printed (%bot-write-limited x depth items chars shared?))
+          #t)
+        x))
+
+  (define (%bot-write-limited x depth items chars shared?)
+    (let ((seen '())
+          (labelled '())
+          (walk-budget chars)
+          (labels '())
+          (next-label 0)
+          (pieces '())
+          (left chars))
+      (define (too-deep? level)
+        (and (> depth 0) (>= level depth)))
+      (define (too-many? index)
+        (and (> items 0) (>= index items)))
+      (define (out-of-budget?)
+        (and (> chars 0) (<= walk-budget 0)))
+      (define (label! x)
+        (if (not (memq x labelled))
+            (set! labelled (cons x labelled))))
+      ; Finds the structure to label, looking at no more than will be printed.
+      (define (walk! x level path)
+        (cond ((not (%bot-compound? x)) #f)
+              ((memq x path) (label! x))
+              ((memq x seen) (if shared? (label! x)))
+              ((or (too-deep? level) (out-of-budget?)) #f)
+              (else
+               (set! seen (cons x seen))
+               (set! walk-budget (- walk-budget 1))
+               (let ((path (cons x path)))
+                 (if (pair? x)
+                     (begin
+                       (walk! (car x) (+ level 1) path)
+                       (walk-rest! (cdr x) level 1 path))
+                     (walk-vector! x 0 (+ level 1) path))))))
+      ; The cdr of a list is printed at the same level, as its elements are.
+      (define (walk-rest! rest level index path)
+        (cond ((not (pair? rest)) (walk! rest level path))
+              ((or (memq rest path) (memq rest seen)) (walk! rest level path))
+              ((or (too-many? index) (out-of-budget?)) #f)
+              (else
+               (set! seen (cons rest seen))
+               (set! walk-budget (- walk-budget 1))
+               (let ((path (cons rest path)))
+                 (walk! (car rest) (+ level 1) path)
+                 (walk-rest! (cdr rest) level (+ index 1) path)))))
+      (define (walk-vector! v index level path)
+        (if (and (< index (vector-length v)) (not (too-many? index)))
+            (begin
+              (walk! (vector-ref v index) level path)
+              (walk-vector! v (+ index 1) level path))))
+      (define (exhausted?)
+        (and (> chars 0) (<= left 0)))
+      (define (emit! s)
+        (if (not (exhausted?))
+            (begin
+              (set! pieces (cons s pieces))
+              (set! left (- left (string-length s))))))
+      (define (elide! s)
+        (set! %bot-elided #t)
+        (emit! s))
+      (define (print! x level)
+        (let ((label (assq x labels)))
+          (cond ((exhausted?) #f)
+                ((not (%bot-compound? x)) (emit! (%bot->string x #t)))
+                (label (emit! (string-append "#" (number->string (cdr label)) "#")))
+                ((too-deep? level) (elide! "..."))
+                (else
+                 (if (memq x labelled)
+                     (begin
+                       (set! labels (cons (cons x next-label) labels))
+                       (emit! (string-append "#" (number->string next-label) "="))
+                       (set! next-label (+ next-label 1))))
+                 (if (pair? x)
+                     (begin
+                       (emit! "(")
+                       (print! (car x) (+ level 1))
+                       (print-rest! (cdr x) level 1)
+                       (emit! ")"))
+                     (begin
+                       (emit! "#(")
+                       (print-vector! x 0 (+ level 1))
+                       (emit! ")")))))))
+      (define (print-rest! rest level index)
+        (cond ((or (null? rest) (exhausted?)) #f)
+              ((and (pair? rest) (not (memq rest labelled)))
+               (emit! " ")
+               (if (too-many? index)
+                   (elide! "...")
+                   (begin
+                     (print! (car rest) (+ level 1))
+                     (print-rest! (cdr rest) level (+ index 1)))))
+              (else
+               (emit! " . ")
+               (print! rest level))))
+      (define (print-vector! v index level)
+        (cond ((or (>= index (vector-length v)) (exhausted?)) #f)
+              ((too-many? index) (elide! " ..."))
+              (else
+               (if (> index 0) (emit! " "))
+               (print! (vector-ref v index) level)
+               (print-vector! v (+ index 1) level))))
+      (walk! x 0 '())
+      (print! x 0)
+      (let ((printed (apply string-append (reverse pieces))))
+        (if (exhausted?)
+            (begin
+              (set! %bot-elided #t)
+              (string-append printed "..."))
+            printed)))))
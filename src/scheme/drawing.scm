@@ -0,0 +1,53 @@
+; A small drawing library. Its procedures build plain lists describing an image, which the bot
+; recognizes in results and renders to a PNG attached to the reply:
+;
+;   (image 200 100 (rect 10 10 50 30 "red") (circle 120 50 25 "#3366ff"))
+;
+; Coordinates are in pixels from the top left corner. Colors are names like "red" or
+; "#rrggbb" strings, and default to black. (plot f from to) graphs a function.
+;
+; This file is a single (begin ...) form so that it can be read in one go.
+
+(begin
+  (define (%drawing-color color)
+    (if (null? color) "black" (car color)))
+
+  (define (image width height . shapes)
+    (append (list '%image width height) shapes))
+
+  (define (line x1 y1 x2 y2 . color)
+    (list 'line x1 y1 x2 y2 (%drawing-color color)))
+
+  (define (rect x y width height . color)
+    (list 'rect x y width height (%drawing-color color)))
+
+  (define (circle x y radius . color)
+    (list 'circle x y radius (%drawing-color color)))
+
+  ; points is a list of (x y) lists.
+  (define (polyline points . color)
+    (list 'polyline (apply append points) (%drawing-color color)))
+
+  ; A 400x300 image of f sampled over [from, to], scaled to fill the image.
+  (define (plot f from to . color)
+    (let* ((width 400)
+           (height 300)
+           (samples 200)
+           (xs (let loop ((i samples) (acc '()))
+                 (if (< i 0)
+                     acc
+                     (loop (- i 1)
+                           (cons (exact->inexact (+ from (* (- to from) (/ i samples))))
+                                 acc)))))
+           (ys (map (lambda (x) (exact->inexact (f x))) xs))
+           (low (apply min ys))
+           (high (apply max ys))
+           (span (if (= high low) 1 (- high low))))
+      (let loop ((xs xs) (ys ys) (points '()))
+        (if (null? xs)
+            (image width height (polyline (reverse points) (%drawing-color color)))
+            (loop (cdr xs)
+                  (cdr ys)
+                  (cons (list (* width (/ (- (car xs) from) (- to from)))
+                              (- height (* height (/ (- (car ys) low) span))))
+                        points)))))))
@@ -0,0 +1,54 @@
+; Assertions for `¡test`. (assert expr) checks that expr is true, and (check-equal? actual
+; expected) that actual is equal? to expected. Failures are recorded rather than raised, so
+; a program keeps running after one and gets a report of every check; the bot resets the
+; counts before each evaluation and reads them with (%bot-take-test-report) afterwards.
+;
+; This file is a single (begin ...) form so that it can be read in one go.
+
+(begin
+  (define %bot-test-pass! #f)
+  (define %bot-test-fail! #f)
+  (define %bot-reset-tests! #f)
+  (define %bot-take-test-report #f)
+
+  (let ((passed 0)
+        (failures '()))
+    (set! %bot-test-pass!
+          (lambda ()
+            (set! passed (+ passed 1))))
+    (set! %bot-test-fail!
+          (lambda (description)
+            (set! failures (cons description failures))))
+    (set! %bot-reset-tests!
+          (lambda ()
+            (set! passed 0)
+            (set! failures '())))
+    ; The number of passed checks on the first line, then a line per failure.
+    (set! %bot-take-test-report
+          (lambda ()
+            (apply string-append
+                   (number->string passed)
+                   (map (lambda (failure) (string-append "\n" failure))
+                        (reverse failures))))))
+
+  (define (%bot-assert expression value)
+    (if value
+        (%bot-test-pass!)
+        (%bot-test-fail! (%bot->string expression #t)))
+    value)
+
+  (define (%bot-check-equal expression actual expected)
+    (if (equal? actual expected)
+        (%bot-test-pass!)
+        (%bot-test-fail! (string-append (%bot->string expression #t)
+                                        ": expected " (%bot->string expected #t)
+                                        ", got " (%bot->string actual #t))))
+    (equal? actual expected))
+
+  (define-syntax assert
+    (syntax-rules ()
+      ((_ expression) (%bot-assert 'expression expression))))
+
+  (define-syntax check-equal?
+    (syntax-rules ()
+      ((_ actual expected) (%bot-check-equal 'actual actual expected)))))
@@ -0,0 +1,90 @@
+//! Usage statistics, for `¡stats`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serenity::model::id::{GuildId, UserId};
+
+use crate::interpreter::Evaluation;
+
+#[derive(Default)]
+pub struct Counters {
+    pub evaluations: u64,
+    pub errors: u64,
+    total_time: Duration,
+    by_user: HashMap<UserId, u64>,
+}
+
+impl Counters {
+    fn record(&mut self, user: UserId, evaluation: &Evaluation) {
+        self.evaluations += 1;
+        if !evaluation.succeeded() {
+            self.errors += 1;
+        }
+        self.total_time += evaluation.elapsed;
+        *self.by_user.entry(user).or_insert(0) += 1;
+    }
+
+    /// Fraction of evaluations that failed, between 0 and 1.
+    pub fn error_rate(&self) -> f64 {
+        if self.evaluations == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.evaluations as f64
+        }
+    }
+
+    pub fn average_time(&self) -> Duration {
+        if self.evaluations == 0 {
+            Duration::default()
+        } else {
+            self.total_time / self.evaluations as u32
+        }
+    }
+
+    /// The `n` users with the most evaluations, busiest first.
+    pub fn busiest_users(&self, n: usize) -> Vec<(UserId, u64)> {
+        let mut users = self
+            .by_user
+            .iter()
+            .map(|(user, count)| (*user, *count))
+            .collect::<Vec<_>>();
+        users.sort_by(|a, b| b.1.cmp(&a.1));
+        users.truncate(n);
+        users
+    }
+}
+
+pub struct Stats {
+    started: Instant,
+    pub global: Counters,
+    guilds: HashMap<GuildId, Counters>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            global: Counters::default(),
+            guilds: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, guild: Option<GuildId>, user: UserId, evaluation: &Evaluation) {
+        self.global.record(user, evaluation);
+        if let Some(guild) = guild {
+            self.guilds
+                .entry(guild)
+                .or_insert_with(Counters::default)
+                .record(user, evaluation);
+        }
+    }
+
+    pub fn guild(&self, guild: GuildId) -> Option<&Counters> {
+        self.guilds.get(&guild)
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
@@ -0,0 +1,49 @@
+//! What the running bot was built from, for `¡version` and the presence.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The git commit the bot was built from, marked `-dirty` if it had uncommitted changes.
+pub const COMMIT: &str = env!("BUILD_COMMIT");
+/// When the bot was built, in seconds since the Unix epoch.
+const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+/// peroxide's manifest, for its version.
+const PEROXIDE_MANIFEST: &str = include_str!("../../peroxide/Cargo.toml");
+/// Asks the interpreter for the R7RS feature identifiers it supports.
+pub const FEATURES_PROGRAM: &str = "(features)";
+
+pub fn built() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(BUILD_TIMESTAMP.parse().unwrap_or(0))
+}
+
+pub fn peroxide_version() -> String {
+    PEROXIDE_MANIFEST
+        .parse::<toml::Value>()
+        .ok()
+        .and_then(|manifest| {
+            Some(
+                manifest
+                    .get("package")?
+                    .get("version")?
+                    .as_str()?
+                    .to_string(),
+            )
+        })
+        .unwrap_or_else(|| "(unknown version)".into())
+}
+
+/// The feature identifiers in the printed result of `FEATURES_PROGRAM`, and the numbers of
+/// the SRFIs among them.
+pub fn parse_features(printed: &str) -> (Vec<&str>, Vec<&str>) {
+    let features = printed
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let srfis = features
+        .iter()
+        .copied()
+        .filter_map(|feature| feature.strip_prefix("srfi-"))
+        .collect();
+    (features, srfis)
+}
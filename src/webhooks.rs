@@ -0,0 +1,80 @@
+//! Posting results through a channel webhook, under the name and avatar of whoever asked for
+//! them, so that a busy channel shows each result next to the person who ran it.
+
+use std::collections::HashMap;
+
+use repl_bot::send;
+use serde_json::Value;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::model::user::User;
+use serenity::model::webhook::Webhook;
+use serenity::Error;
+use tracing::info;
+
+/// Name of the webhooks the bot creates, and looks for before creating one.
+const WEBHOOK_NAME: &str = "peroxide";
+/// Added to the names results are posted under, so they aren't taken for the user's own
+/// messages.
+const NAME_SUFFIX: &str = " via peroxide";
+
+/// The webhook of each channel results were posted to, or `None` where the bot can't manage
+/// webhooks.
+#[derive(Default)]
+pub struct Webhooks {
+    channels: HashMap<ChannelId, Option<Webhook>>,
+}
+
+impl Webhooks {
+    /// The webhook of `channel`, found or created the first time it is asked for.
+    pub fn get(&mut self, http: &Http, channel: ChannelId) -> Option<Webhook> {
+        self.channels
+            .entry(channel)
+            .or_insert_with(|| find_or_create(http, channel))
+            .clone()
+    }
+
+    /// Forgets the webhook of `channel`, for it to be looked for again next time, as it may
+    /// have been deleted.
+    pub fn forget(&mut self, channel: ChannelId) {
+        self.channels.remove(&channel);
+    }
+}
+
+/// The bot's webhook in `channel`, created if there is none yet.
+fn find_or_create(http: &Http, channel: ChannelId) -> Option<Webhook> {
+    let existing = send::with_retries(|| channel.webhooks(http)).map(|webhooks| {
+        webhooks
+            .into_iter()
+            .find(|webhook| webhook.name.as_deref() == Some(WEBHOOK_NAME))
+    });
+    let created = match existing {
+        Ok(Some(webhook)) => return Some(webhook),
+        Ok(None) => send::with_retries(|| channel.create_webhook(http, WEBHOOK_NAME)),
+        Err(why) => Err(why),
+    };
+    created
+        .map_err(|why| {
+            info!(
+                channel = channel.0,
+                "Posting results in this channel normally, as there is no webhook: {:?}", why
+            );
+        })
+        .ok()
+}
+
+/// Posts `embed` through `webhook`, under the name and avatar of `user`.
+pub fn post(http: &Http, webhook: &Webhook, user: &User, embed: &Value) -> Result<Message, Error> {
+    let name = format!("{}{}", user.name, NAME_SUFFIX);
+    let avatar = user.face();
+    let posted = send::with_retries(|| {
+        webhook.execute(http, true, |w| {
+            w.username(&name)
+                .avatar_url(&avatar)
+                .embeds(vec![embed.clone()])
+        })
+    })?;
+    // Waiting for the message makes Discord send it back.
+    posted.ok_or(Error::Other("the webhook message wasn't returned"))
+}
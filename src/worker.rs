@@ -0,0 +1,902 @@
+//! The interpreter thread: owns every interpreter and runs jobs sent by event handlers.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use repl_bot::dispatch::{self, reply};
+use repl_bot::format::Printing;
+use repl_bot::limits::{self, Limits, Preemption};
+use repl_bot::session::SessionPool;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use tracing::error;
+
+use crate::challenge::{Judgement, TestCase};
+use crate::config::Config;
+use crate::forms::{self, split_forms, Form};
+use crate::interpreter::{self, Evaluation, InterruptingInterpreter, Request, StartupSources};
+
+/// Most results kept for identical evaluations to reuse.
+const MAX_CACHED_RESULTS: usize = 64;
+
+/// Which environment an evaluation runs in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SessionKey {
+    /// The environment shared by a guild's channels.
+    Guild(GuildId),
+    /// A private environment for a user's DMs with the bot.
+    Direct(UserId),
+    /// The environment of a pair session in a guild, by session ID, shared by its two
+    /// users.
+    Pair(GuildId, u64),
+    /// The environment for admins, which keeps the dangerous primitives the others lack.
+    Admin,
+}
+
+pub enum Job {
+    Evaluate {
+        session: SessionKey,
+        request: Request,
+        response: SyncSender<Evaluation>,
+    },
+    /// Runs a challenge submission in a fresh interpreter, then each test against it.
+    Judge {
+        submission: String,
+        tests: Vec<TestCase>,
+        limits: Limits,
+        response: SyncSender<Judgement>,
+    },
+    /// Runs a program in a guild's environment and, if it succeeds, adds it to the
+    /// committed environment whole.
+    Install {
+        guild: GuildId,
+        request: Request,
+        response: SyncSender<Evaluation>,
+    },
+    /// Adds the definitions of the user's last evaluation in a guild's environment to it,
+    /// and replies with what that changed.
+    Commit {
+        guild: GuildId,
+        user: UserId,
+        response: SyncSender<Result<Committed, String>>,
+    },
+    /// Replies with the source of the last definition of a name in a session, if the name
+    /// was defined by an evaluation.
+    SourceOf {
+        session: SessionKey,
+        name: String,
+        response: SyncSender<Result<Option<String>, String>>,
+    },
+    /// Replies with the names defined by evaluations in a session, sorted.
+    Bindings {
+        session: SessionKey,
+        response: SyncSender<Result<Vec<String>, String>>,
+    },
+    /// Replies with the source of the last definition of each name defined by evaluations in
+    /// a session, as one program.
+    Export {
+        session: SessionKey,
+        response: SyncSender<Result<String, String>>,
+    },
+    /// Replaces a session's interpreter with a freshly initialized one. If initialization
+    /// fails, the session keeps its current interpreter.
+    Reset {
+        session: SessionKey,
+        response: SyncSender<Result<(), String>>,
+    },
+    /// Turns on `features` in a guild's environment instead of the configured ones, and
+    /// replaces it with a fresh one as `Reset` does. If that fails, the guild keeps its
+    /// environment and features.
+    SetFeatures {
+        guild: GuildId,
+        features: Vec<String>,
+        response: SyncSender<Result<(), String>>,
+    },
+    /// Replies as soon as the worker gets to it, to show it isn't stuck. Whoever sent it may
+    /// have stopped waiting.
+    Ping { response: SyncSender<()> },
+}
+
+impl dispatch::Job for Job {
+    fn ping(response: SyncSender<()>) -> Self {
+        Job::Ping { response }
+    }
+}
+
+/// What committing a user's last evaluation did.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Committed {
+    /// Definitions committed.
+    pub count: usize,
+    /// Names someone else committed after the evaluation ran, whose definitions the commit
+    /// replaced.
+    pub overwritten: Vec<String>,
+}
+
+/// The environment shared by a guild's channels. Evaluations run over it rather than in it,
+/// as if in an environment of their own whose parent is the committed one (see
+/// `InterruptingInterpreter::run_layered`), so whatever they bind is thrown away unless
+/// their definitions are committed, which runs them in the committed environment itself.
+///
+/// Evaluations that can't run over it run in it, and it is then built again from the
+/// committed programs before the next one, as it is when the guild comes back after going
+/// idle. Guilds may only commit so many programs, so that this doesn't take ever longer.
+///
+/// Definitions are committed whole, one program at a time. When two users evaluated against
+/// the same committed environment and both commit, the last one wins, and learns which of
+/// the other's definitions it replaced.
+struct GuildSession {
+    /// Built from the committed programs on first use, and dropped when the guild goes
+    /// idle.
+    interpreter: Option<InterruptingInterpreter>,
+    /// Whether `interpreter` may hold changes that aren't in the committed environment, so
+    /// that it must be built again.
+    dirty: bool,
+    last_used: Instant,
+    /// Definitions of committed evaluations, replayed in order on a fresh interpreter to
+    /// build the committed environment again.
+    committed: Vec<Request>,
+    /// Syntax committed programs defined whose uses may bind names, so that evaluations
+    /// using it can't run over the environment.
+    binding_syntax: HashSet<String>,
+    /// Committed programs, by index, that failed when replayed and were reported.
+    reported_failures: HashSet<usize>,
+    /// Failures of committed programs not reported yet, for the next evaluation to show.
+    replay_errors: Vec<String>,
+    /// Definitions of each user's last evaluation, ready to be committed.
+    uncommitted: HashMap<UserId, Uncommitted>,
+    /// Programs committed so far.
+    generation: u64,
+    /// The `generation` each name was last committed at.
+    committed_at: HashMap<String, u64>,
+    /// Recent results, by program, for identical evaluations to reuse. Cleared whenever the
+    /// committed environment changes.
+    cache: HashMap<String, CachedResult>,
+}
+
+/// The last definition of each name evaluations defined in a session.
+#[derive(Default)]
+struct Definitions {
+    sources: HashMap<String, String>,
+    /// The names, in the order they were last defined in.
+    order: Vec<String>,
+}
+
+impl Definitions {
+    fn insert(&mut self, name: &str, source: &str) {
+        if self
+            .sources
+            .insert(name.to_string(), source.to_string())
+            .is_some()
+        {
+            self.order.retain(|defined| defined != name);
+        }
+        self.order.push(name.to_string());
+    }
+
+    /// Every definition, in the order they were made in, so that those using earlier ones
+    /// still work when run again.
+    fn program(&self) -> String {
+        self.order
+            .iter()
+            .map(|name| self.sources[name].as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+struct Uncommitted {
+    program: Request,
+    /// The `generation` of the committed environment the program ran against.
+    snapshot: u64,
+}
+
+struct CachedResult {
+    evaluated_at: Instant,
+    limits: Limits,
+    privileged: bool,
+    printing: Printing,
+    evaluation: Evaluation,
+}
+
+impl GuildSession {
+    fn new(interpreter: Option<InterruptingInterpreter>) -> Self {
+        Self {
+            interpreter,
+            dirty: false,
+            last_used: Instant::now(),
+            committed: Vec::new(),
+            binding_syntax: HashSet::new(),
+            reported_failures: HashSet::new(),
+            replay_errors: Vec::new(),
+            uncommitted: HashMap::new(),
+            generation: 0,
+            committed_at: HashMap::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Adds `program`, which already ran in the committed environment, to it.
+    fn commit(&mut self, program: Request) {
+        self.generation += 1;
+        for name in defined_names(&program.command) {
+            self.committed_at.insert(name, self.generation);
+        }
+        if let Ok(bindings) = forms::bindings(&program.command) {
+            let syntax = bindings.binding_syntax.iter().map(|name| name.to_string());
+            self.binding_syntax.extend(syntax);
+        }
+        self.committed.push(program);
+        self.cache.clear();
+    }
+
+    /// Runs `program` in the committed environment, then adds it to it.
+    fn run_and_commit(&mut self, program: Request) {
+        if let (Some(interpreter), false) = (&mut self.interpreter, self.dirty) {
+            // The environment then holds part of the program, and must be built again.
+            self.dirty = interpreter.run_string(&program).error.is_some();
+        }
+        self.commit(program);
+    }
+
+    /// A result for `request` evaluated less than `window` ago, if there is one.
+    fn cached(&self, request: &Request, window: Duration) -> Option<Evaluation> {
+        let cached = self.cache.get(&request.command)?;
+        if cached.evaluated_at.elapsed() >= window
+            || cached.limits != request.limits
+            || cached.privileged != request.privileged
+            || cached.printing != request.printing
+        {
+            return None;
+        }
+        Some(Evaluation {
+            cached: true,
+            ..cached.evaluation.clone()
+        })
+    }
+
+    /// Remembers the result of `request` for identical evaluations, unless it depends on who
+    /// asked or on their input, or changed the committed environment.
+    fn remember(&mut self, request: &Request, evaluation: &Evaluation, window: Duration) {
+        let asks_author = forms::atoms(&request.command).map_or(true, |atoms| {
+            atoms
+                .iter()
+                .any(|atom| atom.trim_matches('|') == "message-author")
+        });
+        let personal = !request.inputs.is_empty()
+            || asks_author
+            || !evaluation.actions.is_empty()
+            || evaluation.needs_input;
+        if window == Duration::from_secs(0) || personal || !evaluation.defined_commands.is_empty() {
+            return;
+        }
+        self.cache.retain(|_, c| c.evaluated_at.elapsed() < window);
+        if self.cache.len() < MAX_CACHED_RESULTS {
+            self.cache.insert(
+                request.command.clone(),
+                CachedResult {
+                    evaluated_at: Instant::now(),
+                    limits: request.limits,
+                    privileged: request.privileged,
+                    printing: request.printing,
+                    evaluation: evaluation.clone(),
+                },
+            );
+        }
+    }
+}
+
+/// Every interpreter, by session.
+pub struct Sessions {
+    config: Arc<Config>,
+    /// Startup files to use instead of reading them each time an interpreter is created.
+    preloaded: Option<StartupSources>,
+    guilds: HashMap<GuildId, GuildSession>,
+    direct: SessionPool<InterruptingInterpreter>,
+    pairs: SessionPool<InterruptingInterpreter, u64>,
+    /// Created on first use.
+    admin: Option<InterruptingInterpreter>,
+    /// The last definition of each name in each session. For guild environments, only
+    /// committed definitions count.
+    sources: HashMap<SessionKey, Definitions>,
+    /// The features of guilds whose admins chose them. They outlive the guild's sessions.
+    features: HashMap<GuildId, Vec<String>>,
+}
+
+impl Sessions {
+    pub fn new(config: Arc<Config>, preloaded: Option<StartupSources>) -> Result<Self, String> {
+        // Guild environments are created on first use, so check the startup files and
+        // libraries now.
+        let every_feature = config
+            .feature_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        create_interpreter(&config, preloaded.as_ref(), true, &every_feature)?;
+        let direct = SessionPool::new(config.dm.max_sessions, config.dm.session_idle);
+        let pairs = SessionPool::new(config.pairs.max_sessions, config.pairs.idle);
+        Ok(Self {
+            config,
+            preloaded,
+            guilds: HashMap::new(),
+            direct,
+            pairs,
+            admin: None,
+            sources: HashMap::new(),
+            features: HashMap::new(),
+        })
+    }
+
+    /// A restricted interpreter, as used by every session but the admins'.
+    fn new_interpreter(&self) -> Result<InterruptingInterpreter, String> {
+        create_interpreter(&self.config, self.preloaded.as_ref(), true, &[])
+    }
+
+    pub fn evaluate(&mut self, session: SessionKey, request: &Request) -> Evaluation {
+        if let SessionKey::Guild(guild) = session {
+            // Bot commands are called in the guild's environment, so they must be defined in
+            // it, and are committed.
+            let in_environment = request.privileged && may_define_commands(&request.command);
+            if let (true, Err(e)) = (in_environment, self.room_to_commit(guild)) {
+                return Evaluation::failed(e);
+            }
+            return self.evaluate_shared(guild, request, in_environment);
+        }
+        let evaluation = match self.get(session) {
+            Ok(interpreter) => interpreter.run_string(request),
+            Err(e) => return Evaluation::failed(format!("error creating session: {}", e)),
+        };
+        if !evaluation.needs_input {
+            self.record_sources(session, &request.command, evaluation.values.len());
+        }
+        evaluation
+    }
+
+    /// Remembers the source of the definitions among the first `completed` forms of
+    /// `command`.
+    fn record_sources(&mut self, session: SessionKey, command: &str, completed: usize) {
+        let forms = match split_forms(command) {
+            Ok(forms) => forms,
+            Err(_) => return,
+        };
+        let sources = self.sources.entry(session).or_default();
+        for form in forms.iter().take(completed) {
+            if let Some(name) = form.defined_name() {
+                sources.insert(name, form.source);
+            }
+        }
+    }
+
+    /// The names defined by evaluations in `session`, sorted.
+    pub fn bindings(&self, session: SessionKey) -> Vec<String> {
+        let mut names = self
+            .sources
+            .get(&session)
+            .map(|definitions| definitions.order.clone())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    /// The source of the last definition of `name` in `session`.
+    pub fn source_of(&self, session: SessionKey, name: &str) -> Option<String> {
+        self.sources.get(&session)?.sources.get(name).cloned()
+    }
+
+    /// The last definition of each name in `session`, as a program that makes them again.
+    pub fn export(&self, session: SessionKey) -> String {
+        self.sources
+            .get(&session)
+            .map(Definitions::program)
+            .unwrap_or_default()
+    }
+
+    /// Runs `request` over the environment of `guild`, or in it if `in_environment` or if it
+    /// can't run over it, after building it again if needed. Committed programs that failed
+    /// to replay while building it are reported with the result, once each.
+    fn evaluate_shared(
+        &mut self,
+        guild: GuildId,
+        request: &Request,
+        in_environment: bool,
+    ) -> Evaluation {
+        let cache_window = self.config.result_cache;
+        let cached = self
+            .guilds
+            .get(&guild)
+            .filter(|_| !in_environment)
+            .and_then(|s| s.cached(request, cache_window));
+        // A reused result still counts as the user's last evaluation.
+        let (evaluation, ran_in_environment) = match cached {
+            Some(evaluation) => (evaluation, false),
+            None => match self.run_shared(guild, request, in_environment) {
+                Ok(ran) => ran,
+                Err(e) => return Evaluation::failed(format!("error creating session: {}", e)),
+            },
+        };
+        let (definitions, whole) = match split_forms(&request.command) {
+            Ok(forms) => (
+                forms
+                    .iter()
+                    .take(evaluation.values.len())
+                    .filter(|form| form.is_definition())
+                    .map(|form| form.source)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                evaluation.succeeded() && forms.iter().all(Form::is_definition),
+            ),
+            Err(_) => return evaluation,
+        };
+        let definitions = Request {
+            command: definitions,
+            invocation: None,
+            ..request.clone()
+        };
+        if !evaluation.defined_commands.is_empty() {
+            // Bot commands are called in the guild's environment, so they must stay in it.
+            self.record_sources(SessionKey::Guild(guild), &definitions.command, usize::MAX);
+            let session = self.guilds.get_mut(&guild).unwrap();
+            if !ran_in_environment {
+                session.run_and_commit(definitions);
+            } else {
+                session.commit(definitions);
+                // The definitions were all it ran.
+                session.dirty &= !whole;
+            }
+        } else if let Some(invocation) = &request.invocation {
+            let session = self.guilds.get_mut(&guild).unwrap();
+            if definitions.command.is_empty() {
+                session.uncommitted.remove(&invocation.author_id);
+            } else {
+                let uncommitted = Uncommitted {
+                    program: definitions,
+                    snapshot: session.generation,
+                };
+                session
+                    .uncommitted
+                    .insert(invocation.author_id, uncommitted);
+            }
+        }
+        evaluation
+    }
+
+    /// Runs `request` for `evaluate_shared`, and tells whether it ran in the environment.
+    fn run_shared(
+        &mut self,
+        guild: GuildId,
+        request: &Request,
+        in_environment: bool,
+    ) -> Result<(Evaluation, bool), String> {
+        let cache_window = self.config.result_cache;
+        let session = self.guild_session(guild)?;
+        let layered = match (in_environment, session.interpreter.as_mut()) {
+            (false, Some(interpreter)) => interpreter.run_layered(request, &session.binding_syntax),
+            _ => None,
+        };
+        let ran_in_environment = layered.is_none();
+        let mut evaluation = match layered {
+            Some(evaluation) => evaluation,
+            None => {
+                // What it changed stays until the environment is built again, unless it is
+                // committed whole.
+                session.dirty = true;
+                session.interpreter.as_mut().unwrap().run_string(request)
+            }
+        };
+        session.remember(request, &evaluation, cache_window);
+        evaluation.replay_errors = mem::take(&mut session.replay_errors);
+        Ok((evaluation, ran_in_environment))
+    }
+
+    /// The session of `guild`, with its interpreter created or built again from the
+    /// committed programs if needed. Guilds that went idle lose their interpreter first.
+    fn guild_session(&mut self, guild: GuildId) -> Result<&mut GuildSession, String> {
+        let idle = self.config.guild_session_idle;
+        self.guilds.retain(|id, session| {
+            if *id == guild || session.last_used.elapsed() < idle {
+                return true;
+            }
+            // Committed programs are kept to replay when the guild comes back.
+            session.interpreter = None;
+            session.cache.clear();
+            !session.committed.is_empty() || !session.uncommitted.is_empty()
+        });
+        let (config, preloaded) = (&self.config, self.preloaded.as_ref());
+        let features = guild_features(config, &self.features, guild);
+        let session = self
+            .guilds
+            .entry(guild)
+            .or_insert_with(|| GuildSession::new(None));
+        session.last_used = Instant::now();
+        if session.interpreter.is_none() || session.dirty {
+            let (interpreter, failures) =
+                replay_committed(config, preloaded, features, &session.committed)?;
+            for (index, failure) in failures {
+                if session.reported_failures.insert(index) {
+                    session.replay_errors.push(failure);
+                }
+            }
+            session.interpreter = Some(interpreter);
+            session.dirty = false;
+        }
+        Ok(session)
+    }
+
+    /// Fails if `guild` has committed as many programs as it may.
+    fn room_to_commit(&self, guild: GuildId) -> Result<(), String> {
+        let max = self.config.max_committed_programs;
+        let committed = self.guilds.get(&guild).map_or(0, |s| s.committed.len());
+        if committed < max {
+            return Ok(());
+        }
+        Err(format!(
+            "this guild's environment already has the most committed programs it may have \
+             ({}); commit fewer, larger programs after resetting it",
+            max
+        ))
+    }
+
+    /// Runs `program` in the environment of `guild` and commits it whole if it succeeds, as
+    /// is done for guild preludes.
+    pub fn install(&mut self, guild: GuildId, program: &Request) -> Evaluation {
+        if let Err(e) = self.room_to_commit(guild) {
+            return Evaluation::failed(e);
+        }
+        let evaluation = self.evaluate_shared(guild, program, true);
+        if evaluation.error.is_none() {
+            self.record_sources(SessionKey::Guild(guild), &program.command, usize::MAX);
+            let session = self.guilds.get_mut(&guild).unwrap();
+            session.commit(program.clone());
+            // It was all that ran.
+            session.dirty = false;
+        }
+        evaluation
+    }
+
+    /// Commits the definitions of `user`'s last evaluation in the environment of `guild`.
+    /// Definitions naming any of the bot's own names are refused, as they would be replayed
+    /// for everyone.
+    pub fn commit(&mut self, guild: GuildId, user: UserId) -> Result<Committed, String> {
+        self.room_to_commit(guild)?;
+        let session = match self.guilds.get_mut(&guild) {
+            Some(session) => session,
+            None => return Ok(Committed::default()),
+        };
+        let Uncommitted { program, snapshot } = match session.uncommitted.remove(&user) {
+            Some(uncommitted) => uncommitted,
+            None => return Ok(Committed::default()),
+        };
+        // Whatever form binds them, as with define-values or define-record-type.
+        let atoms = forms::atoms(&program.command).unwrap_or_default();
+        if let Some(name) = atoms
+            .iter()
+            .map(|atom| atom.trim_matches('|'))
+            .find(|name| interpreter::is_bot_name(name))
+        {
+            return Err(format!("`{}` is one of the bot's own names", name));
+        }
+        let names = defined_names(&program.command);
+        let mut overwritten = names
+            .into_iter()
+            .filter(|name| {
+                session
+                    .committed_at
+                    .get(name)
+                    .is_some_and(|&at| at > snapshot)
+            })
+            .collect::<Vec<_>>();
+        overwritten.sort();
+        overwritten.dedup();
+        let count = split_forms(&program.command).map_or(0, |forms| forms.len());
+        session.run_and_commit(program.clone());
+        self.record_sources(SessionKey::Guild(guild), &program.command, count);
+        Ok(Committed { count, overwritten })
+    }
+
+    /// The programs committed to the environment of `guild`, in order.
+    pub fn committed(&self, guild: GuildId) -> Vec<Request> {
+        self.guilds
+            .get(&guild)
+            .map(|session| session.committed.clone())
+            .unwrap_or_default()
+    }
+
+    /// Gives `guild` the features, if it chose them, and the committed programs it had in
+    /// other sessions, as when the sandbox is restarted. The programs are replayed when the
+    /// guild's environment is next used.
+    pub fn restore(
+        &mut self,
+        guild: GuildId,
+        features: Option<Vec<String>>,
+        committed: Vec<Request>,
+    ) {
+        if let Some(features) = features {
+            self.features.insert(guild, features);
+        }
+        self.sources.remove(&SessionKey::Guild(guild));
+        let mut session = GuildSession::new(None);
+        for program in committed {
+            self.record_sources(SessionKey::Guild(guild), &program.command, usize::MAX);
+            session.commit(program);
+        }
+        self.guilds.insert(guild, session);
+    }
+
+    /// Runs a challenge submission in a fresh interpreter, then each test against it.
+    pub fn judge(&self, submission: &str, tests: &[TestCase], limits: Limits) -> Judgement {
+        let mut interpreter = match self.new_interpreter() {
+            Ok(interpreter) => interpreter,
+            Err(e) => {
+                return Judgement {
+                    submission: Evaluation::failed(format!("error creating session: {}", e)),
+                    tests: Vec::new(),
+                }
+            }
+        };
+        // The expected values are worked out before the submission runs, so that it can
+        // change neither them nor how they are compared.
+        let expected = tests
+            .iter()
+            .enumerate()
+            .map(|(i, test)| {
+                interpreter.keep_expected(i, &Request::new(test.expected.clone(), limits))
+            })
+            .collect::<Vec<_>>();
+        let submission = interpreter.run_string(&Request::new(submission.to_string(), limits));
+        let tests = if submission.succeeded() {
+            tests
+                .iter()
+                .zip(expected)
+                .enumerate()
+                .map(|(i, (test, expected))| {
+                    if !expected.succeeded() {
+                        return expected;
+                    }
+                    interpreter.check_expected(i, &Request::new(test.expression.clone(), limits))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Judgement { submission, tests }
+    }
+
+    /// The interpreter for `key`, created if needed.
+    fn get(&mut self, key: SessionKey) -> Result<&mut InterruptingInterpreter, String> {
+        let user = match key {
+            SessionKey::Guild(guild) => {
+                let session = self.guild_session(guild)?;
+                return Ok(session.interpreter.as_mut().unwrap());
+            }
+            SessionKey::Admin => {
+                if self.admin.is_none() {
+                    self.admin = Some(create_interpreter(
+                        &self.config,
+                        self.preloaded.as_ref(),
+                        false,
+                        &[],
+                    )?);
+                }
+                return Ok(self.admin.as_mut().unwrap());
+            }
+            SessionKey::Pair(guild, pair) => {
+                self.pairs.evict(pair);
+                let pairs = &self.pairs;
+                self.sources.retain(|key, _| match key {
+                    SessionKey::Pair(_, id) => *id == pair || pairs.contains(*id),
+                    SessionKey::Guild(_) | SessionKey::Direct(_) | SessionKey::Admin => true,
+                });
+                // Pairs get the features of their guild.
+                let (config, preloaded) = (&self.config, self.preloaded.as_ref());
+                let features = guild_features(config, &self.features, guild);
+                return self.pairs.get_or_create(pair, || {
+                    create_interpreter(config, preloaded, true, features)
+                });
+            }
+            SessionKey::Direct(user) => user,
+        };
+        self.direct.evict(user);
+        let direct = &self.direct;
+        self.sources.retain(|key, _| match key {
+            SessionKey::Direct(owner) => *owner == user || direct.contains(*owner),
+            SessionKey::Guild(_) | SessionKey::Pair(..) | SessionKey::Admin => true,
+        });
+        let (config, preloaded) = (&self.config, self.preloaded.as_ref());
+        self.direct
+            .get_or_create(user, || create_interpreter(config, preloaded, true, &[]))
+    }
+
+    /// Replaces the interpreter of `key` with a fresh one, unless creating it fails.
+    pub fn reset(&mut self, key: SessionKey) -> Result<(), String> {
+        let restricted = key != SessionKey::Admin;
+        let features = match key {
+            SessionKey::Guild(guild) | SessionKey::Pair(guild, _) => {
+                guild_features(&self.config, &self.features, guild)
+            }
+            SessionKey::Direct(_) | SessionKey::Admin => &[],
+        };
+        let interpreter =
+            create_interpreter(&self.config, self.preloaded.as_ref(), restricted, features)?;
+        self.sources.remove(&key);
+        match key {
+            SessionKey::Guild(guild) => {
+                self.guilds
+                    .insert(guild, GuildSession::new(Some(interpreter)));
+            }
+            SessionKey::Admin => self.admin = Some(interpreter),
+            SessionKey::Direct(user) => self.direct.insert(user, interpreter),
+            SessionKey::Pair(_, pair) => self.pairs.insert(pair, interpreter),
+        }
+        Ok(())
+    }
+
+    /// Turns on `features` in the environment of `guild`, replacing it with a fresh one
+    /// unless creating it fails.
+    pub fn set_features(&mut self, guild: GuildId, features: Vec<String>) -> Result<(), String> {
+        let interpreter =
+            create_interpreter(&self.config, self.preloaded.as_ref(), true, &features)?;
+        self.features.insert(guild, features);
+        self.sources.remove(&SessionKey::Guild(guild));
+        self.guilds
+            .insert(guild, GuildSession::new(Some(interpreter)));
+        Ok(())
+    }
+}
+
+/// The features on in `guild`: those in `chosen`, else the configured ones.
+fn guild_features<'a>(
+    config: &'a Config,
+    chosen: &'a HashMap<GuildId, Vec<String>>,
+    guild: GuildId,
+) -> &'a [String] {
+    chosen
+        .get(&guild)
+        .map_or_else(|| config.features(guild), Vec::as_slice)
+}
+
+/// The names the definitions of `program` define.
+fn defined_names(program: &str) -> Vec<String> {
+    split_forms(program)
+        .map(|forms| {
+            forms
+                .iter()
+                .filter_map(|form| form.defined_name())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether `code` may define bot commands.
+fn may_define_commands(code: &str) -> bool {
+    forms::atoms(code).is_ok_and(|atoms| atoms.contains(&"define-command"))
+}
+
+fn create_interpreter(
+    config: &Config,
+    preloaded: Option<&StartupSources>,
+    restricted: bool,
+    features: &[String],
+) -> Result<InterruptingInterpreter, String> {
+    match preloaded {
+        Some(sources) => InterruptingInterpreter::from_sources(sources, restricted, features),
+        None => InterruptingInterpreter::new(&config.startup, restricted, features),
+    }
+}
+
+/// A fresh interpreter with `features` on and the committed programs run in it, with the
+/// failures of those that didn't run to completion, by index.
+fn replay_committed(
+    config: &Config,
+    preloaded: Option<&StartupSources>,
+    features: &[String],
+    committed: &[Request],
+) -> Result<(InterruptingInterpreter, Vec<(usize, String)>), String> {
+    let mut interpreter = create_interpreter(config, preloaded, true, features)?;
+    let mut failures = Vec::new();
+    for (index, program) in committed.iter().enumerate() {
+        if let Some(error) = interpreter.run_string(program).error {
+            error!("Error replaying committed definitions: {}", error.message);
+            let failure = match &error.form {
+                Some((_, form)) => format!("{}\n{}", form, error.message),
+                None => error.message,
+            };
+            failures.push((index, failure));
+        }
+    }
+    Ok((interpreter, failures))
+}
+
+/// Runs jobs from `jobs` until every sender is dropped. `queued` counts the jobs being run
+/// or waiting, for preempting long evaluations. If the interpreter can't start, the error is
+/// logged and the worker stops, which its callers see as the worker being down.
+pub fn run(jobs: Receiver<Job>, config: Arc<Config>, queued: Arc<AtomicUsize>) {
+    if config.preempt_after > Duration::from_secs(0) {
+        limits::set_preemption(Preemption {
+            after: config.preempt_after,
+            queued,
+        });
+    }
+    let mut sessions = match Sessions::new(config, None) {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            error!("Error initializing interpreter: {}", e);
+            return;
+        }
+    };
+    while let Ok(job) = jobs.recv() {
+        match job {
+            Job::Evaluate {
+                session,
+                request,
+                response,
+            } => {
+                reply(response, sessions.evaluate(session, &request), "evaluate");
+            }
+            Job::Judge {
+                submission,
+                tests,
+                limits,
+                response,
+            } => {
+                reply(
+                    response,
+                    sessions.judge(&submission, &tests, limits),
+                    "judge",
+                );
+            }
+            Job::Install {
+                guild,
+                request,
+                response,
+            } => {
+                reply(response, sessions.install(guild, &request), "install");
+            }
+            Job::Commit {
+                guild,
+                user,
+                response,
+            } => {
+                reply(response, sessions.commit(guild, user), "commit");
+            }
+            Job::SourceOf {
+                session,
+                name,
+                response,
+            } => {
+                reply(
+                    response,
+                    Ok(sessions.source_of(session, &name)),
+                    "source-of",
+                );
+            }
+            Job::Bindings { session, response } => {
+                reply(response, Ok(sessions.bindings(session)), "bindings");
+            }
+            Job::Export { session, response } => {
+                reply(response, Ok(sessions.export(session)), "export");
+            }
+            Job::Reset { session, response } => {
+                reply(response, sessions.reset(session), "reset");
+            }
+            Job::SetFeatures {
+                guild,
+                features,
+                response,
+            } => {
+                reply(
+                    response,
+                    sessions.set_features(guild, features),
+                    "set-features",
+                );
+            }
+            Job::Ping { response } => {
+                let _ = response.send(());
+            }
+        }
+    }
+}